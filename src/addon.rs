@@ -1,33 +1,1363 @@
-use std::{collections::HashMap, error::Error, fs::File, io::Read};
+use std::{
+	collections::HashMap,
+	env,
+	error::Error,
+	ffi::OsString,
+	fmt,
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+	thread,
+	time::Duration,
+};
+use rayon::prelude::*;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::checks;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct AddonSpecification {
 	pub required: Vec<String>,
+	/// At least one of these files must exist. All of them which do are loaded.
+	pub required_any: Option<Vec<String>>,
 	pub optional: Option<Vec<String>>,
+	/// DeHackEd patches (`.deh` or `.bex`), which are loaded with `-deh`
+	/// instead of `-file`. They must exist, like the required files.
+	pub deh: Option<Vec<String>>,
+	/// Arguments for GZDoom, like `+set` for cvars, added after the extra
+	/// arguments when the addon is loaded. They are used as they are, without
+	/// being split or unquoted like the extra arguments.
+	pub args: Option<Vec<String>>,
+	/// Environment variables for GZDoom, set when the addon is loaded. `$VAR`
+	/// is expanded in them, like in the extra arguments, which override them.
+	pub env: Option<HashMap<String, String>>,
+	/// Expected SHA-256 hashes of the addon's files, by file name. They are
+	/// only checked with `--verify-checksums`, since hashing is slow.
+	pub checksums: Option<HashMap<String, String>>,
 	pub secondary: Option<String>,
+	pub description: Option<String>,
+	pub tags: Option<Vec<String>>,
+	pub category: Option<String>,
+	/// Standalone primary addons can't be combined with secondary addons
+	pub standalone: Option<bool>,
+	/// Working directory for GZDoom, relative to the addon base directory
+	pub cwd: Option<String>,
+	/// Alternative sets of files, like high or low resolution textures, by
+	/// name. Only the files of the chosen variant are loaded.
+	pub variants: Option<HashMap<String, Vec<String>>>,
+	/// Ask which optional files to load when this addon is selected, instead
+	/// of loading all of them
+	pub prompt_optional: Option<bool>,
+	/// Maps which can be started directly, like `MAP01`
+	pub maps: Option<Vec<String>>,
+	/// Platforms the addon works on: `windows`, `linux` or `macos`. It works
+	/// everywhere if this is left out.
+	pub platforms: Option<Vec<String>>,
+	pub author: Option<String>,
+	/// Version of the addon, like `1.2`
+	pub version: Option<String>,
+	/// Names of other addons which must be loaded before this one
+	pub requires: Option<Vec<String>>,
+	/// Names of other addons which can't be loaded with this one
+	pub conflicts: Option<Vec<String>>,
+	/// Files which were missing when the addon list was loaded. An addon
+	/// with missing files is listed, but can't be loaded.
+	#[serde(skip)]
+	pub missing: Vec<String>,
+}
+
+/// Which part of an addon's entry matched a search query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchMatch {
+	Name,
+	Description,
+	Tag(String),
+}
+
+impl AddonSpecification {
+	/// The required files and patches which don't exist. If none of the
+	/// `required_any` files exist, all of them are listed.
+	pub fn find_missing(&self) -> Vec<String> {
+		let exists = |file: &String| File::open(file).is_ok();
+		let mut missing: Vec<String> = self.required.iter()
+			.chain(self.deh.iter().flatten())
+			.filter(|file| !exists(file))
+			.cloned()
+			.collect();
+		if let Some(any) = &self.required_any {
+			if !any.iter().any(exists) {
+				missing.extend(any.iter().cloned());
+			}
+		}
+		missing
+	}
+
+	/// Whether the addon works on the given platform, which is named like
+	/// `std::env::consts::OS`
+	pub fn supports_platform(&self, platform: &str) -> bool {
+		self.platforms.as_ref().map_or(true, |platforms| {
+			platforms.iter().any(|supported| supported.eq_ignore_ascii_case(platform))
+		})
+	}
+
+	/// Whether the addon works on this platform
+	pub fn supported_here(&self) -> bool {
+		self.supports_platform(env::consts::OS)
+	}
+
+	/// Why the addon can't be selected, if it doesn't work on this platform
+	pub fn platform_note(&self) -> Option<String> {
+		match (self.supported_here(), &self.platforms) {
+			(false, Some(platforms)) => Some(format!("Only works on {}", platforms.join(", "))),
+			_ => None,
+		}
+	}
+
+	/// Which files are missing, if any are
+	pub fn missing_note(&self) -> Option<String> {
+		match self.missing.len() {
+			0 => None,
+			_ => Some(format!("Missing files:\n{}", self.missing.join("\n"))),
+		}
+	}
+
+	/// Who made the addon, and which version it is, like
+	/// `Author: Antnee - v1.2`
+	pub fn credits(&self) -> Option<String> {
+		let author = self.author.as_deref().map(str::trim).filter(|author| !author.is_empty());
+		let version = self.version.as_deref().map(str::trim).filter(|version| !version.is_empty());
+		match (author, version) {
+			(Some(author), Some(version)) => Some(format!("Author: {} - v{}", author, version)),
+			(Some(author), None) => Some(format!("Author: {}", author)),
+			(None, Some(version)) => Some(format!("v{}", version)),
+			(None, None) => None,
+		}
+	}
+
+	/// Whether the user is asked which optional files to load
+	pub fn prompts_for_optional(&self) -> bool {
+		self.prompt_optional.unwrap_or(false) &&
+		self.optional.as_ref().map(|optional| !optional.is_empty()).unwrap_or(false)
+	}
+
+	/// Names of this addon's variants, sorted
+	pub fn variant_names(&self) -> Vec<&String> {
+		let mut names: Vec<&String> = self.variants.iter().flat_map(HashMap::keys).collect();
+		names.sort();
+		names
+	}
+
+	/// The variant to load: the chosen one if this addon has it, otherwise
+	/// the first one by name
+	pub fn variant(&self, chosen: Option<&str>) -> Option<&String> {
+		let names = self.variant_names();
+		chosen.and_then(|chosen| names.iter().find(|name| name.as_str() == chosen).copied())
+			.or_else(|| names.first().copied())
+	}
+
+	/// Files of the variant to load
+	pub fn variant_files(&self, chosen: Option<&str>) -> &[String] {
+		self.variant(chosen)
+			.and_then(|name| self.variants.as_ref()?.get(name))
+			.map(Vec::as_slice)
+			.unwrap_or_default()
+	}
+
+	/// Whether `key` is this addon's name or one of its tags
+	pub fn has_name_or_tag(&self, name: &str, key: &str) -> bool {
+		name == key || self.tags.iter().flatten().any(|tag| tag == key)
+	}
+
+	/// Case-insensitive search of an addon by name, and optionally by its
+	/// description and tags. `query` must already be lowercase.
+	pub fn search(&self, name: &str, query: &str, in_descriptions: bool) -> Option<SearchMatch> {
+		if query.is_empty() || name.to_lowercase().contains(query) {
+			return Some(SearchMatch::Name);
+		}
+		if !in_descriptions {
+			return None;
+		}
+		if self.description.as_ref()
+			.map(|d| d.to_lowercase().contains(query))
+			.unwrap_or(false) {
+			return Some(SearchMatch::Description);
+		}
+		self.tags.iter().flatten()
+			.find(|tag| tag.to_lowercase().contains(query))
+			.map(|tag| SearchMatch::Tag(tag.clone()))
+	}
 }
 
 pub type AddonMap = HashMap<String, AddonSpecification>;
 
-pub fn get_addons(fname: Option<&str>) -> Result<AddonMap, Box<dyn Error>> {
-	let contents = {
-		let mut file = File::open(fname.unwrap_or("addons.yml"))?;
-		let mut s = String::new();
-		file.read_to_string(&mut s)?;
-		s
+/// Secondary addons to select when a primary addon is selected. Each key is a
+/// primary addon name or tag, and each value is a list of secondary addon
+/// names or tags.
+pub type SelectionRules = HashMap<String, Vec<String>>;
+
+/// How optional files which exist are loaded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptionalFiles {
+	/// Always loaded
+	Auto,
+	/// Only loaded if they are picked
+	OptIn,
+}
+
+/// Settings from the addon list, other than the addons
+#[derive(Debug, Clone, Default)]
+pub struct ListSettings {
+	pub rules: SelectionRules,
+	/// Overrides the user's setting for optional files
+	pub optional_files: Option<OptionalFiles>,
+	/// Where the latest version of the addon list can be downloaded from
+	pub manifest_url: Option<String>,
+	/// Where to get the IWAD, if it can't be found
+	pub iwad_help: Option<IwadHelp>,
+	/// Addons which are loaded with every launch, and can't be deselected.
+	/// They aren't in the list of addons.
+	pub always_load: AddonMap,
+	/// Addons which should always be loaded, but whose files are missing
+	pub missing_always_load: Vec<String>,
+}
+
+/// Guidance on getting the IWAD, shown when it can't be found
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IwadHelp {
+	pub hint: Option<String>,
+	#[serde(default)]
+	pub links: Vec<IwadLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IwadLink {
+	pub label: String,
+	pub url: String,
+}
+
+/// Everything read from the addon list
+#[derive(Debug, Clone, Default)]
+pub struct AddonList {
+	pub addons: AddonMap,
+	pub settings: ListSettings,
+	/// Addons with missing files, and which files are missing, by name
+	pub incomplete: Vec<(String, Vec<String>)>,
+	/// Problems with the addon list which don't stop it from being used,
+	/// like bad file patterns
+	pub warnings: Vec<String>,
+}
+
+/// Names of the secondary addons which the rules select for a primary addon
+pub fn rule_selections<'a>(
+	rules: &SelectionRules,
+	addons: &'a AddonMap,
+	primary: &str,
+) -> Vec<&'a String> {
+	let primary_spec = match addons.get(primary) {
+		Some(spec) => spec,
+		None => return vec![],
 	};
+	let targets: Vec<&String> = rules.iter()
+		.filter(|(trigger, _)| primary_spec.has_name_or_tag(primary, trigger))
+		.flat_map(|(_, targets)| targets)
+		.collect();
+	let mut selected: Vec<&String> = addons.iter()
+		.filter(|(_, spec)| spec.secondary.is_some())
+		.filter(|(name, spec)| targets.iter().any(|target| spec.has_name_or_tag(name, target)))
+		.map(|(name, _)| name)
+		.collect();
+	selected.sort();
+	selected
+}
+
+/// Put the given addons in load order, with the addons which each one
+/// requires, directly or through other addons, before it. Also returns
+/// messages about required addons which aren't available, or whose files are
+/// missing. Those are left out of the order.
+pub fn with_requirements<'a>(
+	addons: &'a AddonMap,
+	names: &[&'a String],
+) -> (Vec<&'a String>, Vec<String>) {
+	fn visit<'a>(
+		addons: &'a AddonMap,
+		name: &'a String,
+		visiting: &mut Vec<&'a String>,
+		order: &mut Vec<&'a String>,
+		missing: &mut Vec<String>,
+	) {
+		// Addons which require each other are loaded in the order they are
+		// first reached
+		if order.contains(&name) || visiting.contains(&name) {
+			return;
+		}
+		visiting.push(name);
+		let required = addons.get(name).and_then(|spec| spec.requires.as_ref());
+		required.into_iter().flatten().for_each(|required| match addons.get_key_value(required) {
+			Some((required, spec)) if spec.missing.is_empty() => visit(addons, required, visiting, order, missing),
+			Some((required, spec)) => missing.push(format!(
+				"{} requires {}, which is missing these files: {}",
+				name,
+				required,
+				spec.missing.join(", ")
+			)),
+			None => missing.push(format!("{} requires {}, which isn't available.", name, required)),
+		});
+		visiting.pop();
+		order.push(name);
+	}
+	let mut order = Vec::with_capacity(names.len());
+	let mut missing = vec![];
+	names.iter().for_each(|name| visit(addons, name, &mut vec![], &mut order, &mut missing));
+	(order, missing)
+}
+
+/// The first two of the given addons which can't be loaded together. Either
+/// one of them may say that it conflicts with the other. Addons whose files
+/// are missing aren't loaded, so they don't conflict with anything.
+pub fn find_conflict<'a>(addons: &AddonMap, names: &[&'a String]) -> Option<(&'a String, &'a String)> {
+	let names: Vec<&'a String> = names.iter()
+		.copied()
+		.filter(|name| addons.get(*name).map_or(true, |spec| spec.missing.is_empty()))
+		.collect();
+	let conflicts = |name: &String, other: &String| addons.get(name)
+		.and_then(|spec| spec.conflicts.as_ref())
+		.map_or(false, |conflicts| conflicts.contains(other));
+	names.iter().enumerate().find_map(|(index, &name)| {
+		names[index + 1..].iter()
+			.find(|&&other| conflicts(name, other) || conflicts(other, name))
+			.map(|&other| (name, other))
+	})
+}
+
+/// Environment variable which sets the addon base directory
+pub const ADDON_DIR_VAR: &str = "TALAUNCHER_ADDON_DIR";
+
+/// Find the directory which addon file paths are relative to. In order of
+/// precedence, this is:
+///
+/// 1. The `--addon-dir` command line argument
+/// 2. The `TALAUNCHER_ADDON_DIR` environment variable
+/// 3. The directory the addon list is in
+/// 4. The current working directory (an empty path)
+pub fn addon_base_dir(addon_dir: Option<&Path>, manifest: &Path) -> PathBuf {
+	find_base_dir(addon_dir, env::var_os(ADDON_DIR_VAR), manifest)
+}
+
+/// Like `addon_base_dir`, with the value of the environment variable given
+fn find_base_dir(addon_dir: Option<&Path>, env_dir: Option<OsString>, manifest: &Path) -> PathBuf {
+	if let Some(dir) = addon_dir {
+		return dir.to_path_buf();
+	}
+	if let Some(dir) = env_dir.filter(|dir| !dir.is_empty()) {
+		return PathBuf::from(dir);
+	}
+	manifest.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// Why the addon list couldn't be loaded
+#[derive(Debug)]
+pub enum AddonLoadError {
+	/// The addon list couldn't be read
+	Io(io::Error),
+	/// The addon list isn't valid YAML, or something in it has the wrong type
+	Parse(serde_yaml::Error),
+	/// The same, for an addon list written in TOML
+	Toml(toml::de::Error),
+	/// The addon list has nothing in it, other than comments
+	Empty,
+	/// The addon entries were written at the top level of the file instead of
+	/// being put under `addons:`
+	MissingAddonsKey,
+	/// More than one addon has this name, so only one of them would be kept
+	DuplicateAddon(String),
+}
+
+impl fmt::Display for AddonLoadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			AddonLoadError::Io(e) => write!(f, "Could not read the addon list: {}", e),
+			AddonLoadError::Parse(e) => match e.location() {
+				Some(location) => {
+					// The location is given in a friendlier way instead
+					let message = e.to_string();
+					let message = match message.rfind(" at line ") {
+						Some(end) => &message[..end],
+						None => &message,
+					};
+					write!(
+						f,
+						"Error in the addon list at line {}, column {}: {}",
+						location.line(),
+						location.column(),
+						message,
+					)
+				}
+				None => write!(f, "The addon list could not be parsed: {}", e),
+			},
+			// The error says where in the file it is
+			AddonLoadError::Toml(e) => write!(f, "The addon list could not be parsed:\n{}", e),
+			AddonLoadError::Empty => {
+				writeln!(f, "The addon list is empty.")?;
+				write!(f, "Add your addons under a top-level `addons:` key.")
+			}
+			AddonLoadError::MissingAddonsKey => {
+				writeln!(f, "The addon list has no top-level `addons:` key.")?;
+				writeln!(f, "It looks like the addons are at the top level of the file.")?;
+				write!(f, "Put them under `addons:`, and indent each addon beneath it.")
+			}
+			AddonLoadError::DuplicateAddon(name) => {
+				writeln!(f, "There is more than one addon named \"{}\" in the addon list.", name)?;
+				write!(f, "Rename or remove one of them.")
+			}
+		}
+	}
+}
+
+impl Error for AddonLoadError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			AddonLoadError::Io(e) => Some(e),
+			AddonLoadError::Parse(e) => Some(e),
+			AddonLoadError::Toml(e) => Some(e),
+			AddonLoadError::Empty |
+			AddonLoadError::MissingAddonsKey |
+			AddonLoadError::DuplicateAddon(_) => None,
+		}
+	}
+}
+
+impl From<io::Error> for AddonLoadError {
+	fn from(e: io::Error) -> Self {
+		AddonLoadError::Io(e)
+	}
+}
+
+impl From<serde_yaml::Error> for AddonLoadError {
+	fn from(e: serde_yaml::Error) -> Self {
+		AddonLoadError::Parse(e)
+	}
+}
+
+/// Whether automatically found addons are primary or secondary addons
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddonKind {
+	Primary,
+	Secondary,
+}
+
+/// Files matching a glob pattern, each of which is turned into an addon
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutoAddons {
+	pub pattern: String,
+	#[serde(rename = "as")]
+	pub kind: AddonKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Manifest {
+	addons: AddonMap,
+	auto_addons: Option<Vec<AutoAddons>>,
+	rules: Option<SelectionRules>,
+	optional_files: Option<OptionalFiles>,
+	manifest_url: Option<String>,
+	version: Option<serde_yaml::Value>,
+	iwad_help: Option<IwadHelp>,
+	always_load: Option<Vec<String>>,
+}
+
+/// Formats an addon list can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ManifestFormat {
+	Yaml,
+	Toml,
+}
+
+impl ManifestFormat {
+	/// The format of the addon list, going by its file extension. Anything
+	/// but `.toml` is read as YAML.
+	fn of(fname: &str) -> ManifestFormat {
+		let extension = Path::new(fname).extension().map(|ext| ext.to_ascii_lowercase());
+		match extension {
+			Some(ext) if ext == "toml" => ManifestFormat::Toml,
+			_ => ManifestFormat::Yaml,
+		}
+	}
 
-	#[derive(Serialize, Deserialize, Debug, Clone)]
-	struct Addons {
-		addons: AddonMap,
+	fn parse(self, contents: &str) -> Result<Manifest, AddonLoadError> {
+		match self {
+			ManifestFormat::Yaml => parse_manifest(contents),
+			ManifestFormat::Toml => parse_toml_manifest(contents),
+		}
 	}
+}
+
+/// Check that an addon list can be read, without looking for its files. It
+/// is read as TOML or YAML depending on `fname`, like `get_addons` does.
+pub fn check_manifest(fname: &str, contents: &str) -> Result<(), AddonLoadError> {
+	ManifestFormat::of(fname).parse(contents).map(|_| ())
+}
+
+/// The version of an addon list, if it has one
+pub fn manifest_version(fname: &str, contents: &str) -> Option<String> {
+	ManifestFormat::of(fname).parse(contents).ok()?.version.as_ref().and_then(version_string)
+}
+
+/// Versions may be written as numbers, like `version: 3`
+fn version_string(version: &serde_yaml::Value) -> Option<String> {
+	match version {
+		serde_yaml::Value::String(version) => Some(version.clone()),
+		serde_yaml::Value::Number(version) => Some(version.to_string()),
+		_ => None,
+	}
+}
+
+/// Just the names of the addons, in the order they are written. Reading the
+/// addons into a map would keep only the last of any addons with the same
+/// name.
+#[derive(Deserialize)]
+struct AddonNames {
+	addons: NameList,
+}
 
-	let addons: Addons = serde_yaml::from_str(&contents)?;
-	let addons: AddonMap = addons.addons.into_iter()
-		.filter(|(name, entry)| {
-		name.to_lowercase() != "none" &&
-		entry.required.iter().all(|req_file| File::open(req_file).is_ok())
-	}).collect();
-	Ok(addons)
+struct NameList(Vec<String>);
+
+impl<'de> Deserialize<'de> for NameList {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct NameVisitor;
+		impl<'de> serde::de::Visitor<'de> for NameVisitor {
+			type Value = NameList;
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				f.write_str("a map of addons")
+			}
+			fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<NameList, A::Error> {
+				let mut names = vec![];
+				while let Some((name, _)) = map.next_entry::<String, serde::de::IgnoredAny>()? {
+					names.push(name);
+				}
+				Ok(NameList(names))
+			}
+		}
+		deserializer.deserialize_map(NameVisitor)
+	}
+}
+
+/// The first addon name which is used more than once, if any
+fn duplicate_addon(contents: &str) -> Option<String> {
+	let names = serde_yaml::from_str::<AddonNames>(contents).ok()?.addons.0;
+	names.iter().enumerate()
+		.find(|(index, name)| names[..*index].contains(name))
+		.map(|(_, name)| name.clone())
+}
+
+fn parse_manifest(contents: &str) -> Result<Manifest, AddonLoadError> {
+	if let Some(name) = duplicate_addon(contents) {
+		return Err(AddonLoadError::DuplicateAddon(name));
+	}
+	match serde_yaml::from_str::<Manifest>(contents) {
+		Ok(manifest) => Ok(manifest),
+		Err(e) => {
+			let empty = serde_yaml::from_str::<serde_yaml::Value>(contents)
+				.map(|value| value.is_null())
+				.unwrap_or(false);
+			// A common mistake is to leave out the `addons:` wrapper
+			let unwrapped = serde_yaml::from_str::<AddonMap>(contents)
+				.map(|addons| !addons.is_empty())
+				.unwrap_or(false);
+			if empty {
+				Err(AddonLoadError::Empty)
+			} else if unwrapped {
+				Err(AddonLoadError::MissingAddonsKey)
+			} else {
+				Err(AddonLoadError::Parse(e))
+			}
+		}
+	}
+}
+
+/// How many times to try reading the addon list, and how long to wait before
+/// trying again. The wait doubles after each attempt.
+const READ_ATTEMPTS: u32 = 4;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether an error may go away if reading the file is tried again, like when
+/// another program (such as a cloud sync client) has the file locked.
+fn is_transient(error: &io::Error) -> bool {
+	// ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+	let locked = cfg!(target_family = "windows") &&
+		matches!(error.raw_os_error(), Some(32) | Some(33));
+	locked || matches!(error.kind(),
+		io::ErrorKind::Interrupted |
+		io::ErrorKind::WouldBlock |
+		io::ErrorKind::TimedOut)
+}
+
+/// Read an addon list written in TOML, with each addon in an
+/// `[addons."Name"]` table
+fn parse_toml_manifest(contents: &str) -> Result<Manifest, AddonLoadError> {
+	toml::from_str::<Manifest>(contents).map_err(|e| {
+		let empty = toml::from_str::<toml::Table>(contents)
+			.map(|table| table.is_empty())
+			.unwrap_or(false);
+		if empty {
+			return AddonLoadError::Empty;
+		}
+		match toml_duplicate_addon(&e) {
+			Some(name) => AddonLoadError::DuplicateAddon(name),
+			None => AddonLoadError::Toml(e),
+		}
+	})
+}
+
+/// The addon which a TOML error says is written twice. The TOML parser
+/// rejects the second one itself, so `duplicate_addon` never sees it.
+fn toml_duplicate_addon(error: &toml::de::Error) -> Option<String> {
+	let message = error.message().lines().last()?;
+	let name = message.strip_prefix("duplicate key `")?.strip_suffix("` in table `addons`")?;
+	// Names which need quotes are shown with them
+	let unquoted = name.strip_prefix('"').and_then(|name| name.strip_suffix('"'));
+	Some(String::from(unquoted.unwrap_or(name)))
+}
+
+fn read_manifest(fname: &str) -> io::Result<String> {
+	let mut attempt = 1;
+	let mut delay = RETRY_DELAY;
+	loop {
+		match fs::read_to_string(fname) {
+			Err(e) if attempt < READ_ATTEMPTS && is_transient(&e) => {
+				thread::sleep(delay);
+				attempt += 1;
+				delay *= 2;
+			},
+			result => return result,
+		}
+	}
+}
+
+/// Read the addon list, and note which files of each addon are missing. It
+/// is read as TOML if it is a `.toml` file, and as YAML otherwise. With
+/// `ignore_case`, files which don't exist as written are looked for again
+/// ignoring case, which is slower.
+pub fn get_addons(
+	fname: Option<&str>,
+	base_dir: &Path,
+	ignore_case: bool,
+) -> Result<AddonList, AddonLoadError> {
+	let fname = fname.unwrap_or("addons.yml");
+	let contents = read_manifest(fname)?;
+
+	let manifest = ManifestFormat::of(fname).parse(&contents)?;
+	let mut warnings = vec![];
+	let resolve = |file: &String| {
+		let path = base_dir.join(file);
+		if ignore_case && !path.exists() {
+			if let Some(found) = checks::find_ignoring_case(base_dir, Path::new(file)) {
+				eprintln!("Using {} for {}", found.display(), file);
+				return found.to_string_lossy().into_owned();
+			}
+		}
+		path.to_string_lossy().into_owned()
+	};
+	let mut addons: AddonMap = manifest.addons.into_iter()
+		.map(|(name, mut entry)| {
+			entry.required = entry.required.iter()
+				.flat_map(|file| match is_pattern(file) {
+					true => {
+						let (files, bad) = expand_pattern(base_dir, file, ignore_case);
+						warnings.extend(bad);
+						files
+					},
+					false => vec![resolve(file)],
+				})
+				.collect();
+			entry.required_any = entry.required_any
+				.map(|any| any.iter().map(resolve).collect());
+			entry.optional = entry.optional
+				.map(|optional| optional.iter().map(resolve).collect());
+			entry.deh = entry.deh.map(|deh| deh.iter().map(resolve).collect());
+			entry.checksums = entry.checksums.map(|checksums| checksums.into_iter()
+				.map(|(file, checksum)| (resolve(&file), checksum.trim().to_lowercase()))
+				.collect());
+			entry.cwd = entry.cwd.as_ref().map(resolve);
+			entry.variants = entry.variants.map(|variants| variants.into_iter()
+				.map(|(name, files)| (name, files.iter().map(resolve).collect()))
+				.collect());
+			(name, entry)
+		})
+		.collect();
+	manifest.auto_addons.iter().flatten().for_each(|auto| {
+		warnings.extend(expand_auto_addons(&mut addons, auto, base_dir, ignore_case));
+	});
+	// Addons with missing files are kept, so that the user can see what is
+	// wrong with them. Looking for files is slow on some drives, so addons
+	// are checked in parallel.
+	let mut addons: AddonMap = addons.into_par_iter()
+		.filter(|(name, _entry)| name.to_lowercase() != "none")
+		.map(|(name, mut entry)| {
+			entry.missing = entry.find_missing();
+			(name, entry)
+		})
+		.collect();
+	let mut always_load = AddonMap::new();
+	let mut missing_always_load = vec![];
+	manifest.always_load.iter().flatten().for_each(|name| match addons.remove(name) {
+		Some(addon) if addon.missing.is_empty() => {
+			always_load.insert(name.clone(), addon);
+		},
+		_ => missing_always_load.push(name.clone()),
+	});
+	let mut incomplete: Vec<(String, Vec<String>)> = addons.iter()
+		.filter(|(_name, addon)| !addon.missing.is_empty())
+		.map(|(name, addon)| (name.clone(), addon.missing.clone()))
+		.collect();
+	incomplete.sort();
+	Ok(AddonList {
+		addons,
+		incomplete,
+		warnings,
+		settings: ListSettings {
+			rules: manifest.rules.unwrap_or_default(),
+			optional_files: manifest.optional_files,
+			manifest_url: manifest.manifest_url,
+			iwad_help: manifest.iwad_help,
+			always_load,
+			missing_always_load,
+		},
+	})
+}
+
+/// The SHA-256 hash of a file, in lowercase hexadecimal
+pub fn sha256_file(path: &impl AsRef<Path>) -> io::Result<String> {
+	let mut file = File::open(path)?;
+	let mut hasher = Sha256::new();
+	io::copy(&mut file, &mut hasher)?;
+	Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// The files which don't match their checksums, with the names of their
+/// addons, sorted. Missing files are left out, since they are reported
+/// already.
+pub fn verify_checksums(addons: &AddonMap) -> Vec<(String, String)> {
+	let mut mismatched: Vec<(String, String)> = addons.iter()
+		.flat_map(|(name, addon)| addon.checksums.iter().flatten()
+			.filter(|(file, checksum)| {
+				sha256_file(file).map_or(false, |actual| actual != **checksum)
+			})
+			.map(move |(file, _)| (name.clone(), file.clone())))
+		.collect();
+	mismatched.sort();
+	mismatched
+}
+
+/// Whether a file name is a glob pattern, like `coolmod-*.pk3`
+fn is_pattern(file: &str) -> bool {
+	file.contains(['*', '?', '['])
+}
+
+/// The files matching the pattern, in alphabetical order. If nothing
+/// matches, the pattern is kept as it is, so that it shows up as missing.
+/// With `ignore_case`, case is ignored in the folders and in the pattern.
+/// Returns the files, and a warning if the pattern is bad.
+fn expand_pattern(base_dir: &Path, pattern: &str, ignore_case: bool) -> (Vec<String>, Vec<String>) {
+	let full_pattern = base_dir.join(pattern).to_string_lossy().into_owned();
+	let glob_pattern = match ignore_case {
+		true => pattern_ignoring_case(base_dir, pattern),
+		false => full_pattern.clone(),
+	};
+	let options = glob::MatchOptions {
+		case_sensitive: !ignore_case,
+		..Default::default()
+	};
+	let mut warnings = vec![];
+	let mut files: Vec<String> = match glob::glob_with(&glob_pattern, options) {
+		Ok(paths) => paths.flatten()
+			.filter(|path| path.is_file())
+			.map(|path| path.to_string_lossy().into_owned())
+			.collect(),
+		Err(e) => {
+			warnings.push(format!("Bad required file pattern {}: {}", pattern, e));
+			vec![]
+		}
+	};
+	files.sort();
+	if files.is_empty() {
+		files.push(full_pattern);
+	}
+	(files, warnings)
+}
+
+/// The pattern under `base_dir`, with the folders before the first part
+/// which has wildcards found ignoring case. The glob matcher only ignores
+/// case in the parts which have wildcards.
+fn pattern_ignoring_case(base_dir: &Path, pattern: &str) -> String {
+	let mut folders = PathBuf::new();
+	let mut rest = PathBuf::new();
+	Path::new(pattern).components().for_each(|component| {
+		let wildcard = is_pattern(&component.as_os_str().to_string_lossy());
+		match rest.as_os_str().is_empty() && !wildcard {
+			true => folders.push(component),
+			false => rest.push(component),
+		}
+	});
+	let folders = checks::find_ignoring_case(base_dir, &folders)
+		.unwrap_or_else(|| base_dir.join(&folders));
+	folders.join(rest).to_string_lossy().into_owned()
+}
+
+/// Add an addon for each file matching the pattern. Addons which are
+/// explicitly defined take precedence, by name or by file. Returns a warning
+/// if the pattern is bad.
+fn expand_auto_addons(
+	addons: &mut AddonMap,
+	auto: &AutoAddons,
+	base_dir: &Path,
+	ignore_case: bool,
+) -> Option<String> {
+	let pattern = match ignore_case {
+		true => pattern_ignoring_case(base_dir, &auto.pattern),
+		false => base_dir.join(&auto.pattern).to_string_lossy().into_owned(),
+	};
+	let options = glob::MatchOptions {
+		case_sensitive: !ignore_case,
+		..Default::default()
+	};
+	let paths = match glob::glob_with(&pattern, options) {
+		Ok(paths) => paths,
+		Err(e) => return Some(format!("Bad auto_addons pattern {}: {}", auto.pattern, e)),
+	};
+	paths.flatten().filter(|path| path.is_file()).for_each(|path| {
+		let name = match path.file_stem() {
+			Some(stem) => stem.to_string_lossy().into_owned(),
+			None => return,
+		};
+		let file = path.to_string_lossy().into_owned();
+		let explicit = addons.contains_key(&name) ||
+			addons.values().any(|addon| addon.required.contains(&file));
+		if explicit {
+			return;
+		}
+		let addon = AddonSpecification {
+			required: vec![file],
+			secondary: match auto.kind {
+				AddonKind::Primary => None,
+				AddonKind::Secondary => Some(String::from("1")),
+			},
+			..Default::default()
+		};
+		addons.insert(name, addon);
+	});
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+
+	#[test]
+	fn wrapped() {
+		let manifest = "addons:\n  Foo:\n    required:\n      - foo.pk3\n";
+		let manifest = parse_manifest(manifest).unwrap();
+		assert_eq!(manifest.addons["Foo"].required, vec!["foo.pk3"]);
+	}
+
+	#[test]
+	fn unwrapped() {
+		let manifest = "Foo:\n  required:\n    - foo.pk3\nBar:\n  required: []\n";
+		let error = parse_manifest(manifest).unwrap_err();
+		assert!(matches!(error, AddonLoadError::MissingAddonsKey));
+	}
+
+	#[test]
+	fn base_dir() {
+		let env_dir = || Some(OsString::from("/addons"));
+		let dir = find_base_dir(Some(Path::new("/mods")), env_dir(), Path::new("/lists/addons.yml"));
+		assert_eq!(dir, PathBuf::from("/mods"));
+		let dir = find_base_dir(None, env_dir(), Path::new("/lists/addons.yml"));
+		assert_eq!(dir, PathBuf::from("/addons"));
+		let dir = find_base_dir(None, Some(OsString::new()), Path::new("/lists/addons.yml"));
+		assert_eq!(dir, PathBuf::from("/lists"));
+		let dir = find_base_dir(None, None, Path::new("addons.yml"));
+		assert_eq!(dir, PathBuf::new());
+	}
+
+	#[test]
+	fn auto_addons() {
+		let dir = TestDir::new("auto-addons");
+		["Alpha.pk3", "Beta.pk3", "Gamma.pk3", "readme.txt"].iter().for_each(|file| {
+			File::create(dir.join(file)).unwrap();
+		});
+		let mut addons = AddonMap::new();
+		addons.insert(String::from("Gamma"), AddonSpecification {
+			required: vec![String::from("explicit.pk3")],
+			..Default::default()
+		});
+		let auto = AutoAddons {
+			pattern: String::from("*.pk3"),
+			kind: AddonKind::Secondary,
+		};
+		expand_auto_addons(&mut addons, &auto, &dir, false);
+
+		assert_eq!(addons.len(), 3);
+		assert_eq!(addons["Alpha"].required, vec![dir.join("Alpha.pk3").to_string_lossy()]);
+		assert!(addons["Beta"].secondary.is_some());
+		// Explicitly defined addons take precedence
+		assert_eq!(addons["Gamma"].required, vec!["explicit.pk3"]);
+		assert!(addons["Gamma"].secondary.is_none());
+	}
+
+	#[test]
+	fn required_any() {
+		let dir = TestDir::new("required-any");
+		let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+		File::create(file("base.pk3")).unwrap();
+		File::create(file("music-b.pk3")).unwrap();
+		let mut addon = AddonSpecification {
+			required: vec![file("base.pk3")],
+			required_any: Some(vec![file("music-a.pk3"), file("music-c.pk3")]),
+			..Default::default()
+		};
+		let none_present = addon.find_missing();
+		addon.required_any = Some(vec![file("music-a.pk3"), file("music-b.pk3")]);
+		let some_present = addon.find_missing();
+
+		assert_eq!(none_present, [file("music-a.pk3"), file("music-c.pk3")]);
+		assert!(some_present.is_empty());
+	}
+
+	#[test]
+	fn required_patterns() {
+		let dir = TestDir::new("required-patterns");
+		File::create(dir.join("coolmod-1.2.pk3")).unwrap();
+		File::create(dir.join("coolmod-1.10.pk3")).unwrap();
+		File::create(dir.join("base.pk3")).unwrap();
+		let manifest = dir.join("addons.yml");
+		std::fs::write(&manifest, "addons:\n  \
+			Cool:\n    required: [base.pk3, coolmod-*.pk3]\n  \
+			Missing:\n    required: [othermod-*.pk3]\n  \
+			Bad:\n    required: [\"[oops.pk3\"]\n").unwrap();
+		let list = get_addons(Some(&manifest.to_string_lossy()), &dir, false).unwrap();
+
+		let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+		assert_eq!(list.addons["Cool"].required, [
+			file("base.pk3"),
+			file("coolmod-1.10.pk3"),
+			file("coolmod-1.2.pk3"),
+		]);
+		assert!(list.addons["Cool"].missing.is_empty());
+		// A pattern without matches is a missing file
+		assert_eq!(list.addons["Missing"].missing, [file("othermod-*.pk3")]);
+		// Bad patterns are reported with the list, and are missing too
+		assert_eq!(list.warnings.len(), 1);
+		assert!(list.warnings[0].starts_with("Bad required file pattern [oops.pk3"));
+		assert_eq!(list.addons["Bad"].missing, [file("[oops.pk3")]);
+		assert!(!is_pattern("DOOM64.CE.Maps.Episode1/DOOM64.CE.Maps.Episode1.pk3"));
+	}
+
+	#[test]
+	fn checksums() {
+		let dir = TestDir::new("checksums");
+		std::fs::write(dir.join("good.pk3"), "abc").unwrap();
+		std::fs::write(dir.join("bad.pk3"), "abd").unwrap();
+		let abc = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+		let manifest = dir.join("addons.yml");
+		std::fs::write(&manifest, format!("addons:\n  \
+			Good:\n    required: [good.pk3]\n    checksums:\n      good.pk3: {0}\n  \
+			Bad:\n    required: [good.pk3, bad.pk3]\n    checksums:\n      good.pk3: {0}\n      bad.pk3: {0}\n  \
+			Gone:\n    required: []\n    checksums:\n      gone.pk3: {0}\n", abc)).unwrap();
+		let list = get_addons(Some(&manifest.to_string_lossy()), &dir, false).unwrap();
+		let hash = sha256_file(&dir.join("good.pk3")).unwrap();
+		let mismatched = verify_checksums(&list.addons);
+
+		let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+		assert_eq!(hash, abc.to_lowercase());
+		assert_eq!(mismatched, [(String::from("Bad"), file("bad.pk3"))]);
+	}
+
+	#[test]
+	fn many_addons() {
+		let dir = TestDir::new("many-addons");
+		let count = 500;
+		let mut manifest = String::from("addons:\n");
+		(0..count).for_each(|index| {
+			// Every third addon is missing a file
+			if index % 3 != 0 {
+				File::create(dir.join(format!("addon-{}.pk3", index))).unwrap();
+			}
+			manifest.push_str(&format!("  Addon {0}:\n    required: [addon-{0}.pk3]\n", index));
+		});
+		let manifest_path = dir.join("addons.yml");
+		std::fs::write(&manifest_path, manifest).unwrap();
+		let lists: Vec<AddonList> = (0..3)
+			.map(|_| get_addons(Some(&manifest_path.to_string_lossy()), &dir, false).unwrap())
+			.collect();
+
+		let list = &lists[0];
+		assert_eq!(list.addons.len(), count);
+		assert_eq!(list.incomplete.len(), (0..count).filter(|index| index % 3 == 0).count());
+		(0..count).for_each(|index| {
+			let addon = &list.addons[&format!("Addon {}", index)];
+			assert_eq!(addon.missing.is_empty(), index % 3 != 0);
+		});
+		// The result doesn't depend on which thread checked which addon
+		lists[1..].iter().for_each(|other| {
+			assert_eq!(other.incomplete, list.incomplete);
+			list.addons.iter().for_each(|(name, addon)| {
+				assert_eq!(other.addons[name].missing, addon.missing);
+			});
+		});
+	}
+
+	/// How much checking addons in parallel helps. Run it with
+	/// `cargo test many_addons_timing -- --ignored --nocapture`, ideally with
+	/// the addons on a slow drive.
+	#[test]
+	#[ignore]
+	fn many_addons_timing() {
+		let dir = TestDir::new("many-addons-timing");
+		let addons: Vec<AddonSpecification> = (0..500).map(|index| {
+			let file = dir.join(format!("addon-{}.pk3", index));
+			if index % 3 != 0 {
+				File::create(&file).unwrap();
+			}
+			AddonSpecification {
+				required: vec![file.to_string_lossy().into_owned()],
+				..Default::default()
+			}
+		}).collect();
+		let rounds = 20;
+		let time = |check: &dyn Fn() -> usize| {
+			let start = std::time::Instant::now();
+			let incomplete: Vec<usize> = (0..rounds).map(|_| check()).collect();
+			(start.elapsed() / rounds, incomplete)
+		};
+		let (serial, serial_incomplete) = time(&|| {
+			addons.iter().filter(|addon| !addon.find_missing().is_empty()).count()
+		});
+		let (parallel, parallel_incomplete) = time(&|| {
+			addons.par_iter().filter(|addon| !addon.find_missing().is_empty()).count()
+		});
+		println!(
+			"Checked {} addons in {:?} one at a time, and in {:?} in parallel",
+			addons.len(), serial, parallel,
+		);
+		assert_eq!(serial_incomplete, parallel_incomplete);
+	}
+
+	#[test]
+	fn missing_files() {
+		let dir = TestDir::new("missing-files");
+		File::create(dir.join("present.pk3")).unwrap();
+		File::create(dir.join("present.deh")).unwrap();
+		let manifest = dir.join("addons.yml");
+		std::fs::write(&manifest, "addons:\n  \
+			Present:\n    required: [present.pk3]\n    deh: [present.deh]\n  \
+			Absent:\n    required: [present.pk3, absent.pk3]\n  \
+			Patch:\n    required: []\n    deh: [absent.bex]\n  \
+			Music:\n    required: []\n    required_any: [music-a.pk3, music-b.pk3]\n").unwrap();
+		let list = get_addons(Some(&manifest.to_string_lossy()), &dir, false).unwrap();
+
+		let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+		// Addons with missing files are still listed
+		assert_eq!(list.addons.len(), 4);
+		assert_eq!(list.incomplete, [
+			(String::from("Absent"), vec![file("absent.pk3")]),
+			(String::from("Music"), vec![file("music-a.pk3"), file("music-b.pk3")]),
+			(String::from("Patch"), vec![file("absent.bex")]),
+		]);
+		assert!(list.addons["Present"].missing.is_empty());
+		assert_eq!(list.addons["Present"].deh, Some(vec![file("present.deh")]));
+		assert_eq!(list.addons["Absent"].missing, [file("absent.pk3")]);
+		assert_eq!(list.addons["Music"].missing, [file("music-a.pk3"), file("music-b.pk3")]);
+	}
+
+	#[test]
+	fn ignore_case() {
+		let dir = TestDir::new("ignore-case");
+		std::fs::create_dir_all(dir.join("Music")).unwrap();
+		File::create(dir.join("Music").join("D64Music.PK3")).unwrap();
+		let manifest = dir.join("addons.yml");
+		std::fs::write(&manifest, "addons:\n  \
+			Music:\n    required: [music/d64music.pk3]\n  \
+			Patterned:\n    required: [music/d64*.pk3]\n").unwrap();
+		let manifest = manifest.to_string_lossy();
+		let exact = get_addons(Some(&manifest), &dir, false).unwrap();
+		let ignoring_case = get_addons(Some(&manifest), &dir, true).unwrap();
+
+		assert_eq!(exact.addons["Music"].missing.len(), 1);
+		assert_eq!(exact.addons["Patterned"].missing.len(), 1);
+		let found = vec![dir.join("Music").join("D64Music.PK3").to_string_lossy().into_owned()];
+		assert!(ignoring_case.addons["Music"].missing.is_empty());
+		assert_eq!(ignoring_case.addons["Music"].required, found);
+		// Patterns ignore case too, in the folders and in the file name
+		assert!(ignoring_case.addons["Patterned"].missing.is_empty());
+		assert_eq!(ignoring_case.addons["Patterned"].required, found);
+	}
+
+	#[test]
+	fn rules() {
+		let manifest = "addons:
+  Campaign:
+    required: []
+    tags: [d64]
+  Compat:
+    required: []
+    secondary: 1
+    tags: [d64-compat]
+  Music:
+    required: []
+    secondary: 1
+  Other:
+    required: []
+rules:
+  d64: [d64-compat]
+  Other: [Music]
+";
+		let manifest = parse_manifest(manifest).unwrap();
+		let rules = manifest.rules.unwrap();
+		let addons = manifest.addons;
+		assert_eq!(rule_selections(&rules, &addons, "Campaign"), vec!["Compat"]);
+		assert_eq!(rule_selections(&rules, &addons, "Other"), vec!["Music"]);
+		assert!(rule_selections(&rules, &addons, "None").is_empty());
+	}
+
+	#[test]
+	fn variants() {
+		let manifest = "addons:
+  Textures:
+    required: [base.pk3]
+    variants:
+      Low-res: [low.pk3]
+      High-res: [high.pk3, high-extra.pk3]
+  Plain:
+    required: [plain.pk3]
+";
+		let addons = parse_manifest(manifest).unwrap().addons;
+		let textures = &addons["Textures"];
+		assert_eq!(textures.variant_names(), vec!["High-res", "Low-res"]);
+		assert_eq!(textures.variant_files(None), ["high.pk3", "high-extra.pk3"]);
+		assert_eq!(textures.variant_files(Some("Low-res")), ["low.pk3"]);
+		assert_eq!(textures.variant_files(Some("Removed")), ["high.pk3", "high-extra.pk3"]);
+		assert!(addons["Plain"].variant_files(Some("Low-res")).is_empty());
+		assert_eq!(addons["Plain"].variant(None), None);
+	}
+
+	#[test]
+	fn optional_files() {
+		let manifest = parse_manifest("addons: {}\noptional_files: opt-in\n").unwrap();
+		assert_eq!(manifest.optional_files, Some(OptionalFiles::OptIn));
+		let manifest = parse_manifest("addons: {}\n").unwrap();
+		assert_eq!(manifest.optional_files, None);
+	}
+
+	#[test]
+	fn versions() {
+		assert_eq!(manifest_version("addons.yml", "addons: {}\nversion: 3\n").as_deref(), Some("3"));
+		assert_eq!(
+			manifest_version("addons.yml", "addons: {}\nversion: \"2024-01\"\n").as_deref(),
+			Some("2024-01")
+		);
+		assert_eq!(manifest_version("addons.yml", "addons: {}\n"), None);
+		assert!(check_manifest("addons.yml", "addons: {}\nmanifest_url: https://example.com/addons.yml\n").is_ok());
+		assert!(check_manifest("addons.yml", "- not\n- addons\n").is_err());
+		// TOML lists are read as TOML
+		let toml = "version = 4\nmanifest_url = \"https://example.com/addons.toml\"\n[addons]\n";
+		assert!(check_manifest("addons.toml", toml).is_ok());
+		assert!(check_manifest("addons.yml", toml).is_err());
+		assert_eq!(manifest_version("addons.toml", toml).as_deref(), Some("4"));
+	}
+
+	#[test]
+	fn transient_errors() {
+		assert!(is_transient(&io::Error::from(io::ErrorKind::Interrupted)));
+		// Files locked by another program are only told apart on Windows
+		assert_eq!(is_transient(&io::Error::from_raw_os_error(32)), cfg!(target_family = "windows"));
+		// An unreadable file stays unreadable
+		assert!(!is_transient(&io::Error::from(io::ErrorKind::PermissionDenied)));
+		assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+		assert!(!is_transient(&io::Error::from(io::ErrorKind::InvalidData)));
+	}
+
+	#[test]
+	fn garbage() {
+		let manifest = "- just\n- a\n- list\n";
+		let error = parse_manifest(manifest).unwrap_err();
+		assert!(matches!(error, AddonLoadError::Parse(_)));
+		assert!(error.source().is_some());
+	}
+
+	#[test]
+	fn toml() {
+		assert_eq!(ManifestFormat::of("addons.yml"), ManifestFormat::Yaml);
+		assert_eq!(ManifestFormat::of("sets/Addons.TOML"), ManifestFormat::Toml);
+		assert_eq!(ManifestFormat::of("addons"), ManifestFormat::Yaml);
+
+		let manifest = parse_toml_manifest(concat!(
+			"always_load = [\"Fixes\"]\n",
+			"[addons.Campaign]\n",
+			"required = [\"campaign.pk3\"]\n",
+			"[addons.\"Widescreen HUD\"]\n",
+			"required = [\"hud.pk3\"]\n",
+			"secondary = \"yes\"\n",
+		)).unwrap();
+		assert_eq!(manifest.addons.len(), 2);
+		assert_eq!(manifest.addons["Campaign"].required, ["campaign.pk3"]);
+		assert!(manifest.addons["Widescreen HUD"].secondary.is_some());
+		assert_eq!(manifest.always_load, Some(vec![String::from("Fixes")]));
+
+		assert!(matches!(parse_toml_manifest("# Nothing yet\n"), Err(AddonLoadError::Empty)));
+		let error = parse_toml_manifest("[addons.Campaign]\nrequired = \"campaign.pk3\"\n").unwrap_err();
+		assert!(matches!(error, AddonLoadError::Toml(_)));
+		assert!(error.to_string().contains("line 2"), "{}", error);
+	}
+
+	#[test]
+	fn parse_error_location() {
+		let manifest = "addons:\n  Campaign:\n    required: campaign.pk3\n";
+		let error = parse_manifest(manifest).unwrap_err();
+		assert!(matches!(error, AddonLoadError::Parse(_)));
+		let message = error.to_string();
+		assert!(message.starts_with("Error in the addon list at line 3, column "), "{}", message);
+		assert_eq!(message.matches(" line ").count(), 1, "{}", message);
+	}
+
+	#[test]
+	fn requirements() {
+		let requiring = |requires: &[&str]| AddonSpecification {
+			requires: Some(requires.iter().map(|name| String::from(*name)).collect()),
+			..Default::default()
+		};
+		let addons = AddonMap::from([
+			(String::from("Base"), AddonSpecification::default()),
+			(String::from("Music"), requiring(&["Base"])),
+			(String::from("Remix"), requiring(&["Music", "Base"])),
+			(String::from("Broken"), requiring(&["Nowhere"])),
+			(String::from("Chicken"), requiring(&["Egg"])),
+			(String::from("Egg"), requiring(&["Chicken"])),
+			(String::from("Gone"), AddonSpecification {
+				missing: vec![String::from("gone.pk3")],
+				..Default::default()
+			}),
+			(String::from("Orphan"), requiring(&["Gone", "Base"])),
+		]);
+		let names = |names: &[&str]| names.iter().map(|name| String::from(*name)).collect::<Vec<_>>();
+
+		let selected = names(&["Remix"]);
+		let (order, missing) = with_requirements(&addons, &selected.iter().collect::<Vec<_>>());
+		assert_eq!(order, ["Base", "Music", "Remix"]);
+		assert!(missing.is_empty());
+
+		let selected = names(&["Broken", "Base", "Music"]);
+		let (order, missing) = with_requirements(&addons, &selected.iter().collect::<Vec<_>>());
+		assert_eq!(order, ["Broken", "Base", "Music"]);
+		assert_eq!(missing, ["Broken requires Nowhere, which isn't available."]);
+
+		let selected = names(&["Chicken"]);
+		let (order, _) = with_requirements(&addons, &selected.iter().collect::<Vec<_>>());
+		assert_eq!(order, ["Egg", "Chicken"]);
+
+		// An addon whose files are missing can't be loaded for another one
+		let selected = names(&["Orphan"]);
+		let (order, missing) = with_requirements(&addons, &selected.iter().collect::<Vec<_>>());
+		assert_eq!(order, ["Base", "Orphan"]);
+		assert_eq!(missing, ["Orphan requires Gone, which is missing these files: gone.pk3"]);
+	}
+
+	#[test]
+	fn conflicts() {
+		let addons = AddonMap::from([
+			(String::from("Smooth"), AddonSpecification {
+				conflicts: Some(vec![String::from("Sharp")]),
+				..Default::default()
+			}),
+			(String::from("Sharp"), AddonSpecification::default()),
+			(String::from("Music"), AddonSpecification::default()),
+			(String::from("Rough"), AddonSpecification {
+				conflicts: Some(vec![String::from("Music")]),
+				missing: vec![String::from("rough.pk3")],
+				..Default::default()
+			}),
+		]);
+		let [smooth, sharp, music] = ["Smooth", "Sharp", "Music"].map(String::from);
+		assert_eq!(find_conflict(&addons, &[&smooth, &music, &sharp]), Some((&smooth, &sharp)));
+		// Only one of them has to say so
+		assert_eq!(find_conflict(&addons, &[&music, &sharp, &smooth]), Some((&sharp, &smooth)));
+		assert_eq!(find_conflict(&addons, &[&music, &sharp]), None);
+		// Addons whose files are missing aren't loaded
+		let rough = String::from("Rough");
+		assert_eq!(find_conflict(&addons, &[&rough, &music]), None);
+	}
+
+	#[test]
+	fn credits() {
+		let manifest = "addons:
+  Beta64:
+    required: []
+    author: Antnee
+    version: 1.2
+  Unknown:
+    required: []
+  Anonymous:
+    required: []
+    version: \"2.0-beta\"
+";
+		let addons = parse_manifest(manifest).unwrap().addons;
+		assert_eq!(addons["Beta64"].credits().unwrap(), "Author: Antnee - v1.2");
+		assert_eq!(addons["Anonymous"].credits().unwrap(), "v2.0-beta");
+		assert!(addons["Unknown"].credits().is_none());
+	}
+
+	#[test]
+	fn platforms() {
+		let mut addon = AddonSpecification::default();
+		assert!(addon.supports_platform("linux"));
+		assert!(addon.supported_here());
+		assert!(addon.platform_note().is_none());
+
+		addon.platforms = Some(vec![String::from("Windows"), String::from("macos")]);
+		assert!(addon.supports_platform("windows"));
+		assert!(!addon.supports_platform("linux"));
+
+		addon.platforms = Some(vec![String::from("no-such-os")]);
+		assert!(!addon.supported_here());
+		assert_eq!(addon.platform_note().unwrap(), "Only works on no-such-os");
+	}
+
+	#[test]
+	fn duplicate() {
+		let manifest = "addons:\n  Foo:\n    required: [foo.pk3]\n  Bar:\n    required: []\n  \
+			Foo:\n    required: [foo2.pk3]\n";
+		let error = parse_manifest(manifest).unwrap_err();
+		assert!(matches!(&error, AddonLoadError::DuplicateAddon(name) if name == "Foo"));
+		assert!(error.to_string().contains("\"Foo\""));
+		assert!(duplicate_addon("addons:\n  Foo:\n    required: []\n").is_none());
+
+		let manifest = "[addons.\"Foo Bar\"]\nrequired = []\n[addons.Baz]\nrequired = []\n\
+			[addons.\"Foo Bar\"]\nrequired = []\n";
+		let error = parse_toml_manifest(manifest).unwrap_err();
+		assert!(matches!(&error, AddonLoadError::DuplicateAddon(name) if name == "Foo Bar"), "{}", error);
+		let manifest = "[addons]\nFoo = { required = [] }\nFoo = { required = [] }\n";
+		let error = parse_toml_manifest(manifest).unwrap_err();
+		assert!(matches!(&error, AddonLoadError::DuplicateAddon(name) if name == "Foo"), "{}", error);
+	}
+
+	#[test]
+	fn empty() {
+		["", "\n", "# Nothing here yet\n"].iter().for_each(|manifest| {
+			let error = parse_manifest(manifest).unwrap_err();
+			assert!(matches!(error, AddonLoadError::Empty));
+			assert!(error.to_string().contains("empty"));
+		});
+	}
+
+	#[test]
+	fn unreadable() {
+		let path = TestDir::new("unreadable").join("addons.yml");
+		let error = get_addons(Some(&path.to_string_lossy()), Path::new(""), false).unwrap_err();
+		match error {
+			AddonLoadError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+			error => panic!("Expected an I/O error, got {:?}", error),
+		}
+	}
 }
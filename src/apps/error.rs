@@ -32,10 +32,79 @@ impl App for ErrorMessage {
 					});
 				});
 				ui.end_row();
+				ui.code("--iwad-glob ptn");
+				ui.label("A 'glob' pattern for finding IWADs, instead of looking in DOOMWADDIR, the current directory, and the usual places.");
+				ui.end_row();
+				ui.code("--addons-file file");
+				ui.label("The addon list to use instead of addons.yml.");
+				ui.end_row();
+				ui.code("--builds file");
+				ui.label("A YAML or JSON file listing GZDoom builds, with optional labels.");
+				ui.end_row();
+				ui.code("--select names");
+				ui.vertical(|ui| {
+					ui.label("Select addons by name, separated by commas.");
+					ui.horizontal(|ui| {
+						ui.label("A");
+						ui.code("talauncher://");
+						ui.label("URL with a list of names works too.");
+					});
+				});
+				ui.end_row();
 				ui.code("--quit-on-launch");
 				ui.label("Quit this program when you launch the game.");
 				ui.end_row();
+				ui.code("--addon-dir dir");
+				ui.vertical(|ui| {
+					ui.label("The directory addon file paths are relative to.");
+					ui.horizontal(|ui| {
+						ui.label("If not given, the");
+						ui.code("TALAUNCHER_ADDON_DIR");
+						ui.label("environment variable is used,");
+					});
+					ui.label("then the directory addons.yml is in.");
+				});
+				ui.end_row();
+				ui.code("--verbose");
+				ui.label("Print extra information, like the addon directory.");
+				ui.end_row();
+				ui.code("--self-test file");
+				ui.label("Print the launch command for an addon list, and check it.");
+				ui.end_row();
+				ui.code("--arg-order order");
+				ui.label("Comma-separated order of the GZDoom arguments, for forks which need it. Segments are engine, iwad, config, loadgame, file and exec.");
+				ui.end_row();
+				ui.code("--allow-network");
+				ui.label("Allow checking for a newer addon list at the manifest_url in addons.yml.");
+				ui.end_row();
+				ui.code("--verify-checksums");
+				ui.label("Check addon files against the SHA-256 checksums in addons.yml. This is slow for big files.");
+				ui.end_row();
+				ui.code("--ignore-case");
+				ui.label("Look for addon files again ignoring case, if they don't exist as written in addons.yml.");
+				ui.end_row();
+				ui.code("--emit-schema");
+				ui.label("Print a JSON Schema for addons.yml, for editors which support it, and exit.");
+				ui.end_row();
+				ui.code("--launch");
+				ui.label("Launch GZDoom without opening a window, and exit with its exit code. \
+					Use it with --gzdoom path, --iwad path, --primary name and --secondary name, \
+					which can be given more than once.");
+				ui.end_row();
+				ui.code("--list-addons");
+				ui.label("Print each addon and whether its files exist, and exit. The exit code is 1 if any are missing.");
+				ui.end_row();
+				ui.code("--wine path");
+				ui.label("Wine program for running Windows GZDoom builds on other systems.");
+				ui.end_row();
+				ui.code("--vsync");
+				ui.label("Enable vertical sync for this program's window.");
+				ui.end_row();
+				ui.code("--msaa n");
+				ui.label("Use n samples for anti-aliasing this program's window.");
+				ui.end_row();
 			});
+			ui.label("--vsync and --msaa only take effect when this program starts.");
 			if ui.button("Exit").clicked() {
 				self.1 = true;
 			}
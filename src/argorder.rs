@@ -0,0 +1,125 @@
+use std::fmt;
+
+/// A group of arguments which is passed to GZDoom as a unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+	/// Arguments from the extra arguments box, then those of the loaded addons
+	Engine,
+	Iwad,
+	Config,
+	LoadGame,
+	Files,
+	Exec,
+}
+
+impl Segment {
+	const ALL: [Segment; 6] = [
+		Segment::Engine,
+		Segment::Iwad,
+		Segment::Config,
+		Segment::LoadGame,
+		Segment::Files,
+		Segment::Exec,
+	];
+
+	fn name(self) -> &'static str {
+		match self {
+			Segment::Engine => "engine",
+			Segment::Iwad => "iwad",
+			Segment::Config => "config",
+			Segment::LoadGame => "loadgame",
+			Segment::Files => "file",
+			Segment::Exec => "exec",
+		}
+	}
+
+	fn from_name(name: &str) -> Option<Segment> {
+		Segment::ALL.into_iter().find(|segment| segment.name() == name)
+	}
+}
+
+impl fmt::Display for Segment {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// The order in which the argument segments are passed to GZDoom. Some forks
+/// are picky about this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgOrder(Vec<Segment>);
+
+impl Default for ArgOrder {
+	fn default() -> Self {
+		ArgOrder(Segment::ALL.to_vec())
+	}
+}
+
+impl ArgOrder {
+	/// Parse a comma-separated template, like `iwad,file,config`. Segments
+	/// which aren't in the template go after it, in the default order.
+	pub fn parse(template: &str) -> Result<ArgOrder, String> {
+		let mut order = Vec::with_capacity(Segment::ALL.len());
+		for name in template.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+			let segment = Segment::from_name(name).ok_or_else(|| {
+				let known: Vec<&str> = Segment::ALL.iter().map(|s| s.name()).collect();
+				format!("Unknown argument segment \"{}\" (expected one of: {})", name, known.join(", "))
+			})?;
+			if order.contains(&segment) {
+				return Err(format!("Argument segment \"{}\" is used more than once", segment));
+			}
+			order.push(segment);
+		}
+		let rest: Vec<Segment> = Segment::ALL
+			.into_iter()
+			.filter(|segment| !order.contains(segment))
+			.collect();
+		order.extend(rest);
+		Ok(ArgOrder(order))
+	}
+
+	/// Put together the arguments for each segment, in this order
+	pub fn arrange(&self, mut segment: impl FnMut(Segment) -> Vec<String>) -> Vec<String> {
+		self.0.iter().flat_map(|&s| segment(s)).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn arranged(template: &str) -> Vec<String> {
+		ArgOrder::parse(template).unwrap().arrange(|segment| match segment {
+			Segment::Engine => vec![String::from("+vid_fps")],
+			Segment::Iwad => vec![String::from("-iwad"), String::from("DOOM64.WAD")],
+			Segment::Config => vec![String::from("-config"), String::from("d64.ini")],
+			Segment::LoadGame => vec![],
+			Segment::Files => vec![String::from("-file"), String::from("a.pk3")],
+			Segment::Exec => vec![],
+		})
+	}
+
+	#[test]
+	fn default_order() {
+		assert_eq!(ArgOrder::parse("").unwrap(), ArgOrder::default());
+		assert_eq!(arranged(""), [
+			"+vid_fps", "-iwad", "DOOM64.WAD", "-config", "d64.ini", "-file", "a.pk3",
+		]);
+	}
+
+	#[test]
+	fn reordered() {
+		assert_eq!(arranged("config, file"), [
+			"-config", "d64.ini", "-file", "a.pk3", "+vid_fps", "-iwad", "DOOM64.WAD",
+		]);
+		assert_eq!(arranged("iwad,file,engine,config"), [
+			"-iwad", "DOOM64.WAD", "-file", "a.pk3", "+vid_fps", "-config", "d64.ini",
+		]);
+	}
+
+	#[test]
+	fn invalid() {
+		assert!(ArgOrder::parse("iwad,file,iwad").unwrap_err().contains("more than once"));
+		assert!(ArgOrder::parse("iwad,wads").unwrap_err().contains("\"wads\""));
+	}
+}
@@ -0,0 +1,235 @@
+use std::path::PathBuf;
+
+/// Command line arguments for the launcher itself
+#[derive(Debug, Clone, Default)]
+pub struct LauncherArgs {
+	pub addon_dir: Option<PathBuf>,
+	/// Addon list to use instead of `addons.yml`
+	pub addons_file: Option<PathBuf>,
+	/// YAML or JSON file listing GZDoom builds
+	pub builds: Option<PathBuf>,
+	/// Glob pattern matching GZDoom builds
+	pub gzdoom_glob: Option<String>,
+	/// Glob pattern matching IWADs, to use instead of looking in the usual folders
+	pub iwad_glob: Option<String>,
+	pub verbose: bool,
+	/// Comma-separated addon names, or a `talauncher://` URL
+	pub select: Option<String>,
+	/// These are used when creating the window, so they can't be changed
+	/// while the launcher is running.
+	pub vsync: bool,
+	pub multisampling: u16,
+	/// Addon list to run the self-test with
+	pub self_test: Option<PathBuf>,
+	/// Comma-separated order of the GZDoom argument segments
+	pub arg_order: Option<String>,
+	/// Wine program for running Windows GZDoom builds
+	pub wine: Option<String>,
+	/// Print the JSON Schema of the addon list and exit
+	pub emit_schema: bool,
+	/// Print the addons and whether their files exist, and exit
+	pub list_addons: bool,
+	/// Allow downloading the addon list from its `manifest_url`
+	pub allow_network: bool,
+	/// Look for addon files ignoring case when they don't exist as written
+	pub ignore_case: bool,
+	/// Close the launcher once GZDoom has started
+	pub quit_on_launch: bool,
+	/// Check addon files against the checksums in the addon list
+	pub verify_checksums: bool,
+	/// Launch GZDoom without opening a window, and exit when it does
+	pub launch: bool,
+	/// GZDoom build and IWAD for `--launch`
+	pub gzdoom: Option<String>,
+	pub iwad: Option<String>,
+	/// Addons to load with `--launch`
+	pub primary: Option<String>,
+	pub secondary: Vec<String>,
+}
+
+impl LauncherArgs {
+	pub fn parse(mut args: impl Iterator<Item = String>) -> LauncherArgs {
+		let mut parsed = LauncherArgs::default();
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--addon-dir" => {
+					parsed.addon_dir = args.next().map(PathBuf::from);
+				},
+				"--addons-file" => {
+					parsed.addons_file = args.next().map(PathBuf::from);
+				},
+				"--builds" => {
+					parsed.builds = args.next().map(PathBuf::from);
+				},
+				"--gzdoom-glob" => {
+					parsed.gzdoom_glob = args.next();
+				},
+				"--iwad-glob" => {
+					parsed.iwad_glob = args.next();
+				},
+				"--verbose" => {
+					parsed.verbose = true;
+				},
+				"--select" => {
+					parsed.select = args.next();
+				},
+				"--self-test" => {
+					parsed.self_test = args.next().map(PathBuf::from);
+				},
+				"--arg-order" => {
+					parsed.arg_order = args.next();
+				},
+				"--ignore-case" => {
+					parsed.ignore_case = true;
+				},
+				"--allow-network" => {
+					parsed.allow_network = true;
+				},
+				"--quit-on-launch" => {
+					parsed.quit_on_launch = true;
+				},
+				"--verify-checksums" => {
+					parsed.verify_checksums = true;
+				},
+				"--emit-schema" => {
+					parsed.emit_schema = true;
+				},
+				"--list-addons" => {
+					parsed.list_addons = true;
+				},
+				"--launch" => {
+					parsed.launch = true;
+				},
+				"--gzdoom" => {
+					parsed.gzdoom = args.next();
+				},
+				"--iwad" => {
+					parsed.iwad = args.next();
+				},
+				"--primary" => {
+					parsed.primary = args.next();
+				},
+				"--secondary" => {
+					parsed.secondary.extend(args.next());
+				},
+				"--wine" => {
+					parsed.wine = args.next();
+				},
+				"--vsync" => {
+					parsed.vsync = true;
+				},
+				"--msaa" => {
+					let samples = args.next();
+					match samples.as_deref().map(str::parse) {
+						Some(Ok(samples)) => { parsed.multisampling = samples; },
+						_ => { eprintln!("--msaa needs a number of samples"); }
+					}
+				},
+				_ if arg.starts_with("talauncher://") => {
+					parsed.select = Some(arg);
+				},
+				_ => {
+					eprintln!("Unknown argument: {}", arg);
+				}
+			}
+		}
+		parsed
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(args: &[&str]) -> LauncherArgs {
+		LauncherArgs::parse(args.iter().map(|arg| String::from(*arg)))
+	}
+
+	#[test]
+	fn empty() {
+		let args = parse(&[]);
+		assert_eq!(args.addon_dir, None);
+		assert!(!args.verbose);
+		assert!(!args.vsync);
+		assert_eq!(args.multisampling, 0);
+	}
+
+	#[test]
+	fn rendering() {
+		let args = parse(&["--vsync", "--msaa", "4"]);
+		assert!(args.vsync);
+		assert_eq!(args.multisampling, 4);
+		let args = parse(&["--msaa", "lots"]);
+		assert_eq!(args.multisampling, 0);
+	}
+
+	#[test]
+	fn select() {
+		let args = parse(&["--select", "One,Two"]);
+		assert_eq!(args.select.as_deref(), Some("One,Two"));
+		let args = parse(&["talauncher://One,Two"]);
+		assert_eq!(args.select.as_deref(), Some("talauncher://One,Two"));
+	}
+
+	#[test]
+	fn addon_dir() {
+		let args = parse(&["--verbose", "--addon-dir", "/games/doom64"]);
+		assert_eq!(args.addon_dir, Some(PathBuf::from("/games/doom64")));
+		assert!(args.verbose);
+	}
+
+	#[test]
+	fn addons_file() {
+		let args = parse(&["--addons-file", "/games/doom64/sets/addons.yml"]);
+		assert_eq!(args.addons_file, Some(PathBuf::from("/games/doom64/sets/addons.yml")));
+		assert_eq!(parse(&[]).addons_file, None);
+	}
+
+	#[test]
+	fn gzdoom_glob() {
+		let args = parse(&["--gzdoom-glob", "/opt/gzdoom-*/gzdoom"]);
+		assert_eq!(args.gzdoom_glob.as_deref(), Some("/opt/gzdoom-*/gzdoom"));
+		assert_eq!(parse(&[]).gzdoom_glob, None);
+	}
+
+	#[test]
+	fn iwad_glob() {
+		let args = parse(&["--iwad-glob", "/games/doom/*.wad"]);
+		assert_eq!(args.iwad_glob.as_deref(), Some("/games/doom/*.wad"));
+		assert_eq!(parse(&[]).iwad_glob, None);
+	}
+
+	#[test]
+	fn quit_on_launch() {
+		assert!(parse(&["--quit-on-launch"]).quit_on_launch);
+		assert!(!parse(&[]).quit_on_launch);
+	}
+
+	#[test]
+	fn list_addons() {
+		let args = parse(&["--list-addons", "--addons-file", "sets/addons.yml"]);
+		assert!(args.list_addons);
+		assert_eq!(args.addons_file, Some(PathBuf::from("sets/addons.yml")));
+		assert!(!parse(&[]).list_addons);
+	}
+
+	#[test]
+	fn headless_launch() {
+		let args = parse(&[
+			"--launch", "--gzdoom", "/usr/bin/gzdoom", "--iwad", "DOOM64.WAD",
+			"--primary", "Campaign", "--secondary", "Filter", "--secondary", "Music",
+		]);
+		assert!(args.launch);
+		assert_eq!(args.gzdoom.as_deref(), Some("/usr/bin/gzdoom"));
+		assert_eq!(args.iwad.as_deref(), Some("DOOM64.WAD"));
+		assert_eq!(args.primary.as_deref(), Some("Campaign"));
+		assert_eq!(args.secondary, ["Filter", "Music"]);
+		assert!(!parse(&[]).launch);
+	}
+
+	#[test]
+	fn verify_checksums() {
+		assert!(parse(&["--verify-checksums"]).verify_checksums);
+		assert!(!parse(&[]).verify_checksums);
+	}
+}
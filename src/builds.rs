@@ -0,0 +1,184 @@
+use crate::checks::is_executable;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// A GZDoom build, with an optional name to show instead of its path
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Build {
+	pub path: String,
+	pub label: Option<String>,
+	/// The source port the build is, if it isn't GZDoom
+	#[serde(default)]
+	pub port: Option<SourcePort>,
+}
+
+/// A ZDoom-family source port. They all take the same arguments for the
+/// IWAD, files, configuration, and saved games, and are run the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SourcePort {
+	#[default]
+	GZDoom,
+	LZDoom,
+	ZDoom,
+	Zandronum,
+}
+
+impl SourcePort {
+	pub const ALL: [SourcePort; 4] = [
+		SourcePort::GZDoom,
+		SourcePort::LZDoom,
+		SourcePort::ZDoom,
+		SourcePort::Zandronum,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			SourcePort::GZDoom => "GZDoom",
+			SourcePort::LZDoom => "LZDoom",
+			SourcePort::ZDoom => "ZDoom",
+			SourcePort::Zandronum => "Zandronum",
+		}
+	}
+
+	/// Guess the source port from the name of its program
+	pub fn detect(path: &str) -> Option<SourcePort> {
+		let name = Path::new(path).file_stem()?.to_str()?.to_lowercase();
+		// "gzdoom" and "lzdoom" contain "zdoom", so they are checked first
+		[SourcePort::Zandronum, SourcePort::LZDoom, SourcePort::GZDoom, SourcePort::ZDoom]
+			.into_iter()
+			.find(|port| name.contains(&port.name().to_lowercase()))
+	}
+
+	/// Whether the port may have `-norun`. The others start the game when
+	/// asked for `--help` or given `-norun`, so the launch can't be checked.
+	pub fn may_have_norun(self) -> bool {
+		matches!(self, SourcePort::GZDoom | SourcePort::LZDoom)
+	}
+}
+
+impl Build {
+	pub fn label(&self) -> &str {
+		self.label.as_deref().unwrap_or(&self.path)
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BuildList {
+	builds: Vec<Build>,
+}
+
+fn parse_builds(contents: &str, json: bool) -> Result<Vec<Build>, String> {
+	let list: BuildList = match json {
+		true => serde_json::from_str(contents).map_err(|e| e.to_string())?,
+		false => serde_yaml::from_str(contents).map_err(|e| e.to_string())?,
+	};
+	Ok(list.builds)
+}
+
+/// Load GZDoom builds from a YAML or JSON file. Builds which can't be run are
+/// left out. Returns the builds, and warnings about anything left out.
+pub fn load_builds(fname: &Path) -> (Vec<Build>, Vec<String>) {
+	let json = fname.extension()
+		.map(|ext| ext.eq_ignore_ascii_case("json"))
+		.unwrap_or(false);
+	let builds = fs::read_to_string(fname)
+		.map_err(|e| e.to_string())
+		.and_then(|contents| parse_builds(&contents, json));
+	match builds {
+		Ok(builds) => {
+			let (builds, unusable): (Vec<Build>, Vec<Build>) = builds.into_iter()
+				.partition(|build| is_executable(&build.path));
+			let warnings = unusable.iter()
+				.map(|build| format!("GZDoom build {} is missing or not executable", build.path))
+				.collect();
+			(builds, warnings)
+		},
+		Err(e) => (vec![], vec![format!("Cannot load {}: {}", fname.display(), e)]),
+	}
+}
+
+/// Find GZDoom builds matching a glob pattern, like `/opt/gzdoom-*/gzdoom`.
+/// Files which can't be run are left out. Returns the builds, and a warning
+/// if the pattern is bad.
+pub fn glob_builds(pattern: &str) -> (Vec<Build>, Vec<String>) {
+	let paths = match glob::glob(pattern) {
+		Ok(paths) => paths,
+		Err(e) => return (vec![], vec![format!("Bad GZDoom build pattern {}: {}", pattern, e)]),
+	};
+	let builds = paths.flatten()
+		.filter(|path| path.is_file() && is_executable(path))
+		.map(|path| Build { path: path.to_string_lossy().into_owned(), ..Default::default() })
+		.collect();
+	(builds, vec![])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+
+	#[test]
+	fn yaml() {
+		let contents = "builds:\n  - path: /opt/gzdoom/gzdoom\n    label: GZDoom 4.11 (testing)\n  - path: /usr/bin/gzdoom\n";
+		let builds = parse_builds(contents, false).unwrap();
+		assert_eq!(builds.len(), 2);
+		assert_eq!(builds[0].label(), "GZDoom 4.11 (testing)");
+		assert_eq!(builds[1].label(), "/usr/bin/gzdoom");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn glob() {
+		use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+		let dir = TestDir::new("glob-builds");
+		["gzdoom-4.10", "gzdoom-4.11", "gzdoom-notes"].iter().for_each(|version| {
+			fs::create_dir_all(dir.join(version)).unwrap();
+		});
+		let executable = fs::Permissions::from_mode(0o755);
+		fs::write(dir.join("gzdoom-4.10").join("gzdoom"), "").unwrap();
+		fs::set_permissions(dir.join("gzdoom-4.10").join("gzdoom"), executable.clone()).unwrap();
+		fs::write(dir.join("gzdoom-4.11").join("gzdoom"), "").unwrap();
+		fs::set_permissions(dir.join("gzdoom-4.11").join("gzdoom"), executable).unwrap();
+		fs::write(dir.join("gzdoom-notes").join("gzdoom"), "").unwrap();
+
+		let pattern = dir.join("gzdoom-*").join("gzdoom");
+		let (builds, warnings) = glob_builds(&pattern.to_string_lossy());
+		let (bad, bad_warnings) = glob_builds("[");
+
+		let paths: Vec<PathBuf> = builds.iter().map(|build| PathBuf::from(&build.path)).collect();
+		assert_eq!(paths, [
+			dir.join("gzdoom-4.10").join("gzdoom"),
+			dir.join("gzdoom-4.11").join("gzdoom"),
+		]);
+		assert!(warnings.is_empty());
+		assert!(bad.is_empty());
+		assert_eq!(bad_warnings.len(), 1);
+	}
+
+	#[test]
+	fn json() {
+		let contents = r#"{"builds": [{"path": "C:\\GZDoom\\gzdoom.exe", "label": "Stable"}]}"#;
+		let builds = parse_builds(contents, true).unwrap();
+		assert_eq!(builds, vec![Build {
+			path: String::from("C:\\GZDoom\\gzdoom.exe"),
+			label: Some(String::from("Stable")),
+			port: None,
+		}]);
+	}
+
+	#[test]
+	fn source_ports() {
+		let contents = "builds:\n  - path: /opt/zan/zandronum\n    port: Zandronum\n  - path: /usr/bin/gzdoom\n";
+		let builds = parse_builds(contents, false).unwrap();
+		assert_eq!(builds[0].port, Some(SourcePort::Zandronum));
+		assert_eq!(builds[1].port, None);
+
+		assert_eq!(SourcePort::detect("/usr/bin/gzdoom"), Some(SourcePort::GZDoom));
+		assert_eq!(SourcePort::detect("C:\\LZDoom\\lzdoom.exe"), Some(SourcePort::LZDoom));
+		assert_eq!(SourcePort::detect("/opt/zdoom/zdoom"), Some(SourcePort::ZDoom));
+		assert_eq!(SourcePort::detect("/opt/zan/Zandronum"), Some(SourcePort::Zandronum));
+		assert_eq!(SourcePort::detect("/usr/bin/flatpak"), None);
+		assert!(SourcePort::GZDoom.may_have_norun());
+		assert!(!SourcePort::Zandronum.may_have_norun());
+	}
+}
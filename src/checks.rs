@@ -1,22 +1,25 @@
 use std::{
-	path::Path,
+	collections::{HashMap, HashSet},
+	path::{Component, Path, PathBuf},
 	fs::{self, File},
-	io::Read, ffi::OsString
+	io::{self, Read, Seek, SeekFrom},
+	sync::{Mutex, OnceLock},
+	time::SystemTime,
 };
 #[cfg(not(target_family = "windows"))]
 use std::os::unix::fs::PermissionsExt;
 
 const S_IXOTH: u32 = 0o1;
 const S_IXUSR: u32 = 0o100;
-// const S_IXGRP: u32 = 0o10;
+const S_IXGRP: u32 = 0o10;
 #[cfg(not(target_family = "windows"))]
 pub fn is_executable(path: &impl AsRef<Path>) -> bool {
-	// Linux/Unix uses a file permission bit
+	// Linux/Unix uses a file permission bit, for the user, group, or others
 	let metadata = fs::metadata(path);
 	match metadata {
 		Ok(m) => {
 			let mode = m.permissions().mode();
-			(mode & (S_IXOTH | S_IXUSR)) != 0
+			(mode & (S_IXOTH | S_IXGRP | S_IXUSR)) != 0
 		}
 		Err(_) => false
 	}
@@ -24,31 +27,510 @@ pub fn is_executable(path: &impl AsRef<Path>) -> bool {
 
 #[cfg(target_family = "windows")]
 pub fn is_executable(path: &impl AsRef<Path>) -> bool {
-	// Windows executables have certain extensions
-	let executable_extns = ["exe", "bat"];
-	match path.extension() {
-		Some(ext) => {executable_extns.iter().any(
-			|extn| ext.eq_ignore_ascii_case(extn))},
+	// Windows executables have the extensions listed in PATHEXT
+	let pathext = std::env::var("PATHEXT").ok()
+		.filter(|pathext| !pathext.trim().is_empty());
+	match path.as_ref().extension() {
+		Some(ext) => in_pathext(ext, pathext.as_deref().unwrap_or(DEFAULT_PATHEXT)),
 		None => false
 	}
 }
 
+/// The extensions of Windows programs, if PATHEXT isn't set
+#[cfg(any(target_family = "windows", test))]
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// Whether the extension is in `pathext`, a list like `.COM;.EXE`
+#[cfg(any(target_family = "windows", test))]
+fn in_pathext(ext: &std::ffi::OsStr, pathext: &str) -> bool {
+	pathext.split(';')
+		.map(|extn| extn.trim().trim_start_matches('.'))
+		.filter(|extn| !extn.is_empty())
+		.any(|extn| ext.eq_ignore_ascii_case(extn))
+}
+
+/// Where a symlink points, if it points to something which doesn't exist
+pub fn dangling_symlink(path: &impl AsRef<Path>) -> Option<PathBuf> {
+	let path = path.as_ref();
+	let is_link = fs::symlink_metadata(path).map_or(false, |m| m.file_type().is_symlink());
+	match is_link && fs::metadata(path).is_err() {
+		true => fs::read_link(path).ok(),
+		false => None,
+	}
+}
+
+/// Whether the file looks like a Windows program, which could be run with
+/// Wine on other systems
+pub fn is_windows_exe(path: &impl AsRef<Path>) -> bool {
+	path.as_ref().extension()
+		.map(|ext| ext.eq_ignore_ascii_case("exe"))
+		.unwrap_or(false)
+}
+
+/// Find `relative` under `base`, ignoring the case of each part of the path
+/// which doesn't exist as written. Addon lists written on Windows or macOS
+/// often get the case of file names wrong.
+pub fn find_ignoring_case(base: &Path, relative: &Path) -> Option<PathBuf> {
+	let mut found = base.to_path_buf();
+	for component in relative.components() {
+		let name = match component {
+			Component::Normal(name) => name,
+			other => {
+				found.push(other);
+				continue;
+			}
+		};
+		if found.join(name).exists() {
+			found.push(name);
+			continue;
+		}
+		let lowercase = name.to_str()?.to_lowercase();
+		let dir = match found.as_os_str().is_empty() {
+			true => Path::new("."),
+			false => found.as_path(),
+		};
+		let entry = fs::read_dir(dir).ok()?.flatten().find(|entry| {
+			entry.file_name().to_str()
+				.map(|entry_name| entry_name.to_lowercase() == lowercase)
+				.unwrap_or(false)
+		})?;
+		found.push(entry.file_name());
+	}
+	Some(found)
+}
+
+/// The most lumps a WAD directory is expected to have, so that a corrupt
+/// header doesn't make us read a huge directory
+const MAX_LUMPS: usize = 1 << 20;
+/// Lumps which only the Doom 64 IWAD has: the legal screen and demos of the
+/// original Nintendo 64 release
+const DOOM64_LUMPS: [&str; 2] = ["USLEGAL", "DEMO1LMP"];
+
+/// Which game an IWAD is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IwadKind {
+	Doom,
+	UltimateDoom,
+	Doom2,
+	Heretic,
+	Hexen,
+	Strife,
+	FreedoomPhase1,
+	FreedoomPhase2,
+	Doom64,
+	/// An IWAD which isn't one of the above, or a zipped IWAD
+	Unknown,
+}
+
+impl IwadKind {
+	pub fn name(&self) -> &'static str {
+		match self {
+			IwadKind::Doom => "Doom",
+			IwadKind::UltimateDoom => "The Ultimate Doom",
+			IwadKind::Doom2 => "Doom II",
+			IwadKind::Heretic => "Heretic",
+			IwadKind::Hexen => "Hexen",
+			IwadKind::Strife => "Strife",
+			IwadKind::FreedoomPhase1 => "Freedoom: Phase 1",
+			IwadKind::FreedoomPhase2 => "Freedoom: Phase 2",
+			IwadKind::Doom64 => "Doom 64",
+			IwadKind::Unknown => "Unknown game",
+		}
+	}
+
+	/// Tell the game from the lumps in the IWAD, like GZDoom does
+	fn from_lumps(lumps: &HashSet<String>) -> IwadKind {
+		let has = |names: &[&str]| names.iter().all(|name| lumps.contains(*name));
+		if has(&["ENDSTRF"]) {
+			IwadKind::Strife
+		} else if has(&["TITLE", "MAP01", "WINNOWR"]) {
+			IwadKind::Hexen
+		} else if has(&["E1M1", "E2M1", "TITLE", "MUS_E1M1"]) {
+			IwadKind::Heretic
+		} else if has(&["FREEDOOM", "MAP01"]) {
+			IwadKind::FreedoomPhase2
+		} else if has(&["FREEDOOM", "E1M1"]) {
+			IwadKind::FreedoomPhase1
+		} else if DOOM64_LUMPS.iter().any(|name| lumps.contains(*name)) {
+			// Doom 64 has MAP01 too
+			IwadKind::Doom64
+		} else if has(&["MAP01"]) {
+			IwadKind::Doom2
+		} else if has(&["E4M1"]) {
+			IwadKind::UltimateDoom
+		} else if has(&["E1M1"]) {
+			IwadKind::Doom
+		} else {
+			IwadKind::Unknown
+		}
+	}
+}
+
+/// Which game the IWAD is for, if it is an IWAD. The game of a zipped IWAD
+/// is not looked for.
+pub fn iwad_game(path: &impl AsRef<Path>) -> Option<IwadKind> {
+	let path = path.as_ref();
+	if has_zip_extension(&path) {
+		return is_iwad(&path).then_some(IwadKind::Unknown);
+	}
+	let lumps = lump_names(&mut File::open(path).ok()?).ok()?;
+	Some(IwadKind::from_lumps(&lumps))
+}
+
+/// The names of the lumps in an IWAD
+fn lump_names(wad: &mut (impl Read + Seek)) -> io::Result<HashSet<String>> {
+	let mut header = [0u8; 12];
+	wad.read_exact(&mut header)?;
+	if &header[..4] != b"IWAD" {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an IWAD"));
+	}
+	let number = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+	let count = number(&header[4..8]) as usize;
+	if count > MAX_LUMPS {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Too many lumps"));
+	}
+	wad.seek(SeekFrom::Start(number(&header[8..12]) as u64))?;
+	// Each entry is the lump's offset, its size, and its name
+	let mut directory = vec![0u8; count * 16];
+	wad.read_exact(&mut directory)?;
+	Ok(directory.chunks_exact(16).map(|entry| {
+		let name = &entry[8..16];
+		let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+		String::from_utf8_lossy(&name[..end]).to_uppercase()
+	}).collect())
+}
+
+/// Results of checking files, by path, with the time each file was modified
+/// when it was checked
+type CheckCache<T> = OnceLock<Mutex<HashMap<PathBuf, (SystemTime, T)>>>;
+
+static MAGIC_CACHE: CheckCache<Option<[u8; 4]>> = OnceLock::new();
+static IWAD_CACHE: CheckCache<bool> = OnceLock::new();
+
+/// Check a file, or use the result from the last time it was checked if it
+/// hasn't been modified since. Files on a network share can be slow to read.
+fn cached<T: Copy>(cache: &CheckCache<T>, path: &Path, check: impl FnOnce(&Path) -> T) -> T {
+	let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+		Ok(modified) => modified,
+		Err(_) => return check(path),
+	};
+	let cache = cache.get_or_init(Default::default);
+	let found = cache.lock().ok()
+		.and_then(|cache| cache.get(path).copied())
+		.filter(|(checked, _)| *checked == modified);
+	if let Some((_, result)) = found {
+		return result;
+	}
+	let result = check(path);
+	if let Ok(mut cache) = cache.lock() {
+		cache.insert(path.to_path_buf(), (modified, result));
+	}
+	result
+}
+
+/// The first 4 bytes of a file, which say what kind of WAD it is
+fn wad_magic(path: &impl AsRef<Path>) -> Option<[u8; 4]> {
+	cached(&MAGIC_CACHE, path.as_ref(), |path| {
+		let mut magic: [u8; 4] = [0; 4];
+		match File::open(path) {
+			Ok(mut f) => f.read_exact(&mut magic).ok().map(|_| magic),
+			Err(e) => {
+				eprintln!("{:?}", e);
+				None
+			},
+		}
+	})
+}
+
+/// The magic of a zip file, which is the header of its first entry
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+
+/// The most bytes which can come after the end of a zip's central directory
+const MAX_ZIP_COMMENT: u64 = 0xFFFF;
+
 pub fn is_iwad(path: &impl AsRef<Path>) -> bool {
-	let iwad = b"IWAD";
-	let mut magic: [u8; 4] = [0; 4];
-	let ipk3 = OsString::from("ipk3");
-	if path.as_ref().extension() == Some(&ipk3) {
-		return true;
-	}
-	match File::open(path) {
-		Ok(mut f) => {
-			let ok = f.read_exact(&mut magic).is_ok();
-			if !ok { return false; }
-			&magic == iwad
-		},
-		Err(e) => {
-			eprintln!("{:?}", e);
-			false
-		},
+	cached(&IWAD_CACHE, path.as_ref(), check_iwad)
+}
+
+fn check_iwad(path: &Path) -> bool {
+	if !has_zip_extension(&path) {
+		return wad_magic(&path) == Some(*b"IWAD");
+	}
+	if !is_zip(&path) {
+		return false;
+	}
+	// A `.pk3` is usually an addon, so it must say that it's an IWAD
+	let ipk3 = path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("ipk3"));
+	ipk3 || File::open(path).and_then(|mut f| zip_names(&mut f))
+		.map(|names| names.iter().any(|name| is_iwadinfo(name)))
+		.unwrap_or(false)
+}
+
+/// Whether the file is named like a zipped IWAD, which is an `.ipk3` or a
+/// `.pk3`
+pub fn has_zip_extension(path: &impl AsRef<Path>) -> bool {
+	path.as_ref().extension().map_or(false, |ext| {
+		ext.eq_ignore_ascii_case("ipk3") || ext.eq_ignore_ascii_case("pk3")
+	})
+}
+
+/// Whether the file starts like a zip file
+pub fn is_zip(path: &impl AsRef<Path>) -> bool {
+	wad_magic(path) == Some(ZIP_MAGIC)
+}
+
+/// Whether a zip entry is the `IWADINFO` or `GAMEINFO` lump at the top of
+/// the zip, which GZDoom reads from IWADs
+fn is_iwadinfo(name: &str) -> bool {
+	let stem = name.split('.').next().unwrap_or(name);
+	!name.contains('/') &&
+		(stem.eq_ignore_ascii_case("iwadinfo") || stem.eq_ignore_ascii_case("gameinfo"))
+}
+
+/// The names of the files in a zip, read from its central directory
+fn zip_names(zip: &mut (impl Read + Seek)) -> io::Result<Vec<String>> {
+	let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+	let short = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+	let number = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+	// The end of the central directory is 22 bytes, followed by a comment
+	let len = zip.seek(SeekFrom::End(0))?;
+	let tail_len = len.min(22 + MAX_ZIP_COMMENT);
+	zip.seek(SeekFrom::Start(len - tail_len))?;
+	let mut tail = vec![0u8; tail_len as usize];
+	zip.read_exact(&mut tail)?;
+	let end = (0..tail.len().saturating_sub(21)).rev()
+		.find(|&at| &tail[at..at + 4] == b"PK\x05\x06")
+		.map(|at| &tail[at..at + 22])
+		.ok_or_else(|| invalid("No zip central directory"))?;
+	let count = short(&end[10..12]);
+	let (size, offset) = (number(&end[12..16]) as u64, number(&end[16..20]) as u64);
+	// A corrupt header mustn't make us read past the end of the file
+	if offset + size > len {
+		return Err(invalid("Zip central directory is past the end of the file"));
+	}
+	let mut directory = vec![0u8; size as usize];
+	zip.seek(SeekFrom::Start(offset))?;
+	zip.read_exact(&mut directory)?;
+	// Each entry is 46 bytes, followed by its name, extra field, and comment
+	let mut names = Vec::with_capacity(count);
+	let mut at = 0;
+	for _ in 0..count {
+		let entry = directory.get(at..at + 46)
+			.filter(|entry| &entry[..4] == b"PK\x01\x02")
+			.ok_or_else(|| invalid("Bad zip central directory"))?;
+		let name_len = short(&entry[28..30]);
+		let next = at + 46 + name_len + short(&entry[30..32]) + short(&entry[32..34]);
+		let name = directory.get(at + 46..at + 46 + name_len)
+			.ok_or_else(|| invalid("Bad zip central directory"))?;
+		names.push(String::from_utf8_lossy(name).into_owned());
+		at = next;
+	}
+	Ok(names)
+}
+
+/// Whether the file is a PWAD, which is loaded as an addon rather than as
+/// the IWAD
+pub fn is_pwad(path: &impl AsRef<Path>) -> bool {
+	wad_magic(path) == Some(*b"PWAD")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+	use std::io::Cursor;
+
+	/// A WAD with empty lumps of the given names
+	fn wad(magic: &[u8; 4], names: &[&str]) -> Vec<u8> {
+		let mut wad = magic.to_vec();
+		wad.extend((names.len() as u32).to_le_bytes());
+		wad.extend(12u32.to_le_bytes());
+		names.iter().for_each(|name| {
+			wad.extend(12u32.to_le_bytes());
+			wad.extend(0u32.to_le_bytes());
+			let mut padded = [0u8; 8];
+			padded[..name.len()].copy_from_slice(name.as_bytes());
+			wad.extend(padded);
+		});
+		wad
+	}
+
+	fn game(names: &[&str]) -> IwadKind {
+		IwadKind::from_lumps(&lump_names(&mut Cursor::new(wad(b"IWAD", names))).unwrap())
+	}
+
+	#[test]
+	fn iwad_games() {
+		assert_eq!(game(&["PLAYPAL", "E1M1", "THINGS"]), IwadKind::Doom);
+		assert_eq!(game(&["E1M1", "E4M1"]), IwadKind::UltimateDoom);
+		assert_eq!(game(&["MAP01", "MAP32"]), IwadKind::Doom2);
+		assert_eq!(game(&["E1M1", "E2M1", "TITLE", "MUS_E1M1"]), IwadKind::Heretic);
+		assert_eq!(game(&["MAP01", "TITLE", "WINNOWR"]), IwadKind::Hexen);
+		assert_eq!(game(&["MAP01", "ENDSTRF"]), IwadKind::Strife);
+		assert_eq!(game(&["FREEDOOM", "MAP01"]), IwadKind::FreedoomPhase2);
+		assert_eq!(game(&["MAP01", "USLEGAL", "DEMO1LMP"]), IwadKind::Doom64);
+		assert_eq!(game(&["MAP01", "DEMO1LMP"]), IwadKind::Doom64);
+		assert_eq!(game(&["freedoom", "e1m1"]), IwadKind::FreedoomPhase1);
+		assert_eq!(game(&["PLAYPAL"]), IwadKind::Unknown);
+		assert_eq!(game(&[]), IwadKind::Unknown);
+
+		assert!(lump_names(&mut Cursor::new(wad(b"PWAD", &["MAP01"]))).is_err());
+		// The directory is cut off
+		let mut truncated = wad(b"IWAD", &["MAP01", "MAP02"]);
+		truncated.truncate(30);
+		assert!(lump_names(&mut Cursor::new(truncated)).is_err());
+	}
+
+	/// A zip with empty, uncompressed files of the given names
+	fn zip(names: &[&str]) -> Vec<u8> {
+		let mut zip = Vec::new();
+		let mut directory = Vec::new();
+		names.iter().for_each(|name| {
+			let offset = zip.len() as u32;
+			zip.extend(ZIP_MAGIC);
+			zip.extend([0u8; 22]);
+			zip.extend((name.len() as u16).to_le_bytes());
+			zip.extend(0u16.to_le_bytes());
+			zip.extend(name.as_bytes());
+			directory.extend(b"PK\x01\x02");
+			directory.extend([0u8; 24]);
+			directory.extend((name.len() as u16).to_le_bytes());
+			directory.extend([0u8; 12]);
+			directory.extend(offset.to_le_bytes());
+			directory.extend(name.as_bytes());
+		});
+		let offset = zip.len() as u32;
+		zip.extend(&directory);
+		zip.extend(b"PK\x05\x06");
+		zip.extend([0u8; 4]);
+		zip.extend((names.len() as u16).to_le_bytes());
+		zip.extend((names.len() as u16).to_le_bytes());
+		zip.extend((directory.len() as u32).to_le_bytes());
+		zip.extend(offset.to_le_bytes());
+		zip.extend(0u16.to_le_bytes());
+		zip
+	}
+
+	#[test]
+	fn zipped_iwads() {
+		let names = ["iwadinfo.txt", "maps/MAP01.wad", "mapinfo/gameinfo"];
+		assert_eq!(zip_names(&mut Cursor::new(zip(&names))).unwrap(), names);
+		let mut truncated = zip(&names);
+		truncated.truncate(40);
+		assert!(zip_names(&mut Cursor::new(truncated)).is_err());
+		// A directory size bigger than the file isn't believed
+		let mut huge = zip(&names);
+		let size_at = huge.len() - 10;
+		huge[size_at..size_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+		assert_eq!(zip_names(&mut Cursor::new(huge)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert!(is_iwadinfo("IWADINFO"));
+		assert!(is_iwadinfo("GameInfo.txt"));
+		assert!(!is_iwadinfo("mapinfo/gameinfo"));
+
+		let dir = TestDir::new("zipped-iwads");
+		let file = |name: &str| dir.join(name);
+		fs::write(file("game.ipk3"), zip(&["maps/MAP01.wad"])).unwrap();
+		fs::write(file("game.pk3"), zip(&["iwadinfo.txt", "maps/MAP01.wad"])).unwrap();
+		fs::write(file("addon.pk3"), zip(&["mapinfo.txt", "maps/MAP01.wad"])).unwrap();
+		fs::write(file("notzip.ipk3"), b"Not a zip file").unwrap();
+		let iwads = ["game.ipk3", "game.pk3", "addon.pk3", "notzip.ipk3"]
+			.map(|name| (is_iwad(&file(name)), is_zip(&file(name))));
+		let notzip_game = iwad_game(&file("notzip.ipk3"));
+
+		assert_eq!(iwads, [(true, true), (true, true), (false, true), (false, false)]);
+		assert_eq!(notzip_game, None);
+	}
+
+	#[cfg(not(target_family = "windows"))]
+	#[test]
+	fn executables() {
+		let dir = TestDir::new("executables");
+		let modes = [0o644, 0o744, 0o654, 0o645, 0o755];
+		let executable = modes.map(|mode| {
+			let file = dir.join(format!("gzdoom-{:o}", mode));
+			File::create(&file).unwrap();
+			fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+			is_executable(&file)
+		});
+		let missing = is_executable(&dir.join("missing"));
+
+		assert_eq!(executable, [false, true, true, true, true]);
+		assert!(!missing);
+	}
+
+	#[cfg(not(target_family = "windows"))]
+	#[test]
+	fn dangling_symlinks() {
+		let dir = TestDir::new("symlinks");
+		let target = dir.join("gzdoom-real");
+		File::create(&target).unwrap();
+		std::os::unix::fs::symlink(&target, dir.join("gzdoom")).unwrap();
+		std::os::unix::fs::symlink(dir.join("gone"), dir.join("gzdoom-old")).unwrap();
+		let links = ["gzdoom", "gzdoom-old", "gzdoom-real", "missing"]
+			.map(|name| dangling_symlink(&dir.join(name)));
+
+		assert_eq!(links, [None, Some(dir.join("gone")), None, None]);
+	}
+
+	#[test]
+	fn pathext() {
+		let ext = |ext: &str| std::ffi::OsString::from(ext);
+		assert!(in_pathext(&ext("exe"), DEFAULT_PATHEXT));
+		assert!(in_pathext(&ext("Cmd"), DEFAULT_PATHEXT));
+		assert!(!in_pathext(&ext("wad"), DEFAULT_PATHEXT));
+		assert!(in_pathext(&ext("py"), ".COM;.EXE; .PY;"));
+		assert!(in_pathext(&ext("ps1"), "ps1"));
+		assert!(!in_pathext(&ext("bat"), ".COM;.EXE;.PY"));
+		assert!(!in_pathext(&ext(""), ";;"));
+	}
+
+	#[test]
+	fn cached_checks() {
+		let dir = TestDir::new("cached-checks");
+		let path = dir.join("DOOM64.WAD");
+		let set_modified = |seconds: u64| {
+			let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+			File::options().write(true).open(&path).unwrap().set_modified(time).unwrap();
+		};
+		fs::write(&path, wad(b"IWAD", &["MAP01"])).unwrap();
+		set_modified(1_000_000);
+		let first = is_iwad(&path);
+		// The file isn't read again while it has the same modification time
+		fs::write(&path, wad(b"PWAD", &["MAP01"])).unwrap();
+		set_modified(1_000_000);
+		let unchanged = is_iwad(&path);
+		set_modified(2_000_000);
+		let modified = (is_iwad(&path), is_pwad(&path));
+
+		assert!(first);
+		assert!(unchanged);
+		assert_eq!(modified, (false, true));
+	}
+
+	#[test]
+	fn wad_kinds() {
+		let dir = TestDir::new("wad-kinds");
+		fs::write(dir.join("DOOM64.WAD"), wad(b"IWAD", &["MAP01"])).unwrap();
+		fs::write(dir.join("maps.wad"), wad(b"PWAD", &["MAP01"])).unwrap();
+		fs::write(dir.join("short.wad"), b"PW").unwrap();
+		let iwad = dir.join("DOOM64.WAD");
+		let pwad = dir.join("maps.wad");
+		let short = dir.join("short.wad");
+		let kinds = [&iwad, &pwad, &short].map(|path| (is_iwad(path), is_pwad(path)));
+
+		assert_eq!(kinds, [(true, false), (false, true), (false, false)]);
+	}
+
+	#[test]
+	fn iwad_game_files() {
+		let dir = TestDir::new("iwad-game");
+		fs::write(dir.join("DOOM2.WAD"), wad(b"IWAD", &["MAP01"])).unwrap();
+		fs::write(dir.join("mod.wad"), wad(b"PWAD", &["MAP01"])).unwrap();
+		let doom2 = iwad_game(&dir.join("DOOM2.WAD"));
+		let pwad = iwad_game(&dir.join("mod.wad"));
+
+		assert_eq!(doom2, Some(IwadKind::Doom2));
+		assert_eq!(pwad, None);
+		assert_eq!(iwad_game(&dir.join("missing.wad")), None);
 	}
 }
@@ -1,11 +1,14 @@
 use std::borrow::Cow;
 
+/// Splits a command line into arguments. If a quote isn't closed, the rest of
+/// the command line is one argument; use `unclosed_quote` to check for that.
 #[derive(Debug, Clone, Default)]
 pub struct CommandLineParser<'a> {
 	text: &'a str,
 	pos: usize,
 	escape: bool,
 	in_quotes: bool,
+	in_single_quotes: bool,
 }
 
 impl<'a> Iterator for CommandLineParser<'a> {
@@ -13,19 +16,24 @@ impl<'a> Iterator for CommandLineParser<'a> {
 
 	fn next(&mut self) -> Option<Self::Item> {
 		let start = self.pos;
-		let start = start + self.text.bytes().skip(start).position(|ch| {
-			!ch.is_ascii_whitespace()
-		})?;
-		let end = self.text.bytes().skip(start).position(|ch: u8| {
-			if ch == b'\\' && !self.escape {
+		let start = start + self.text[start..].char_indices().find(|(_, ch)| {
+			!ch.is_whitespace()
+		})?.0;
+		let end = self.text[start..].char_indices().find(|&(_, ch)| {
+			// Nothing is escaped in single quotes
+			if self.in_single_quotes {
+				if ch == '\'' { self.in_single_quotes = false; }
+			} else if ch == '\\' && !self.escape {
 				self.escape = true;
-			} else if ch == b'"' {
+			} else if ch == '"' && !self.escape {
 				self.in_quotes = !self.in_quotes;
+			} else if ch == '\'' && !self.in_quotes && !self.escape {
+				self.in_single_quotes = true;
 			} else {
 				self.escape = false;
 			}
-			!self.escape && !self.in_quotes && ch.is_ascii_whitespace()
-		}).unwrap_or(self.text.len().saturating_sub(start)) + start;
+			!self.escape && !self.in_quotes && !self.in_single_quotes && ch.is_whitespace()
+		}).map_or(self.text.len(), |(end, _)| start + end);
 		self.pos = end;
 		Some(&self.text[start..end])
 	}
@@ -38,8 +46,24 @@ pub fn parse_cmdline<'a>(text: &'a str) -> CommandLineParser<'a> {
 	}
 }
 
+/// The quote character which is still open at the end of the command line,
+/// if there is one
+pub fn unclosed_quote(text: &str) -> Option<char> {
+	let mut parser = parse_cmdline(text);
+	parser.by_ref().for_each(drop);
+	if parser.in_quotes {
+		Some('"')
+	} else if parser.in_single_quotes {
+		Some('\'')
+	} else {
+		None
+	}
+}
+
 pub fn dequote<'a>(text: &'a str) -> Cow<'a, str> {
-	if text.starts_with('"') && text.ends_with('"') {
+	if let Some(text) = single_quoted(text) {
+		Cow::from(text)
+	} else if text.starts_with('"') && text.ends_with('"') {
 		let text = Cow::from(text.trim_matches('"'));
 		if text.contains('\\') {
 			let mut eschar = false;
@@ -56,6 +80,11 @@ pub fn dequote<'a>(text: &'a str) -> Cow<'a, str> {
 	}
 }
 
+/// The text inside single quotes, which is used as it is
+pub fn single_quoted(text: &str) -> Option<&str> {
+	text.strip_prefix('\'')?.strip_suffix('\'')
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -89,4 +118,65 @@ mod tests {
 			assert_eq!(actual, expected);
 		});
 	}
+
+	#[test]
+	fn mixed_single() {
+		let cmdline = "A='Has spaces' B=nospaces Cnoeq D='no \\escaped \\' E F G=\"it's\" H";
+		let expected = ["A='Has spaces'", "B=nospaces", "Cnoeq", "D='no \\escaped \\'", "E", "F", "G=\"it's\"", "H"];
+		let parser = parse_cmdline(cmdline);
+
+		assert_eq!(parser.clone().count(), expected.len());
+		parser.zip(expected.into_iter()).for_each(|(actual, expected)| {
+			assert_eq!(actual, expected);
+		});
+	}
+
+	#[test]
+	fn dequoted_single() {
+		let cmdline = "A='Has spaces' B=nospaces Cnoeq D='no \\escaped \\' E F G=\"it's\" H='unclosed";
+		let expected = [
+			Some(("A", Cow::from("Has spaces"))),
+			Some(("B", Cow::from("nospaces"))),
+			None,
+			Some(("D", Cow::from("no \\escaped \\"))),
+			None, None,
+			Some(("G", Cow::from("it's"))),
+			Some(("H", Cow::from("'unclosed"))),
+		];
+		let parser = parse_cmdline(cmdline).map(|th| {
+			let g = th.split_once('=')?;
+			Some((g.0, dequote(g.1)))
+		});
+
+		assert_eq!(parser.clone().count(), expected.len());
+		parser.zip(expected.into_iter()).for_each(|(actual, expected)| {
+			assert_eq!(actual, expected);
+		});
+	}
+
+	#[test]
+	fn unclosed_quotes() {
+		let args: Vec<&str> = parse_cmdline("a \"b c").collect();
+		assert_eq!(args, ["a", "\"b c"]);
+		assert_eq!(unclosed_quote("a \"b c"), Some('"'));
+		assert_eq!(unclosed_quote("a 'b c"), Some('\''));
+		assert_eq!(unclosed_quote("a \"b c\" 'd'"), None);
+		assert_eq!(unclosed_quote("a \"it's\""), None);
+		// An escaped quote neither opens nor closes quotes
+		let args: Vec<&str> = parse_cmdline("A=\"x\\\"y\" %command%").collect();
+		assert_eq!(args, ["A=\"x\\\"y\"", "%command%"]);
+		assert_eq!(unclosed_quote("A=\"x\\\"y\" %command%"), None);
+		let args: Vec<&str> = parse_cmdline("-file \"a\\\"b.wad\"").collect();
+		assert_eq!(args, ["-file", "\"a\\\"b.wad\""]);
+		assert_eq!(unclosed_quote("-file \"a\\\"b.wad\""), None);
+		assert_eq!(unclosed_quote("a \\\"b"), None);
+		assert_eq!(unclosed_quote(""), None);
+	}
+
+	#[test]
+	fn unicode_whitespace() {
+		let args: Vec<&str> = parse_cmdline("-file\u{a0}café.wad\u{3000}\"a\u{a0}b\"").collect();
+		assert_eq!(args, ["-file", "café.wad", "\"a\u{a0}b\""]);
+		assert_eq!(parse_cmdline("\u{a0}\u{2003}").count(), 0);
+	}
 }
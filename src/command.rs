@@ -1,14 +1,152 @@
 use crate::cmdlineparse;
-use std::borrow::Cow;
+use std::{borrow::Cow, env, ffi::OsStr, process::Command};
 
+/// How to run a program, from launch options like `VAR=value wrapper
+/// %command% -arg`. Make one with `get_run_info`.
 #[derive(Debug, Clone, Default)]
 pub struct RunInfo<'a> {
-	pub environment: Vec<(&'a str, Cow<'a, str>)>,
-	pub new_executable: Option<&'a str>,
-	pub arguments: Vec<&'a str>
+	environment: Vec<(&'a str, Cow<'a, str>)>,
+	new_executable: Option<Cow<'a, str>>,
+	arguments: Vec<Cow<'a, str>>
 }
 
+impl<'a> RunInfo<'a> {
+	/// Environment variables to set, in order. `$VAR` is expanded in their
+	/// values, unless they are in single quotes.
+	pub fn environment(&self) -> &[(&'a str, Cow<'a, str>)] {
+		&self.environment
+	}
+
+	/// The program which runs the original executable, like `mangohud`, if
+	/// there is one before `%command%`
+	pub fn new_executable(&self) -> Option<&str> {
+		self.new_executable.as_deref()
+	}
+
+	/// Arguments for the program. When it is run through another program,
+	/// the original executable is one of them.
+	pub fn arguments(&self) -> &[Cow<'a, str>] {
+		&self.arguments
+	}
+
+	/// The program to run: the new executable, or the original one
+	pub fn program<'b>(&'b self, orig_exe: &'b str) -> &'b str {
+		self.new_executable().unwrap_or(orig_exe)
+	}
+
+	/// A command which runs the program with the arguments and environment
+	pub fn into_command(self, orig_exe: &str) -> Command {
+		new_command(
+			self.program(orig_exe),
+			self.arguments.iter().map(|arg| &**arg),
+			self.environment.iter().map(|(key, value)| (*key, &**value)),
+		)
+	}
+}
+
+/// A command which runs `program` with the arguments, and with the
+/// environment variables set in order. The launcher builds its commands with
+/// this too, so launch options are run the same way everywhere.
+pub fn new_command<A, K, V>(
+	program: impl AsRef<OsStr>,
+	arguments: impl IntoIterator<Item = A>,
+	environment: impl IntoIterator<Item = (K, V)>,
+) -> Command
+where
+	A: AsRef<OsStr>,
+	K: AsRef<OsStr>,
+	V: AsRef<OsStr>,
+{
+	let mut command = Command::new(program);
+	command.envs(environment).args(arguments);
+	command
+}
+
+/// Replace `$VAR` and `${VAR}` (and `%VAR%` on Windows) with the values of
+/// environment variables. Unknown variables are replaced with nothing, and
+/// `$$` is a single `$`.
+pub fn expand_vars<'a>(text: Cow<'a, str>) -> Cow<'a, str> {
+	expand_vars_with(text, &env_var)
+}
+
+/// Like `expand_vars`, but variables are looked up with `lookup` instead of
+/// in the environment
+pub fn expand_vars_with<'a>(text: Cow<'a, str>, lookup: &dyn Fn(&str) -> Option<String>) -> Cow<'a, str> {
+	let windows = cfg!(target_family = "windows");
+	match text {
+		Cow::Borrowed(text) => expand_with(text, lookup, windows),
+		Cow::Owned(text) => Cow::from(expand_with(&text, lookup, windows).into_owned()),
+	}
+}
+
+/// The value of an environment variable, if it is set and is valid Unicode
+pub fn env_var(name: &str) -> Option<String> {
+	env::var(name).ok()
+}
+
+fn is_var_char(ch: char) -> bool {
+	ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+fn expand_with<'a>(
+	text: &'a str,
+	lookup: impl Fn(&str) -> Option<String>,
+	percent: bool
+) -> Cow<'a, str> {
+	if !(text.contains('$') || (percent && text.contains('%'))) {
+		return Cow::from(text);
+	}
+	let mut expanded = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find(|ch| ch == '$' || (percent && ch == '%')) {
+		expanded.push_str(&rest[..start]);
+		let after = &rest[start + 1..];
+		// The name of the variable, and what comes after it
+		let var = if rest[start..].starts_with('%') {
+			after.split_once('%').filter(|(name, _)| {
+				!name.is_empty() && name.chars().all(|ch| is_var_char(ch) || "()".contains(ch))
+			})
+		} else if let Some(after) = after.strip_prefix('$') {
+			expanded.push('$');
+			rest = after;
+			continue;
+		} else if let Some(braced) = after.strip_prefix('{') {
+			braced.split_once('}').filter(|(name, _)| !name.is_empty())
+		} else {
+			let end = after.find(|ch| !is_var_char(ch)).unwrap_or(after.len());
+			Some(after.split_at(end))
+				.filter(|(name, _)| name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_'))
+		};
+		match var {
+			Some((name, after)) => {
+				expanded.push_str(&lookup(name).unwrap_or_default());
+				rest = after;
+			},
+			None => {
+				expanded.push_str(&rest[start..start + 1]);
+				rest = after;
+			}
+		}
+	}
+	expanded.push_str(rest);
+	Cow::from(expanded)
+}
+
+/// Parse launch options for running `orig_exe`. Without `%command%`, they
+/// are all arguments for it. Otherwise, what comes before `%command%` is
+/// environment variables, then the program to run it with and its arguments,
+/// and what comes after are more arguments.
 pub fn get_run_info<'a>(args: &'a str, orig_exe: &'a str) -> RunInfo<'a> {
+	get_run_info_with(args, orig_exe, &env_var)
+}
+
+/// Like `get_run_info`, but variables are looked up with `lookup` instead of
+/// in the environment
+pub fn get_run_info_with<'a>(
+	args: &'a str,
+	orig_exe: &'a str,
+	lookup: &dyn Fn(&str) -> Option<String>,
+) -> RunInfo<'a> {
 	let command: Option<(&str, &str)> = args.split_once("%command%");
 	match command {
 		Some((prefix, suffix)) => {
@@ -20,9 +158,12 @@ pub fn get_run_info<'a>(args: &'a str, orig_exe: &'a str) -> RunInfo<'a> {
 					let pair: Option<(&str, &str)> = arg.split_once('=');
 					match pair {
 						Some((key, val)) => {
-							run_info.environment.push(
-								(key, cmdlineparse::dequote(val))
-							);
+							// Like in a shell, nothing is expanded in single quotes
+							let val = match cmdlineparse::single_quoted(val) {
+								Some(val) => Cow::from(val),
+								None => expand_vars_with(cmdlineparse::dequote(val), lookup),
+							};
+							run_info.environment.push((key, val));
 						},
 						None => {
 							parsing_env = false;
@@ -32,23 +173,27 @@ pub fn get_run_info<'a>(args: &'a str, orig_exe: &'a str) -> RunInfo<'a> {
 				// These two "ifs" are separate so that arguments can be
 				// parsed after parsing_env is set to false
 				if !parsing_env {
+					let arg = expand_vars_with(Cow::from(arg), lookup);
 					match run_info.new_executable {
 						Some(_) => { run_info.arguments.push(arg); },
 						None => { run_info.new_executable.get_or_insert(arg); }
 					}
 				}
 			});
+			// The original executable is a path, not something to expand
 			if run_info.new_executable.is_some() {
-				run_info.arguments.push(orig_exe);
+				run_info.arguments.push(Cow::from(orig_exe));
 			}
 			cmdlineparse::parse_cmdline(suffix).for_each(|arg| {
-				run_info.arguments.push(arg.trim_matches('"'));
+				run_info.arguments.push(expand_vars_with(Cow::from(arg.trim_matches('"')), lookup));
 			});
 			run_info
 		},
 		None => {
 			RunInfo {
-				arguments: cmdlineparse::parse_cmdline(args).collect(),
+				arguments: cmdlineparse::parse_cmdline(args)
+					.map(|arg| expand_vars_with(Cow::from(arg), lookup))
+					.collect(),
 				..Default::default()
 			}
 		}
@@ -72,7 +217,7 @@ mod tests {
 		let arghs = "CUP=TEA FOOL=BARF mangohud %command% booba.wad feet.wad";
 		let actual = get_run_info(arghs, "gzdoom");
 		let expected_env: Vec<(&str, Cow<str>)> = vec![("CUP", Cow::from("TEA")), ("FOOL", Cow::from("BARF"))];
-		let expected_exe = Some("mangohud");
+		let expected_exe = Some(Cow::from("mangohud"));
 		let expected_args = vec!["gzdoom", "booba.wad", "feet.wad"];
 
 		assert_eq!(actual.arguments, expected_args);
@@ -87,7 +232,7 @@ mod tests {
 		let arghs = "ENABLE_VKBASALT=1 mangohud %command%";
 		let actual = get_run_info(arghs, "gzdoom");
 		let expected_env: Vec<(&str, Cow<str>)> = vec![("ENABLE_VKBASALT", Cow::from("1"))];
-		let expected_exe = Some("mangohud");
+		let expected_exe = Some(Cow::from("mangohud"));
 		let expected_args = vec!["gzdoom"];
 
 		assert_eq!(actual.arguments, expected_args);
@@ -111,4 +256,72 @@ mod tests {
 			assert_eq!(key, val);
 		});
 	}
+
+	fn expand(text: &str, percent: bool) -> Cow<'_, str> {
+		let lookup = |name: &str| match name {
+			"HOME" => Some(String::from("/home/doomguy")),
+			"GAME_DIR" => Some(String::from("wads")),
+			"ProgramFiles(x86)" => Some(String::from("C:\\Program Files (x86)")),
+			_ => None,
+		};
+		expand_with(text, lookup, percent)
+	}
+
+	#[test]
+	fn expanded_vars() {
+		assert!(matches!(expand("nothing to expand", false), Cow::Borrowed(_)));
+		assert_eq!(expand("$HOME/wads", false), "/home/doomguy/wads");
+		assert_eq!(expand("${HOME}wads", false), "/home/doomguywads");
+		assert_eq!(expand("$HOME/$GAME_DIR", false), "/home/doomguy/wads");
+		assert_eq!(expand("[$UNKNOWN]", false), "[]");
+		assert_eq!(expand("[${UNKNOWN}]", false), "[]");
+		assert_eq!(expand("cost: $$5", false), "cost: $5");
+		assert_eq!(expand("$$HOME", false), "$HOME");
+		assert_eq!(expand("$5 and $", false), "$5 and $");
+		assert_eq!(expand("${HOME", false), "${HOME");
+		assert_eq!(expand("%HOME%", false), "%HOME%");
+		assert_eq!(expand("%HOME%\\wads", true), "/home/doomguy\\wads");
+		assert_eq!(expand("%ProgramFiles(x86)%", true), "C:\\Program Files (x86)");
+		assert_eq!(expand("%UNKNOWN%x", true), "x");
+		assert_eq!(expand("50% of 100%", true), "50% of 100%");
+		assert_eq!(expand("100%", true), "100%");
+	}
+
+	#[test]
+	fn expanded_command() {
+		let lookup = |name: &str| (name == "TALAUNCHER_TEST_WADS").then(|| String::from("/wads"));
+		let arghs = "DOOMWADDIR=$TALAUNCHER_TEST_WADS/doom mangohud %command% -file ${TALAUNCHER_TEST_WADS}/a.wad";
+		let actual = get_run_info_with(arghs, "gzdoom", &lookup);
+		assert_eq!(actual.environment, vec![("DOOMWADDIR", Cow::from("/wads/doom"))]);
+		assert_eq!(actual.arguments, vec!["gzdoom", "-file", "/wads/a.wad"]);
+
+		let actual = get_run_info_with("A='$TALAUNCHER_TEST_WADS' B=\"$TALAUNCHER_TEST_WADS\" %command%", "gzdoom", &lookup);
+		assert_eq!(actual.environment, vec![
+			("A", Cow::from("$TALAUNCHER_TEST_WADS")),
+			("B", Cow::from("/wads")),
+		]);
+
+		let actual = get_run_info_with("-file $TALAUNCHER_TEST_WADS/a.wad", "gzdoom", &lookup);
+		assert_eq!(actual.arguments, vec!["-file", "/wads/a.wad"]);
+	}
+
+	#[test]
+	fn into_command() {
+		let run_info = get_run_info("DXVK_HUD=1 mangohud --dlsym %command% -fast", "gzdoom");
+		assert_eq!(run_info.new_executable(), Some("mangohud"));
+		assert_eq!(run_info.program("gzdoom"), "mangohud");
+		assert_eq!(run_info.arguments(), ["--dlsym", "gzdoom", "-fast"]);
+		assert_eq!(run_info.environment(), [("DXVK_HUD", Cow::from("1"))]);
+		let command = run_info.into_command("gzdoom");
+		assert_eq!(command.get_program(), "mangohud");
+		let args: Vec<_> = command.get_args().collect();
+		assert_eq!(args, ["--dlsym", "gzdoom", "-fast"]);
+		let envs: Vec<_> = command.get_envs().collect();
+		assert_eq!(envs, [("DXVK_HUD".as_ref(), Some("1".as_ref()))]);
+
+		// Run directly
+		let command = get_run_info("-fast", "gzdoom").into_command("gzdoom");
+		assert_eq!(command.get_program(), "gzdoom");
+		assert_eq!(command.get_args().collect::<Vec<_>>(), ["-fast"]);
+	}
 }
@@ -0,0 +1,147 @@
+use crate::checks::{is_iwad, iwad_game, IwadKind};
+use std::{collections::HashSet, env, fs, path::{Path, PathBuf}};
+
+/// Extensions of files which may be IWADs. Other files aren't read.
+const IWAD_EXTENSIONS: [&str; 4] = ["wad", "iwad", "ipk3", "pk3"];
+
+/// An IWAD found on the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundIwad {
+	pub path: String,
+	/// The game it is for, if it is one that can be told apart
+	pub game: Option<IwadKind>,
+}
+
+impl FoundIwad {
+	pub fn new(path: &Path) -> FoundIwad {
+		let game = iwad_game(&path).filter(|game| *game != IwadKind::Unknown);
+		FoundIwad { path: path.to_string_lossy().into_owned(), game }
+	}
+}
+
+/// The path of an IWAD, with the game it is for if it is known
+pub fn iwad_label(path: &str, game: Option<IwadKind>) -> String {
+	match game {
+		Some(game) => format!("{} ({})", path, game.name()),
+		None => String::from(path),
+	}
+}
+
+/// Folders which IWADs are usually in: those in `DOOMWADDIR`, the current
+/// folder, and where the system's packages and stores put them
+pub fn iwad_dirs() -> Vec<PathBuf> {
+	let mut dirs: Vec<PathBuf> = env::var_os("DOOMWADDIR")
+		.map(|dirs| env::split_paths(&dirs).collect())
+		.unwrap_or_default();
+	dirs.extend(env::current_dir());
+	#[cfg(unix)]
+	{
+		let home = env::var_os("HOME").map(PathBuf::from);
+		dirs.push(PathBuf::from("/usr/share/games/doom"));
+		dirs.push(PathBuf::from("/usr/local/share/games/doom"));
+		dirs.push(PathBuf::from("/usr/share/doom"));
+		dirs.extend(home.iter().map(|home| home.join(".local/share/games/doom")));
+		#[cfg(target_os = "macos")]
+		dirs.extend(home.iter().map(|home| home.join("Library/Application Support/gzdoom")));
+	}
+	#[cfg(windows)]
+	{
+		["ProgramFiles(x86)", "ProgramFiles"].iter()
+			.filter_map(env::var_os)
+			.map(PathBuf::from)
+			.for_each(|programs| {
+				dirs.push(programs.join("Steam/steamapps/common/Doom 64"));
+				dirs.push(programs.join("GOG Galaxy/Games/DOOM 64"));
+			});
+	}
+	dirs
+}
+
+/// The IWADs in the given folders, in the order of the folders, and sorted by
+/// path in each folder. Folders aren't searched recursively, and a file is
+/// only listed once, even if the folders overlap.
+pub fn find_iwads(dirs: &[PathBuf]) -> Vec<FoundIwad> {
+	let mut seen = HashSet::new();
+	dirs.iter().flat_map(|dir| {
+		let mut paths: Vec<PathBuf> = fs::read_dir(dir).into_iter().flatten().flatten()
+			.map(|entry| entry.path())
+			.filter(|path| has_iwad_extension(path) && path.is_file())
+			.collect();
+		paths.sort();
+		paths
+	})
+		.filter(|path| seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+		.filter(is_iwad)
+		.map(|path| FoundIwad::new(&path))
+		.collect()
+}
+
+/// Find IWADs matching a glob pattern, like `/games/doom/*.wad`. Files which
+/// aren't IWADs are left out. Returns the IWADs, and a warning if the pattern
+/// is bad.
+pub fn glob_iwads(pattern: &str) -> (Vec<FoundIwad>, Vec<String>) {
+	let paths = match glob::glob(pattern) {
+		Ok(paths) => paths,
+		Err(e) => return (vec![], vec![format!("Bad IWAD pattern {}: {}", pattern, e)]),
+	};
+	let iwads = paths.flatten()
+		.filter(|path| path.is_file() && is_iwad(path))
+		.map(|path| FoundIwad::new(&path))
+		.collect();
+	(iwads, vec![])
+}
+
+fn has_iwad_extension(path: &Path) -> bool {
+	path.extension().map_or(false, |ext| {
+		IWAD_EXTENSIONS.iter().any(|iwad_ext| ext.eq_ignore_ascii_case(iwad_ext))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+
+	/// A WAD without any lumps
+	fn empty_wad(magic: &[u8; 4]) -> Vec<u8> {
+		let mut wad = magic.to_vec();
+		wad.extend(0u32.to_le_bytes());
+		wad.extend(12u32.to_le_bytes());
+		wad
+	}
+
+	#[test]
+	fn found_iwads() {
+		let dir = TestDir::new("find-iwads");
+		let other = dir.join("other");
+		fs::create_dir_all(&other).unwrap();
+		fs::write(dir.join("DOOM64.WAD"), empty_wad(b"IWAD")).unwrap();
+		fs::write(dir.join("addon.wad"), empty_wad(b"PWAD")).unwrap();
+		fs::write(dir.join("notes.txt"), empty_wad(b"IWAD")).unwrap();
+		fs::write(other.join("freedoom2.wad"), empty_wad(b"IWAD")).unwrap();
+
+		// The same folder twice, and one which doesn't exist
+		let dirs = [other.clone(), dir.to_path_buf(), dir.join("."), dir.join("missing")];
+		let found = find_iwads(&dirs);
+		let pattern = dir.join("*.WAD");
+		let (globbed, warnings) = glob_iwads(&pattern.to_string_lossy());
+		let (_, bad_warnings) = glob_iwads("[");
+
+		let paths: Vec<&str> = found.iter().map(|iwad| iwad.path.as_str()).collect();
+		assert_eq!(paths, [
+			other.join("freedoom2.wad").to_string_lossy(),
+			dir.join("DOOM64.WAD").to_string_lossy(),
+		]);
+		// Neither has the lumps which tell which game it is
+		assert_eq!(found[1].game, None);
+		assert_eq!(globbed, &found[1..]);
+		assert!(warnings.is_empty());
+		assert_eq!(bad_warnings.len(), 1);
+	}
+
+	#[test]
+	fn labels() {
+		assert_eq!(iwad_label("/games/DOOM2.WAD", Some(IwadKind::Doom2)), "/games/DOOM2.WAD (Doom II)");
+		assert_eq!(iwad_label("/games/DOOM64.WAD", None), "/games/DOOM64.WAD");
+	}
+}
@@ -0,0 +1,475 @@
+use addon_manager_egui::command::new_command;
+use serde::{Deserialize, Serialize};
+use std::{
+	env,
+	fs::{self, File},
+	io::{self, BufRead, BufReader, Read},
+	mem,
+	path::Path,
+	process::{self, Child, Command, ExitStatus, Stdio},
+	sync::{Arc, Mutex},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+/// Engine flag which loads everything and exits without starting the game
+pub const NORUN: &str = "-norun";
+/// How long the engine gets to list its flags
+const HELP_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the engine gets to load everything
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many lines of engine output to show when the engine fails
+const FAILURE_OUTPUT_LINES: usize = 10;
+/// Where the engine's output goes when it is watched on startup, in the
+/// temporary directory. The launcher's process ID is added, so that several
+/// launchers don't write to the same file.
+const STARTUP_LOG: &str = "talauncher-gzdoom";
+/// How much of the engine's output to keep in the log, in bytes
+const MAX_LOG_LEN: usize = 1 << 20;
+
+/// A command started by `LaunchCommand::spawn_logged`
+#[derive(Debug)]
+pub struct LoggedChild {
+	child: Child,
+	/// Threads adding the output to the log. Each returns what it read.
+	readers: Vec<JoinHandle<String>>,
+}
+
+impl LoggedChild {
+	/// Wait for the command to exit, and for all of its output to be read.
+	/// Returns the exit status, and the output of this run of the command.
+	pub fn wait(mut self) -> io::Result<(ExitStatus, String)> {
+		let status = self.child.wait()?;
+		let output = self.readers
+			.into_iter()
+			.map(|reader| reader.join().unwrap_or_default())
+			.collect::<Vec<_>>()
+			.concat();
+		Ok((status, output))
+	}
+}
+
+/// The result of checking a launch command with `-norun`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preflight {
+	Passed,
+	/// The engine doesn't have `-norun`, so the check was skipped
+	Unsupported,
+	/// The engine exited with an error. Contains the end of its output.
+	Failed(String),
+}
+
+/// What the engine did within the grace period after it was started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Startup {
+	Running,
+	/// The engine exited normally
+	Exited,
+	/// The engine exited with an error. Contains the end of its output.
+	Crashed(String),
+}
+
+/// A fully assembled command for launching the game, which can be run again
+/// exactly as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchCommand {
+	pub program: String,
+	pub arguments: Vec<String>,
+	pub environment: Vec<(String, String)>,
+	/// Files the command refers to, which must exist for it to work
+	pub files: Vec<String>,
+	#[serde(default)]
+	pub working_dir: Option<String>,
+}
+
+impl LaunchCommand {
+	fn command(&self) -> Command {
+		let environment = self.environment.iter().map(|(k, v)| (k, v));
+		let mut command = new_command(&self.program, &self.arguments, environment);
+		if let Some(dir) = &self.working_dir {
+			command.current_dir(dir);
+		}
+		command
+	}
+
+	pub fn spawn(&self) -> io::Result<Child> {
+		self.command().spawn()
+	}
+
+	/// Start the command, adding its output to `log` as it is written
+	pub fn spawn_logged(&self, log: &Arc<Mutex<String>>) -> io::Result<LoggedChild> {
+		let mut child = self.command()
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()?;
+		let readers = [
+			child.stdout.take().map(|pipe| follow(pipe, Arc::clone(log))),
+			child.stderr.take().map(|pipe| follow(pipe, Arc::clone(log))),
+		].into_iter().flatten().collect();
+		Ok(LoggedChild { child, readers })
+	}
+
+	/// Run the command with `-norun`, so the engine loads the IWAD and addons
+	/// and exits. The check is skipped if `engine` doesn't have `-norun`.
+	pub fn preflight(&self, engine: &str) -> io::Result<Preflight> {
+		if !self.supports_norun(engine) {
+			return Ok(Preflight::Unsupported);
+		}
+		let mut command = self.command();
+		command.arg(NORUN);
+		let (status, output) = run_captured(&mut command, PREFLIGHT_TIMEOUT)?;
+		if status.success() {
+			return Ok(Preflight::Passed);
+		}
+		Ok(Preflight::Failed(failure_report(status, &output)))
+	}
+
+	/// Start the command, and watch it for `grace` to see whether it crashes
+	/// on startup. It keeps running afterwards. Its output goes to a log
+	/// file, which it can keep writing to after the launcher has quit.
+	pub fn spawn_watched(&self, grace: Duration) -> io::Result<Startup> {
+		let log_path = env::temp_dir().join(format!("{}-{}.log", STARTUP_LOG, process::id()));
+		let log = File::create(&log_path)?;
+		let mut child = self.command()
+			.stdin(Stdio::null())
+			.stdout(log.try_clone()?)
+			.stderr(log)
+			.spawn()?;
+		let started = Instant::now();
+		while started.elapsed() < grace {
+			if let Some(status) = child.try_wait()? {
+				if status.success() {
+					return Ok(Startup::Exited);
+				}
+				let output = String::from_utf8_lossy(&fs::read(&log_path)?).into_owned();
+				return Ok(Startup::Crashed(failure_report(status, &output)));
+			}
+			thread::sleep(Duration::from_millis(50));
+		}
+		Ok(Startup::Running)
+	}
+
+	/// Run `engine` through another program, such as Wine. If the engine is
+	/// already run by a wrapper, like `mangohud`, the new program goes
+	/// between the two.
+	pub fn wrap_engine(&mut self, engine: &str, program: &str) {
+		if self.program == engine {
+			let engine = mem::replace(&mut self.program, String::from(program));
+			self.arguments.insert(0, engine);
+		} else if let Some(index) = self.arguments.iter().position(|arg| arg == engine) {
+			self.arguments.insert(index, String::from(program));
+		}
+	}
+
+	/// Whether the engine mentions `-norun` in its help output. The engine is
+	/// run the way the command runs it, so that a Windows build is asked
+	/// through Wine.
+	pub fn supports_norun(&self, engine: &str) -> bool {
+		// The wrappers before the engine, and the engine itself
+		let wrapped = match self.arguments.iter().position(|arg| arg == engine) {
+			Some(index) if self.program != engine => index + 1,
+			_ => 0,
+		};
+		let help = LaunchCommand {
+			arguments: self.arguments[..wrapped].iter()
+				.cloned()
+				.chain(std::iter::once(String::from("--help")))
+				.collect(),
+			..self.clone()
+		};
+		run_captured(&mut help.command(), HELP_TIMEOUT)
+			.map(|(_status, output)| output.contains(NORUN))
+			.unwrap_or(false)
+	}
+
+	/// Whether all the files the command refers to still exist
+	pub fn files_exist(&self) -> bool {
+		self.files.iter().all(|file| Path::new(file).exists())
+	}
+
+	/// The command as it could be typed into a shell, with the environment
+	/// variables in front of it
+	pub fn command_line(&self) -> String {
+		self.environment.iter()
+			.map(|(key, value)| format!("{}={}", key, quote(value)))
+			.chain(std::iter::once(quote(&self.program)))
+			.chain(self.arguments.iter().map(|arg| quote(arg)))
+			.collect::<Vec<String>>()
+			.join(" ")
+	}
+}
+
+/// Quote an argument if the shell would treat any of it specially, so that
+/// the command line can be pasted into a shell and run
+#[cfg(not(target_family = "windows"))]
+fn quote(arg: &str) -> String {
+	let plain = |c: char| c.is_alphanumeric() || "-_./:=+,%@".contains(c);
+	if !arg.is_empty() && arg.chars().all(plain) {
+		return String::from(arg);
+	}
+	format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(target_family = "windows")]
+fn quote(arg: &str) -> String {
+	if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"') {
+		return String::from(arg);
+	}
+	format!("\"{}\"", arg.replace('"', "\\\""))
+}
+
+/// Add each line of `output` to `log` in the background, until the pipe is
+/// closed. The oldest lines are dropped when the log gets too long. The
+/// thread returns everything it read, which is trimmed the same way.
+fn follow(output: impl Read + Send + 'static, log: Arc<Mutex<String>>) -> JoinHandle<String> {
+	thread::spawn(move || {
+		let mut output = BufReader::new(output);
+		let mut line = Vec::new();
+		let mut read = String::new();
+		while matches!(output.read_until(b'\n', &mut line), Ok(count) if count > 0) {
+			// Output which isn't UTF-8 is not worth failing over
+			let mut text = String::from_utf8_lossy(&line).into_owned();
+			if !text.ends_with('\n') {
+				text.push('\n');
+			}
+			match log.lock() {
+				Ok(mut log) => {
+					log.push_str(&text);
+					trim_log(&mut log, MAX_LOG_LEN);
+				}
+				Err(_) => break,
+			}
+			read.push_str(&text);
+			trim_log(&mut read, MAX_LOG_LEN);
+			line.clear();
+		}
+		read
+	})
+}
+
+/// Drop whole lines from the start of the log until it is at most `max_len`
+/// bytes long
+fn trim_log(log: &mut String, max_len: usize) {
+	if log.len() <= max_len {
+		return;
+	}
+	// Look for the end of a line in bytes, since `excess` may be in the
+	// middle of a character
+	let excess = log.len() - max_len;
+	let cut = log.as_bytes()[excess..].iter()
+		.position(|byte| *byte == b'\n')
+		.map_or(log.len(), |end| excess + end + 1);
+	log.drain(..cut);
+}
+
+/// The exit status and the end of the output of an engine which failed
+pub fn failure_report(status: ExitStatus, output: &str) -> String {
+	let lines: Vec<&str> = output.lines().filter(|line| !line.trim().is_empty()).collect();
+	let tail = &lines[lines.len().saturating_sub(FAILURE_OUTPUT_LINES)..];
+	format!("{}\n{}", status, tail.join("\n"))
+}
+
+/// Run a command to completion, collecting its standard output and error.
+/// It is killed if it takes longer than `timeout`.
+fn run_captured(command: &mut Command, timeout: Duration) -> io::Result<(ExitStatus, String)> {
+	let mut child = command
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()?;
+	// Read both pipes at once, so the child doesn't block on a full pipe
+	let readers = [
+		child.stdout.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+		child.stderr.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+	].map(|pipe| thread::spawn(move || {
+		let mut output = String::new();
+		if let Some(mut pipe) = pipe {
+			// Output which isn't UTF-8 is not worth failing over
+			let mut bytes = Vec::new();
+			let _ = pipe.read_to_end(&mut bytes);
+			output = String::from_utf8_lossy(&bytes).into_owned();
+		}
+		output
+	}));
+	let started = Instant::now();
+	let status = loop {
+		if let Some(status) = child.try_wait()? {
+			break status;
+		}
+		if started.elapsed() > timeout {
+			let _ = child.kill();
+			let _ = child.wait();
+			return Err(io::Error::new(
+				io::ErrorKind::TimedOut,
+				format!("{:?} did not finish within {} seconds", command.get_program(), timeout.as_secs()),
+			));
+		}
+		thread::sleep(Duration::from_millis(50));
+	};
+	let output = readers
+		.into_iter()
+		.map(|reader| reader.join().unwrap_or_default())
+		.collect::<Vec<_>>()
+		.join("\n");
+	Ok((status, output))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+
+	fn command(engine: &str, iwad: &str) -> LaunchCommand {
+		LaunchCommand {
+			program: String::from(engine),
+			arguments: vec![String::from("-iwad"), String::from(iwad)],
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn wrap_engine() {
+		let mut direct = command("gzdoom.exe", "DOOM64.WAD");
+		direct.wrap_engine("gzdoom.exe", "wine");
+		assert_eq!(direct.program, "wine");
+		assert_eq!(direct.arguments, ["gzdoom.exe", "-iwad", "DOOM64.WAD"]);
+
+		let mut wrapped = command("mangohud", "DOOM64.WAD");
+		wrapped.arguments.insert(0, String::from("gzdoom.exe"));
+		wrapped.arguments.insert(0, String::from("--dlsym"));
+		wrapped.wrap_engine("gzdoom.exe", "wine");
+		assert_eq!(wrapped.program, "mangohud");
+		assert_eq!(wrapped.arguments, ["--dlsym", "wine", "gzdoom.exe", "-iwad", "DOOM64.WAD"]);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn command_line() {
+		let mut command = command("/usr/bin/gzdoom", "/games/Doom 64/DOOM64.WAD");
+		assert_eq!(command.command_line(), "/usr/bin/gzdoom -iwad '/games/Doom 64/DOOM64.WAD'");
+
+		command.environment.push((String::from("WINEPREFIX"), String::from("/home/doomguy/.wine")));
+		command.arguments.extend(["+echo", "it's $5", ""].map(String::from));
+		assert_eq!(
+			command.command_line(),
+			"WINEPREFIX=/home/doomguy/.wine /usr/bin/gzdoom -iwad '/games/Doom 64/DOOM64.WAD' \
+			+echo 'it'\\''s $5' ''",
+		);
+
+		// The shell gets the same arguments back
+		let output = Command::new("sh")
+			.arg("-c")
+			.arg(format!("printf '%s\\n' {}", command.command_line()))
+			.output()
+			.unwrap();
+		let lines: Vec<String> = String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect();
+		assert_eq!(lines[0], "WINEPREFIX=/home/doomguy/.wine");
+		assert_eq!(&lines[1..], [&[command.program.clone()], command.arguments.as_slice()].concat());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn spawn_logged() {
+		let command = LaunchCommand {
+			program: String::from("sh"),
+			arguments: ["-c", "echo out; echo err >&2; printf 'no newline'"].map(String::from).to_vec(),
+			..Default::default()
+		};
+		let log = Arc::new(Mutex::new(String::from("> earlier run\n")));
+		let (status, output) = command.spawn_logged(&log).unwrap().wait().unwrap();
+		assert!(status.success());
+		// All the output has been read once it has exited
+		let mut lines: Vec<&str> = output.lines().collect();
+		lines.sort();
+		assert_eq!(lines, ["err", "no newline", "out"]);
+		let log = log.lock().unwrap();
+		assert!(log.starts_with("> earlier run\n"));
+		let mut lines: Vec<&str> = log.lines().skip(1).collect();
+		lines.sort();
+		assert_eq!(lines, ["err", "no newline", "out"]);
+	}
+
+	/// Make a fake engine which fails when loading "bad.wad"
+	#[cfg(unix)]
+	fn fake_engine(dir: &Path, help: &str) -> String {
+		use std::{fs, os::unix::fs::PermissionsExt};
+		let engine = dir.join("engine.sh");
+		let script = format!(
+			"#!/bin/sh\n[ \"$1\" = --help ] && echo '{}' && exit 0\n\
+			for arg; do [ \"$arg\" = bad.wad ] && echo 'Cannot load bad.wad' && exit 1; done\nexit 0\n",
+			help
+		);
+		fs::write(&engine, script).unwrap();
+		fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+		engine.to_string_lossy().into_owned()
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn preflight() {
+		use std::{fs, os::unix::fs::PermissionsExt};
+		let dir = TestDir::new("preflight");
+
+		let engine = fake_engine(&dir, "-iwad -file -norun");
+		assert!(command(&engine, "good.wad").supports_norun(&engine));
+		assert_eq!(command(&engine, "good.wad").preflight(&engine).unwrap(), Preflight::Passed);
+		match command(&engine, "bad.wad").preflight(&engine).unwrap() {
+			Preflight::Failed(output) => assert!(output.contains("Cannot load bad.wad")),
+			result => panic!("Expected failure, got {:?}", result),
+		}
+
+		let engine = fake_engine(&dir, "-iwad -file");
+		assert!(!command(&engine, "good.wad").supports_norun(&engine));
+		assert_eq!(command(&engine, "bad.wad").preflight(&engine).unwrap(), Preflight::Unsupported);
+
+		// An engine which can't be run by itself, like a Windows build, is
+		// asked through the program which runs it
+		let engine = fake_engine(&dir, "-iwad -file -norun");
+		fs::set_permissions(&engine, fs::Permissions::from_mode(0o644)).unwrap();
+		let mut wrapped = command(&engine, "bad.wad");
+		wrapped.wrap_engine(&engine, "sh");
+		assert!(wrapped.supports_norun(&engine));
+		assert!(matches!(wrapped.preflight(&engine).unwrap(), Preflight::Failed(_)));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn spawn_watched() {
+		let dir = TestDir::new("watched");
+		let engine = fake_engine(&dir, "-iwad -file");
+		let grace = Duration::from_secs(5);
+
+		let exited = command(&engine, "good.wad").spawn_watched(grace).unwrap();
+		let crashed = command(&engine, "bad.wad").spawn_watched(grace).unwrap();
+		assert_eq!(exited, Startup::Exited);
+		match crashed {
+			Startup::Crashed(output) => assert!(output.contains("Cannot load bad.wad")),
+			result => panic!("Expected a crash, got {:?}", result),
+		}
+
+		let sleeper = LaunchCommand {
+			program: String::from("sleep"),
+			arguments: vec![String::from("1")],
+			..Default::default()
+		};
+		let running = sleeper.spawn_watched(Duration::from_millis(100)).unwrap();
+		assert_eq!(running, Startup::Running);
+	}
+
+	#[test]
+	fn trimmed_log() {
+		let mut log = String::from("short\n");
+		trim_log(&mut log, 100);
+		assert_eq!(log, "short\n");
+
+		// Cutting 2 bytes from the start would split a replacement character
+		let mut log = String::from("\u{fffd}\u{fffd}\u{fffd}\nsecond\n");
+		let max_len = log.len() - 2;
+		trim_log(&mut log, max_len);
+		assert_eq!(log, "second\n");
+		let mut log = String::from("\u{fffd}\u{fffd}");
+		trim_log(&mut log, 2);
+		assert_eq!(log, "");
+	}
+}
@@ -0,0 +1,5 @@
+//! Parsing of GZDoom launch options, including Steam's `%command%` syntax,
+//! for tools which run GZDoom the same way as the launcher.
+
+pub mod cmdlineparse;
+pub mod command;
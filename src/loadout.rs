@@ -0,0 +1,99 @@
+use crate::addon::AddonMap;
+use crate::selection::{self, Selection};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Start of a shared loadout, so it can be told apart from other text
+pub const PREFIX: &str = "loadout:";
+
+/// A selection of addons which can be shared with someone who has the same
+/// addon list. It has no paths, since they differ between computers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Loadout {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub primary: Option<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub secondary: Vec<String>,
+	/// The chosen variant of each addon in the loadout which has variants
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	pub variants: HashMap<String, String>,
+	/// The map to start on
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub map: Option<String>,
+}
+
+impl Loadout {
+	/// Encode the loadout as text which can be pasted anywhere
+	pub fn encode(&self) -> String {
+		let json = serde_json::to_string(self).unwrap_or_default();
+		format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(json))
+	}
+
+	/// Read a loadout from text made by `encode`. The prefix may be left out.
+	pub fn decode(text: &str) -> Result<Loadout, String> {
+		let text = text.trim();
+		let encoded = text.strip_prefix(PREFIX).unwrap_or(text);
+		let json = URL_SAFE_NO_PAD
+			.decode(encoded)
+			.map_err(|_| String::from("This is not a shared loadout."))?;
+		serde_json::from_slice(&json)
+			.map_err(|e| format!("The shared loadout could not be read: {}", e))
+	}
+
+	/// Find the loadout's addons in the local addon list. Returns the
+	/// selection, and warnings about addons which couldn't be used.
+	pub fn resolve(&self, addons: &AddonMap) -> (Selection, Vec<String>) {
+		let names: Vec<String> = self.primary.iter().chain(&self.secondary).cloned().collect();
+		selection::resolve_selection(&names, addons)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::addon::AddonSpecification;
+
+	fn loadout() -> Loadout {
+		Loadout {
+			primary: Some(String::from("Beta64 by Antnee")),
+			secondary: vec![String::from("Arranged BGM"), String::from("Widescreen HUD")],
+			variants: HashMap::from([(String::from("Arranged BGM"), String::from("FLAC"))]),
+			map: Some(String::from("MAP02")),
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let encoded = loadout().encode();
+		assert!(encoded.starts_with(PREFIX));
+		assert!(!encoded.contains('/'));
+		assert_eq!(Loadout::decode(&encoded).unwrap(), loadout());
+		// Without the prefix, and with whitespace from copying and pasting
+		let bare = format!("  {}\n", &encoded[PREFIX.len()..]);
+		assert_eq!(Loadout::decode(&bare).unwrap(), loadout());
+		assert_eq!(Loadout::decode(&Loadout::default().encode()).unwrap(), Loadout::default());
+	}
+
+	#[test]
+	fn garbage() {
+		assert!(Loadout::decode("loadout:!!!").is_err());
+		let not_json = format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode("hello"));
+		assert!(Loadout::decode(&not_json).unwrap_err().contains("could not be read"));
+	}
+
+	#[test]
+	fn missing_addons() {
+		let addons = AddonMap::from([
+			(String::from("Beta64 by Antnee"), AddonSpecification::default()),
+			(String::from("Arranged BGM"), AddonSpecification {
+				secondary: Some(String::from("1")),
+				..Default::default()
+			}),
+		]);
+		let (selection, warnings) = loadout().resolve(&addons);
+		assert_eq!(selection.primary.as_deref(), Some("Beta64 by Antnee"));
+		assert_eq!(selection.secondary, vec!["Arranged BGM"]);
+		assert_eq!(warnings, vec!["Unknown addon: Widescreen HUD"]);
+	}
+}
@@ -1,16 +1,53 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, error::Error, fs::File, iter, process::Command};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs::{self, File},
+    io,
+    iter,
+    mem,
+    path::{Path, PathBuf},
+    process::{self, ExitStatus},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
 mod addon;
+mod argorder;
+mod args;
+mod builds;
 mod apps;
 mod checks;
-mod cmdlineparse;
-mod command;
+mod iwads;
+mod launch;
+mod loadout;
+mod savegame;
+mod schema;
+mod selection;
+mod selftest;
+#[cfg(test)]
+mod testdir;
+mod update;
 
-use addon::{AddonMap, AddonSpecification};
+use addon::{
+    AddonList, AddonMap, AddonSpecification, IwadHelp, IwadLink, ListSettings, OptionalFiles,
+    SearchMatch,
+};
+use argorder::{ArgOrder, Segment};
 use apps::error::ErrorMessage;
+use args::LauncherArgs;
+use builds::{Build, SourcePort};
 use checks::*;
+use addon_manager_egui::{cmdlineparse, command};
 use command::*;
+use iwads::FoundIwad;
+use launch::{failure_report, LaunchCommand, Preflight, Startup};
+use loadout::Loadout;
+use savegame::SaveGame;
+use selection::Selection;
+use update::{DiffLine, ManifestUpdate};
 use eframe::{
     App,
     AppCreator,
@@ -23,41 +60,209 @@ use eframe::{
 use egui::viewport::{ViewportBuilder, ViewportCommand};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let addons: Result<HashMap<String, AddonSpecification>, Box<dyn Error>> =
-        addon::get_addons(None);
-    let app: AppCreator = Box::new(|cc| -> Box<dyn App> {
-        let data = cc.storage.map(Persistence::from);
+    let args = LauncherArgs::parse(env::args().skip(1));
+    if let Some(manifest) = &args.self_test {
+        let addon_dir = addon::addon_base_dir(args.addon_dir.as_deref(), manifest);
+        if let Err(e) = selftest::run(manifest, &addon_dir, args.ignore_case) {
+            eprintln!("Self-test failed: {}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.emit_schema {
+        println!("{:#}", schema::manifest_schema());
+        return Ok(());
+    }
+    let manifest = args.addons_file.clone().unwrap_or_else(|| PathBuf::from(MANIFEST));
+    let addon_dir = addon::addon_base_dir(args.addon_dir.as_deref(), &manifest);
+    if args.verbose {
+        let shown_dir = match addon_dir.as_os_str().is_empty() {
+            true => env::current_dir().unwrap_or_default(),
+            false => addon_dir.clone(),
+        };
+        eprintln!("Addon base directory: {}", shown_dir.display());
+    }
+    if args.list_addons {
+        match selftest::list_addons(&manifest, &addon_dir, args.ignore_case) {
+            Ok(true) => return Ok(()),
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("{}\nAddon list: {}", e, manifest.display());
+                process::exit(1);
+            }
+        }
+    }
+    let addons = addon::get_addons(Some(&manifest.to_string_lossy()), &addon_dir, args.ignore_case)
+        .map_err(|e| format!("{}\nAddon list: {}", e, manifest.display()));
+    let mut warnings = vec![];
+    if let Ok(list) = &addons {
+        warnings.extend(list.warnings.iter().cloned());
+        list.settings.missing_always_load.iter().for_each(|name| {
+            warnings.push(format!(
+                "{} should always be loaded, but some of its files are missing.",
+                name
+            ));
+        });
+        if let Some(message) = incomplete_addons_message(&list.incomplete) {
+            warnings.push(message);
+        }
+        if args.verify_checksums {
+            if let Some(message) = checksums_message(&addon::verify_checksums(&list.addons)) {
+                warnings.push(message);
+            }
+        }
+    }
+    let arg_order = match args.arg_order.as_deref().map(ArgOrder::parse) {
+        Some(Ok(order)) => order,
+        Some(Err(e)) => {
+            warnings.push(format!("{}. Using the default argument order.", e));
+            ArgOrder::default()
+        }
+        None => ArgOrder::default(),
+    };
+    if args.launch {
+        warnings.iter().for_each(|warning| eprintln!("{}", warning));
+        let code = match addons {
+            Ok(list) => launch_headless(list, &args, &manifest, addon_dir, arg_order),
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+        process::exit(code);
+    }
+    let selection = match (&args.select, &addons) {
+        (Some(names), Ok(list)) => {
+            let names = selection::parse_selection(names);
+            let (selection, selection_warnings) =
+                selection::resolve_selection(&names, &list.addons);
+            warnings.extend(selection_warnings);
+            Some(selection)
+        }
+        _ => None,
+    };
+    let launcher = LauncherStart {
+        addons,
+        selection,
+        warnings,
+        manifest,
+        addon_dir,
+        arg_order,
+        args: args.clone(),
+    };
+    let app = app_creator(launcher.clone());
+    let options = native_options(&args, HardwareAcceleration::Preferred);
+    if let Err(e) = eframe::run_native("Talauncher", options, app) {
+        // The graphics driver may not support what was asked for, so try
+        // again on the same event loop, asking for as little as possible.
+        eprintln!("Could not start with hardware acceleration: {}", e);
+        eprintln!("Trying again without it...");
+        let app = app_creator(launcher);
+        let options = NativeOptions {
+            multisampling: 0,
+            ..native_options(&args, HardwareAcceleration::Off)
+        };
+        if let Err(e) = eframe::run_native("Talauncher", options, app) {
+            eprintln!("Could not create a window: {}", e);
+            eprintln!("This program needs OpenGL 2.0 or later. Please make sure your");
+            eprintln!("graphics drivers are installed and up to date.");
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// What the launcher window starts with, worked out from the command line
+/// before it is opened
+#[derive(Clone)]
+struct LauncherStart {
+    addons: Result<AddonList, String>,
+    /// Addons selected on the command line, instead of the saved ones
+    selection: Option<Selection>,
+    /// Problems to show once the window is open
+    warnings: Vec<String>,
+    manifest: PathBuf,
+    addon_dir: PathBuf,
+    arg_order: ArgOrder,
+    args: LauncherArgs,
+}
+
+fn app_creator(launcher: LauncherStart) -> AppCreator {
+    let LauncherStart {
+        addons,
+        selection,
+        warnings,
+        manifest,
+        addon_dir,
+        arg_order,
+        args,
+    } = launcher;
+    Box::new(move |cc| -> Box<dyn App> {
+        let mut data = cc.storage.map(Persistence::from);
+        if let Some(selection) = selection {
+            let data = data.get_or_insert_with(Persistence::default);
+            data.primary_addon = selection.primary;
+            data.secondary_addons = Some(selection.secondary);
+        }
         match addons {
-            Ok(addons) => Box::new(AddonManager::new(addons, data)),
-            Err(error) => {
-                let message = format!("{:#?}", error);
-                Box::new(ErrorMessage::from(message))
+            Ok(list) => {
+                let mut manager = AddonManager::new(list.addons, Box::from([]), data);
+                manager.start_discovery(args.builds, args.gzdoom_glob, args.iwad_glob);
+                manager.list = list.settings;
+                manager.manifest = manifest.to_string_lossy().into_owned();
+                match load_profiles(&manager.profiles_path()) {
+                    Ok(profiles) => manager.profiles = profiles,
+                    Err(e) => manager.show_message(e),
+                }
+                manager.addon_dir = addon_dir;
+                manager.arg_order = arg_order;
+                if let Some(wine) = args.wine {
+                    manager.wine = wine;
+                }
+                manager.allow_network = args.allow_network;
+                manager.ignore_case = args.ignore_case;
+                cc.egui_ctx.set_visuals(manager.theme().egui_visuals());
+                // The flag turns it on for this session; the checkbox is remembered
+                if args.quit_on_launch {
+                    manager.quit_on_launch = true;
+                }
+                manager.saved_settings = Some(Persistence::from(&manager));
+                warnings
+                    .into_iter()
+                    .for_each(|warning| manager.show_message(warning));
+                Box::new(manager)
             }
+            Err(message) => Box::new(ErrorMessage::from(message)),
         }
-    });
-    let native_options = NativeOptions {
+    })
+}
+
+fn native_options(args: &LauncherArgs, hardware_acceleration: HardwareAcceleration) -> NativeOptions {
+    NativeOptions {
         viewport: ViewportBuilder::default()
             .with_active(true)
             .with_fullscreen(true)
             .with_decorations(true)
             .with_maximized(true)
             .with_app_id("Talon1024.Talauncher"),
-        vsync: false,
-        multisampling: 0,
+        vsync: args.vsync,
+        multisampling: args.multisampling,
         depth_buffer: 0,
         stencil_buffer: 0,
-        hardware_acceleration: HardwareAcceleration::Preferred,
+        hardware_acceleration,
         renderer: Default::default(),
         follow_system_theme: false,
         default_theme: Theme::Dark,
-        run_and_return: false,
+        // winit can't create a second event loop. With this, eframe keeps
+        // the first one, so it can be used again if starting with hardware
+        // acceleration fails.
+        run_and_return: true,
         event_loop_builder: None,
         window_builder: None,
         shader_version: None,
         centered: true,
         persist_window: false,
-    };
-    eframe::run_native("Talauncher", native_options, app).map_err(Box::from)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -73,10 +278,70 @@ impl Default for GZDoomBuildSelection {
     }
 }
 
+/// Kinds of files picked with a file browser. Each kind starts in its own
+/// folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Picker {
+    Builds,
+    Iwads,
+    /// Saved games, scripts and other files used with addons
+    Files,
+}
+
+impl Picker {
+    const ALL: [Picker; 3] = [Picker::Builds, Picker::Iwads, Picker::Files];
+
+    fn label(self) -> &'static str {
+        match self {
+            Picker::Builds => "GZDoom builds",
+            Picker::Iwads => "IWADs",
+            Picker::Files => "Other files",
+        }
+    }
+}
+
+/// How many failed launches to keep
+const MAX_FAILED_LAUNCHES: usize = 10;
+/// How many GZDoom build paths which were typed in are remembered
+const MAX_RECENT_BUILDS: usize = 10;
+
+/// Default hotkey for launching the pinned settings
+const PINNED_HOTKEY: &str = "Ctrl+Enter";
+
+/// Program used to run Windows GZDoom builds on other systems
+const WINE: &str = "wine";
+/// How long GZDoom has to keep running before the launcher quits, in seconds
+const STARTUP_GRACE: u64 = 5;
+const MAX_STARTUP_GRACE: u64 = 60;
+/// Shown when the IWAD can't be found, unless the addon list has its own hint
+const IWAD_HINT: &str = "Doom 64 CE needs DOOM64.WAD from the 2020 release of Doom 64, \
+which is sold on Steam, GOG and other stores. It is in the game's install folder.\n\n\
+Freedoom is a free IWAD, which can be used with addons that don't need Doom 64's assets.";
+/// Where to get the IWAD, unless the addon list has its own links. Doom 64
+/// comes first, since Doom 64 CE needs it.
+const IWAD_LINKS: [(&str, &str); 3] = [
+    ("Doom 64 on Steam", "https://store.steampowered.com/app/1148590/DOOM_64/"),
+    ("Doom 64 on GOG", "https://www.gog.com/game/doom_64"),
+    ("Freedoom", "https://freedoom.github.io/"),
+];
+
+/// The addon manifest, relative to the working directory
+const MANIFEST: &str = "addons.yml";
+/// Where launch profiles are saved, next to the addon list
+const PROFILES: &str = "profiles.yml";
+
+/// Default height of the secondary addon list, and the limits of its height
+const SECONDARY_HEIGHT: f32 = 200.0;
+const MIN_SECONDARY_HEIGHT: f32 = 60.0;
+const MAX_SECONDARY_HEIGHT: f32 = 2000.0;
+
 #[derive(Debug, Clone, Default)]
 struct AddonManager {
-    builds: Box<[String]>,
+    builds: Box<[Build]>,
     iwads: Box<[String]>,
+    /// The game each found IWAD is for, by path, if it is known
+    iwad_games: HashMap<String, IwadKind>,
     addons: AddonMap,
     primary_addons: Box<[String]>,
     secondary_addons: Box<[String]>,
@@ -87,9 +352,99 @@ struct AddonManager {
     popup: Option<String>,
     exargs: String,
     config: String,
+    secondary_filter: String,
+    search_descriptions: bool,
+    saves: Option<Box<[SaveGame]>>,
+    load_game: String,
+    remember_load_game: bool,
+    group_primary_by_category: bool,
+    last_command: Option<LaunchCommand>,
+    secondary_height: f32,
+    exec_scripts: Vec<String>,
+    backup_config: bool,
+    preflight: bool,
+    /// The launch command, if it is being tried with `-norun`
+    preflighting: Option<PendingPreflight>,
+    /// Quit once GZDoom has been running for the grace period
+    quit_on_launch: bool,
+    startup_grace: u64,
+    /// Set when the launcher should quit on the next frame
+    quit_requested: bool,
+    /// The settings as they were when they were last saved, to tell whether
+    /// they have changed since
+    saved_settings: Option<Persistence>,
+    /// Whether to ask what to do with unsaved changes before exiting
+    exit_prompt_open: bool,
+    /// Set once the user has chosen to exit anyway
+    exit_confirmed: bool,
+    /// Exit without saving the settings
+    discard_changes: bool,
+    use_wine: bool,
+    wine: String,
+    wine_prefix: String,
+    /// The folder GZDoom is started in, instead of the one addons ask for
+    launch_dir: String,
+    /// GZDoom build paths which were typed in and launched, most recent first
+    recent_builds: Vec<String>,
+    failed_launches: Vec<FailedLaunch>,
+    /// The chosen variant of each addon which has variants
+    selected_variants: HashMap<String, String>,
+    /// The map to start on, for each addon which has a list of maps
+    selected_maps: HashMap<String, String>,
+    /// The folder each kind of file browser starts in
+    browse_dirs: HashMap<Picker, String>,
+    /// Whether optional files are only loaded if they are picked
+    opt_in_optional: bool,
+    /// Optional files picked or unpicked, by addon name and then by path
+    optional_selections: HashMap<String, HashMap<String, bool>>,
+    /// Settings which can be launched at any time with a hotkey
+    pinned: Option<Box<Persistence>>,
+    pinned_hotkey: String,
+    /// Secondary addons in the order the user wants them loaded in
+    secondary_order: Vec<String>,
+    /// Whether to use the light theme instead of the dark one
+    light_theme: bool,
+    /// The source port chosen by the user. Otherwise, it is found from the
+    /// selected build.
+    source_port: Option<SourcePort>,
+    /// Addon to ask which optional files to load for
+    optional_prompt: Option<String>,
+    /// Whether to show where to get the IWAD
+    iwad_help_open: bool,
+    /// The game the IWAD at the path is for, so the IWAD isn't read every frame
+    iwad_kind: Option<(String, Option<IwadKind>)>,
+    /// Whether to show the command which would be launched
+    command_preview_open: bool,
+    discovery: Option<PendingDiscovery>,
+    profiles: Vec<Profile>,
+    /// Index of the profile which was last loaded or saved
+    current_profile: Option<usize>,
+    /// Name for a new profile
+    profile_name: String,
+    /// GZDoom, if it was launched and is still running
+    running: Option<RunningGame>,
+    /// GZDoom, if it was launched with "quit on launch" and is starting up
+    watching: Option<WatchedGame>,
+    /// What GZDoom has written to its standard output and error
+    game_log: Arc<Mutex<String>>,
+    log_open: bool,
+    import_text: String,
+    /// A shared loadout pasted by the user
+    loadout_text: String,
+    manifest: String,
+    addon_dir: PathBuf,
+    arg_order: ArgOrder,
+    /// Settings from the addon list
+    list: ListSettings,
+    allow_network: bool,
+    manifest_update: Option<ManifestUpdate>,
+    update_check: Option<PendingUpdateCheck>,
+    /// Look for addon files ignoring case when they don't exist as written
+    ignore_case: bool,
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 struct Persistence {
     gzdoom_build: Option<String>,
     primary_addon: Option<String>,
@@ -97,6 +452,138 @@ struct Persistence {
     exargs: Option<String>,
     config: Option<String>,
     iwad: Option<String>,
+    load_game: Option<String>,
+    remember_load_game: bool,
+    group_primary_by_category: bool,
+    last_command: Option<LaunchCommand>,
+    secondary_height: Option<f32>,
+    exec_scripts: Option<Vec<String>>,
+    backup_config: bool,
+    preflight: bool,
+    quit_on_launch: bool,
+    startup_grace: Option<u64>,
+    use_wine: bool,
+    wine: Option<String>,
+    wine_prefix: Option<String>,
+    launch_dir: Option<String>,
+    recent_builds: Vec<String>,
+    failed_launches: Vec<FailedLaunch>,
+    selected_variants: HashMap<String, String>,
+    selected_maps: HashMap<String, String>,
+    browse_dirs: HashMap<Picker, String>,
+    opt_in_optional: bool,
+    optional_selections: HashMap<String, HashMap<String, bool>>,
+    pinned: Option<Box<Persistence>>,
+    pinned_hotkey: Option<String>,
+    secondary_order: Vec<String>,
+    light_theme: bool,
+    source_port: Option<SourcePort>,
+}
+
+/// GZDoom builds, IWADs and saved games found in the background
+#[derive(Debug, Clone, Default)]
+struct Discovery {
+    builds: Vec<Build>,
+    iwads: Vec<FoundIwad>,
+    warnings: Vec<String>,
+    saves: Option<Box<[SaveGame]>>,
+}
+
+/// A result which a background thread fills in when it is done
+type Shared<T> = Arc<Mutex<Option<T>>>;
+
+#[derive(Debug, Clone)]
+struct PendingDiscovery {
+    /// Filled in by the background thread when it is done
+    result: Shared<Discovery>,
+    /// The GZDoom build path when the search started, so that a path typed
+    /// in since then is kept
+    build_path: String,
+}
+
+/// A newer addon list being downloaded in the background
+#[derive(Debug, Clone)]
+struct PendingUpdateCheck {
+    /// Filled in by the background thread when the download is done
+    result: Shared<Result<Option<ManifestUpdate>, String>>,
+}
+
+/// Named settings, which are saved in a file next to the addon list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Profile {
+    name: String,
+    settings: Persistence,
+    /// The theme to switch to when the profile is loaded. The global theme
+    /// is kept if this isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    light_theme: Option<bool>,
+}
+
+/// Read the saved profiles. There are none if the file doesn't exist yet.
+fn load_profiles(path: &Path) -> Result<Vec<Profile>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Could not read the profiles in {}:\n{}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Could not read the profiles in {}:\n{}", path.display(), e)),
+    }
+}
+
+fn save_profiles(path: &Path, profiles: &[Profile]) -> Result<(), String> {
+    let contents = serde_yaml::to_string(profiles).map_err(|e| e.to_string())?;
+    fs::write(path, contents)
+        .map_err(|e| format!("Could not save the profiles to {}:\n{}", path.display(), e))
+}
+
+/// A GZDoom process which is waited for in the background
+#[derive(Debug, Clone)]
+struct RunningGame {
+    /// Filled in by the background thread when GZDoom exits, with its
+    /// output from this run
+    result: Shared<io::Result<(ExitStatus, String)>>,
+}
+
+/// A launch command which is being tried with `-norun` in the background, and
+/// is launched if that works
+#[derive(Debug, Clone)]
+struct PendingPreflight {
+    /// Filled in by the background thread when GZDoom has exited
+    result: Shared<io::Result<Preflight>>,
+    command: LaunchCommand,
+    build: GZDoomBuildSelection,
+    /// The settings it was prepared with, kept if it fails
+    settings: Persistence,
+}
+
+/// GZDoom, launched with "quit on launch", which is watched in the background
+/// until it has been running for the grace period
+#[derive(Debug, Clone)]
+struct WatchedGame {
+    /// Filled in by the background thread once GZDoom has been running for
+    /// the grace period, or has exited
+    result: Shared<io::Result<Startup>>,
+    command: LaunchCommand,
+    build: GZDoomBuildSelection,
+    /// The settings it was launched with, kept if it crashes
+    settings: Persistence,
+}
+
+/// A launch which failed, and the settings it was tried with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FailedLaunch {
+    error: String,
+    settings: Persistence,
+}
+
+impl FailedLaunch {
+    fn summary(&self) -> String {
+        let build = Path::new(self.settings.gzdoom_build.as_deref().unwrap_or_default())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let primary = self.settings.primary_addon.as_deref().unwrap_or("No primary addon");
+        format!("{} with {}", primary, build)
+    }
 }
 
 macro_rules! persist_item {
@@ -115,22 +602,85 @@ macro_rules! persist_item {
 }
 
 impl Persistence {
+    /// The settings the user sets, without what is remembered as the
+    /// launcher is used, like the history and the size of the addon list
+    fn user_settings(&self) -> Persistence {
+        Persistence {
+            last_command: None,
+            secondary_height: None,
+            recent_builds: vec![],
+            failed_launches: vec![],
+            browse_dirs: HashMap::new(),
+            ..self.clone()
+        }
+    }
     fn save(&self, storage: &mut dyn Storage) {
         persist_item!(storage, self.gzdoom_build);
         persist_item!(storage, self.primary_addon);
-        persist_item!(storage, self.gzdoom_build);
-        // TODO: Use Iterator::intersperse when it's stable
-        let secondary_addons = self.secondary_addons.as_ref()
-            .map(|v| v.iter()
-                .map(|kv| {
-                    let mut kv = kv.clone();
-                    kv.push('\n');
-                    kv
-                }).collect::<String>());
+        let secondary_addons = self.secondary_addons.as_ref().map(|names| names.join("\n"));
         persist_item!(storage, secondary_addons);
         persist_item!(storage, self.exargs);
         persist_item!(storage, self.config);
         persist_item!(storage, self.iwad);
+        persist_item!(storage, self.load_game);
+        storage.set_string("remember_load_game", self.remember_load_game.to_string());
+        storage.set_string(
+            "group_primary_by_category",
+            self.group_primary_by_category.to_string(),
+        );
+        storage.set_string(
+            "last_command",
+            serde_json::to_string(&self.last_command).unwrap_or_default(),
+        );
+        let secondary_height = self.secondary_height.map(|h| h.to_string());
+        persist_item!(storage, secondary_height);
+        let exec_scripts = self.exec_scripts.as_ref().map(|scripts| scripts.join("\n"));
+        persist_item!(storage, exec_scripts);
+        storage.set_string("backup_config", self.backup_config.to_string());
+        storage.set_string("preflight", self.preflight.to_string());
+        storage.set_string("quit_on_launch", self.quit_on_launch.to_string());
+        let startup_grace = self.startup_grace.map(|grace| grace.to_string());
+        persist_item!(storage, startup_grace);
+        storage.set_string("use_wine", self.use_wine.to_string());
+        persist_item!(storage, self.wine);
+        persist_item!(storage, self.wine_prefix);
+        persist_item!(storage, self.launch_dir);
+        storage.set_string(
+            "recent_builds",
+            serde_json::to_string(&self.recent_builds).unwrap_or_default(),
+        );
+        storage.set_string(
+            "failed_launches",
+            serde_json::to_string(&self.failed_launches).unwrap_or_default(),
+        );
+        storage.set_string(
+            "selected_variants",
+            serde_json::to_string(&self.selected_variants).unwrap_or_default(),
+        );
+        storage.set_string(
+            "selected_maps",
+            serde_json::to_string(&self.selected_maps).unwrap_or_default(),
+        );
+        storage.set_string(
+            "browse_dirs",
+            serde_json::to_string(&self.browse_dirs).unwrap_or_default(),
+        );
+        storage.set_string("opt_in_optional", self.opt_in_optional.to_string());
+        storage.set_string(
+            "optional_selections",
+            serde_json::to_string(&self.optional_selections).unwrap_or_default(),
+        );
+        storage.set_string(
+            "pinned",
+            serde_json::to_string(&self.pinned).unwrap_or_default(),
+        );
+        persist_item!(storage, self.pinned_hotkey);
+        storage.set_string("secondary_order", self.secondary_order.join("\n"));
+        storage.set_string("light_theme", self.light_theme.to_string());
+        storage.set_string(
+            "source_port",
+            serde_json::to_string(&self.source_port).unwrap_or_default(),
+        );
     }
 }
 
@@ -169,6 +719,45 @@ impl From<&AddonManager> for Persistence {
                 }
                 .clone(),
             ),
+            load_game: (v.remember_load_game && !v.load_game.is_empty())
+                .then(|| v.load_game.clone()),
+            remember_load_game: v.remember_load_game,
+            group_primary_by_category: v.group_primary_by_category,
+            last_command: v.last_command.clone(),
+            secondary_height: Some(v.secondary_height),
+            exec_scripts: match v.exec_scripts.len() {
+                0 => None,
+                _ => Some(v.exec_scripts.clone()),
+            },
+            backup_config: v.backup_config,
+            preflight: v.preflight,
+            quit_on_launch: v.quit_on_launch,
+            startup_grace: Some(v.startup_grace),
+            use_wine: v.use_wine,
+            wine: match v.wine.len() {
+                0 => None,
+                _ => Some(v.wine.clone()),
+            },
+            wine_prefix: match v.wine_prefix.len() {
+                0 => None,
+                _ => Some(v.wine_prefix.clone()),
+            },
+            launch_dir: match v.launch_dir.len() {
+                0 => None,
+                _ => Some(v.launch_dir.clone()),
+            },
+            recent_builds: v.recent_builds.clone(),
+            failed_launches: v.failed_launches.clone(),
+            selected_variants: v.selected_variants.clone(),
+            selected_maps: v.selected_maps.clone(),
+            browse_dirs: v.browse_dirs.clone(),
+            opt_in_optional: v.opt_in_optional,
+            optional_selections: v.optional_selections.clone(),
+            pinned: v.pinned.clone(),
+            pinned_hotkey: Some(v.pinned_hotkey.clone()),
+            secondary_order: v.secondary_order.clone(),
+            light_theme: v.light_theme,
+            source_port: v.source_port,
         }
     }
 }
@@ -179,10 +768,109 @@ impl From<&dyn Storage> for Persistence {
         let primary_addon = storage.get_string("primary_addon");
         let secondary_addons = storage
             .get_string("secondary_addons")
-            .map(|s| s.split(['\n']).map(str::to_string).collect());
+            .map(|s| {
+                s.split('\n')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
         let exargs = storage.get_string("exargs");
         let config = storage.get_string("config");
         let iwad = storage.get_string("iwad");
+        let load_game = storage.get_string("load_game");
+        let remember_load_game = storage
+            .get_string("remember_load_game")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let group_primary_by_category = storage
+            .get_string("group_primary_by_category")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let last_command = storage
+            .get_string("last_command")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .flatten();
+        let secondary_height = storage
+            .get_string("secondary_height")
+            .and_then(|s| s.parse().ok());
+        let exec_scripts = storage.get_string("exec_scripts").map(|s| {
+            s.split('\n')
+                .filter(|script| !script.is_empty())
+                .map(str::to_string)
+                .collect()
+        });
+        let backup_config = storage
+            .get_string("backup_config")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let preflight = storage
+            .get_string("preflight")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let quit_on_launch = storage
+            .get_string("quit_on_launch")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let startup_grace = storage
+            .get_string("startup_grace")
+            .and_then(|s| s.parse().ok());
+        let use_wine = storage
+            .get_string("use_wine")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let wine = storage.get_string("wine").filter(|s| !s.is_empty());
+        let wine_prefix = storage.get_string("wine_prefix").filter(|s| !s.is_empty());
+        let launch_dir = storage.get_string("launch_dir").filter(|s| !s.is_empty());
+        let recent_builds = storage
+            .get_string("recent_builds")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let failed_launches = storage
+            .get_string("failed_launches")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let selected_variants = storage
+            .get_string("selected_variants")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let selected_maps = storage
+            .get_string("selected_maps")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let browse_dirs = storage
+            .get_string("browse_dirs")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let opt_in_optional = storage
+            .get_string("opt_in_optional")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let optional_selections = storage
+            .get_string("optional_selections")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let pinned = storage
+            .get_string("pinned")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .flatten();
+        let pinned_hotkey = storage.get_string("pinned_hotkey").filter(|s| !s.is_empty());
+        let secondary_order = storage
+            .get_string("secondary_order")
+            .map(|s| {
+                s.split('\n')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let light_theme = storage
+            .get_string("light_theme")
+            .map(|s| s == "true")
+            .unwrap_or(false);
+        let source_port = storage
+            .get_string("source_port")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .flatten();
         Self {
             gzdoom_build,
             primary_addon,
@@ -190,13 +878,148 @@ impl From<&dyn Storage> for Persistence {
             exargs,
             config,
             iwad,
+            load_game,
+            remember_load_game,
+            group_primary_by_category,
+            last_command,
+            secondary_height,
+            exec_scripts,
+            backup_config,
+            preflight,
+            quit_on_launch,
+            startup_grace,
+            use_wine,
+            wine,
+            wine_prefix,
+            launch_dir,
+            recent_builds,
+            failed_launches,
+            selected_variants,
+            selected_maps,
+            browse_dirs,
+            opt_in_optional,
+            optional_selections,
+            pinned,
+            pinned_hotkey,
+            secondary_order,
+            light_theme,
+            source_port,
         }
     }
 }
 
+/// Describe the addons which can't be loaded because files are missing, if
+/// there are any
+fn incomplete_addons_message(incomplete: &[(String, Vec<String>)]) -> Option<String> {
+    if incomplete.is_empty() {
+        return None;
+    }
+    let addons: Vec<String> = incomplete
+        .iter()
+        .map(|(name, files)| format!("{}: {}", name, files.join(", ")))
+        .collect();
+    Some(format!(
+        "Some addons can't be loaded, because files are missing:\n{}",
+        addons.join("\n")
+    ))
+}
+
+/// Describe the addon files which don't match their checksums, if there are
+/// any
+fn checksums_message(mismatched: &[(String, String)]) -> Option<String> {
+    if mismatched.is_empty() {
+        return None;
+    }
+    let files: Vec<String> = mismatched
+        .iter()
+        .map(|(name, file)| format!("{}: {}", name, file))
+        .collect();
+    Some(format!(
+        "Some addon files don't match their checksums, and may be corrupt. \
+        Download them again before playing:\n{}",
+        files.join("\n")
+    ))
+}
+
+/// Describe the addons which were selected last time, but are no longer in
+/// the addon list, if there are any.
+fn missing_addons_message(config: &Persistence, addons: &AddonMap) -> Option<String> {
+    let missing: Vec<&str> = config
+        .primary_addon
+        .iter()
+        .chain(config.secondary_addons.iter().flatten())
+        .map(String::as_str)
+        .filter(|name| !name.is_empty() && !addons.contains_key(*name))
+        .collect();
+    match missing.len() {
+        0 => None,
+        1 => Some(format!(
+            "1 previously selected addon is no longer available:\n{}",
+            missing[0]
+        )),
+        count => Some(format!(
+            "{} previously selected addons are no longer available:\n{}",
+            count,
+            missing.join("\n")
+        )),
+    }
+}
+
+/// Describe the addons which were selected last time, but don't work on this
+/// platform, if there are any. They are deselected.
+fn unsupported_addons_message(config: &Persistence, addons: &AddonMap) -> Option<String> {
+    let unsupported: Vec<&str> = config
+        .primary_addon
+        .iter()
+        .chain(config.secondary_addons.iter().flatten())
+        .map(String::as_str)
+        .filter(|name| !supported_addon(addons, name))
+        .collect();
+    match unsupported.len() {
+        0 => None,
+        1 => Some(format!(
+            "1 previously selected addon doesn't work on {}, and was deselected:\n{}",
+            env::consts::OS,
+            unsupported[0]
+        )),
+        count => Some(format!(
+            "{} previously selected addons don't work on {}, and were deselected:\n{}",
+            count,
+            env::consts::OS,
+            unsupported.join("\n")
+        )),
+    }
+}
+
+/// Whether the addon can be selected on this platform. Addons which aren't in
+/// the addon list are reported separately.
+fn supported_addon(addons: &AddonMap, name: &str) -> bool {
+    addons.get(name).map_or(true, AddonSpecification::supported_here)
+}
+
+/// Sort primary addon names alphabetically, or by category and then
+/// alphabetically. "None" is always first.
+fn sort_primary_addons(names: &mut [String], addons: &AddonMap, by_category: bool) {
+    let category = |name: &String| {
+        let category = addons.get(name).and_then(|addon| addon.category.as_deref());
+        // Uncategorized addons go last
+        (category.is_none(), category)
+    };
+    names.sort_by(|a, b| {
+        use std::cmp::Ordering::*;
+        match (a.as_str(), b.as_str()) {
+            ("None", _) => Less,
+            (_, "None") => Greater,
+            _ if by_category => category(a).cmp(&category(b)).then_with(|| a.cmp(b)),
+            _ => a.cmp(b),
+        }
+    });
+}
+
 impl AddonManager {
     pub fn new(
         addons: AddonMap,
+        builds: Box<[Build]>,
         config: Option<Persistence>,
     ) -> AddonManager {
         let mut primary_addons: Box<[String]> = iter::once(String::from("None"))
@@ -207,14 +1030,107 @@ impl AddonManager {
                     .map(|(name, _addon)| name.clone()),
             )
             .collect();
-        primary_addons.sort_by(|a, b| {
-            use std::cmp::Ordering::*;
-            match (a.as_str(), b.as_str()) {
-                ("None", _) => Less,
-                (_, "None") => Greater,
-                _ => a.cmp(b),
-            }
+        let group_primary_by_category = config
+            .as_ref()
+            .map(|config| config.group_primary_by_category)
+            .unwrap_or(false);
+        sort_primary_addons(&mut primary_addons, &addons, group_primary_by_category);
+        let last_command = config.as_ref().and_then(|config| config.last_command.clone());
+        let secondary_height = config
+            .as_ref()
+            .and_then(|config| config.secondary_height)
+            .unwrap_or(SECONDARY_HEIGHT)
+            .clamp(MIN_SECONDARY_HEIGHT, MAX_SECONDARY_HEIGHT);
+        let exec_scripts = config
+            .as_ref()
+            .and_then(|config| config.exec_scripts.clone())
+            .unwrap_or_default();
+        let popup = config.as_ref().and_then(|config| {
+            let messages: Vec<String> = missing_addons_message(config, &addons)
+                .into_iter()
+                .chain(unsupported_addons_message(config, &addons))
+                .collect();
+            (!messages.is_empty()).then(|| messages.join("\n\n"))
         });
+        let backup_config = config
+            .as_ref()
+            .map(|config| config.backup_config)
+            .unwrap_or(false);
+        let preflight = config
+            .as_ref()
+            .map(|config| config.preflight)
+            .unwrap_or(false);
+        let quit_on_launch = config
+            .as_ref()
+            .map(|config| config.quit_on_launch)
+            .unwrap_or(false);
+        let startup_grace = config
+            .as_ref()
+            .and_then(|config| config.startup_grace)
+            .unwrap_or(STARTUP_GRACE)
+            .min(MAX_STARTUP_GRACE);
+        let use_wine = config
+            .as_ref()
+            .map(|config| config.use_wine)
+            .unwrap_or(false);
+        let wine = config
+            .as_ref()
+            .and_then(|config| config.wine.clone())
+            .unwrap_or_else(|| String::from(WINE));
+        let wine_prefix = config
+            .as_ref()
+            .and_then(|config| config.wine_prefix.clone())
+            .unwrap_or_default();
+        let launch_dir = config
+            .as_ref()
+            .and_then(|config| config.launch_dir.clone())
+            .unwrap_or_default();
+        // Builds which have been removed since are left out
+        let recent_builds = config
+            .as_ref()
+            .map(|config| config.recent_builds.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(usable_build)
+            .collect();
+        let failed_launches = config
+            .as_ref()
+            .map(|config| config.failed_launches.clone())
+            .unwrap_or_default();
+        let selected_variants = config
+            .as_ref()
+            .map(|config| config.selected_variants.clone())
+            .unwrap_or_default();
+        let selected_maps = config
+            .as_ref()
+            .map(|config| config.selected_maps.clone())
+            .unwrap_or_default();
+        let browse_dirs = config
+            .as_ref()
+            .map(|config| config.browse_dirs.clone())
+            .unwrap_or_default();
+        let opt_in_optional = config
+            .as_ref()
+            .map(|config| config.opt_in_optional)
+            .unwrap_or(false);
+        let optional_selections = config
+            .as_ref()
+            .map(|config| config.optional_selections.clone())
+            .unwrap_or_default();
+        let pinned = config.as_ref().and_then(|config| config.pinned.clone());
+        let pinned_hotkey = config
+            .as_ref()
+            .and_then(|config| config.pinned_hotkey.clone())
+            .unwrap_or_else(|| String::from(PINNED_HOTKEY));
+        let secondary_order = config
+            .as_ref()
+            .map(|config| config.secondary_order.clone())
+            .unwrap_or_default();
+        let light_theme = config
+            .as_ref()
+            .map(|config| config.light_theme)
+            .unwrap_or(false);
+        let source_port = config.as_ref().and_then(|config| config.source_port);
         let primary_addons = primary_addons;
         let mut secondary_addons: Box<[String]> = addons
             .iter()
@@ -225,8 +1141,16 @@ impl AddonManager {
         let secondary_addons = secondary_addons;
         let selected_secondary_addons: Box<[bool]> =
             Box::from_iter(secondary_addons.iter().map(|_| true));
-        let builds: Box<[String]> = Box::from([]);
         let iwads: Box<[String]> = Box::from([]);
+        let remember_load_game = config
+            .as_ref()
+            .map(|config| config.remember_load_game)
+            .unwrap_or(false);
+        let load_game = config
+            .as_ref()
+            .and_then(|config| config.load_game.clone())
+            .filter(|_| remember_load_game)
+            .unwrap_or_default();
 
         // STEP: Load configuration
         let (
@@ -247,6 +1171,7 @@ impl AddonManager {
                             .as_ref()
                             .map(|s| s.eq(pa))
                             .unwrap_or(false)
+                            && supported_addon(&addons, pa)
                     })
                     .unwrap_or(0);
                 let selected_secondary_addons = secondary_addons
@@ -255,30 +1180,14 @@ impl AddonManager {
                         config
                             .secondary_addons
                             .as_ref()
-                            .map(|addons| addons.iter().find_map(
-                                |asa| (sa == asa).then_some(true)
+                            .map(|selected| selected.iter().find_map(
+                                |asa| (sa == asa).then_some(supported_addon(&addons, sa))
                                 ).unwrap_or(false)
                             ).unwrap_or(true)
                     })
                     .collect();
-                let selected_gzdoom_build = match builds.len() {
-                    0 => GZDoomBuildSelection::FullPath(
-                        config.gzdoom_build.clone().unwrap_or_default(),
-                    ),
-                    1 => GZDoomBuildSelection::Single,
-                    _ => GZDoomBuildSelection::ListIndex(
-                        builds
-                            .iter()
-                            .position(|build| {
-                                config
-                                    .gzdoom_build
-                                    .as_ref()
-                                    .map(|gzd| gzd.as_str() == build)
-                                    .unwrap_or(false)
-                            })
-                            .unwrap_or_default(),
-                    ),
-                };
+                let selected_gzdoom_build =
+                    build_selection(&builds, config.gzdoom_build.as_deref().unwrap_or_default());
                 let selected_iwad = match iwads.len() {
                     0 => GZDoomBuildSelection::FullPath(config.iwad.clone().unwrap_or_default()),
                     1 => GZDoomBuildSelection::Single,
@@ -309,7 +1218,11 @@ impl AddonManager {
             .unwrap_or((
                 0,
                 selected_secondary_addons,
-                GZDoomBuildSelection::default(),
+                match builds.len() {
+                    0 => GZDoomBuildSelection::default(),
+                    1 => GZDoomBuildSelection::Single,
+                    _ => GZDoomBuildSelection::ListIndex(0),
+                },
                 GZDoomBuildSelection::default(),
                 Default::default(),
                 Default::default(),
@@ -326,138 +1239,1906 @@ impl AddonManager {
             selected_iwad,
             exargs,
             config,
+            load_game,
+            remember_load_game,
+            group_primary_by_category,
+            last_command,
+            secondary_height,
+            exec_scripts,
+            popup,
+            backup_config,
+            preflight,
+            quit_on_launch,
+            startup_grace,
+            use_wine,
+            wine,
+            wine_prefix,
+            launch_dir,
+            recent_builds,
+            failed_launches,
+            selected_variants,
+            selected_maps,
+            browse_dirs,
+            opt_in_optional,
+            optional_selections,
+            pinned,
+            pinned_hotkey,
+            secondary_order,
+            light_theme,
+            source_port,
             ..Default::default()
         }
     }
-    fn gzdoom_build(&self) -> &str {
-        match &self.selected_gzdoom_build {
-            GZDoomBuildSelection::Single => self
-                .builds
-                .get(0)
-                .map(String::as_str)
-                .expect("How did this happen?!"),
-            GZDoomBuildSelection::ListIndex(index) => {
-                self.builds.get(*index).map(String::as_str).unwrap_or("")
-            }
-            GZDoomBuildSelection::FullPath(path) => path.as_str(),
+    /// Read the addon manifest again, keeping the current selections where
+    /// the addons still exist
+    fn reload_addons(&mut self) -> Result<(), String> {
+        let list = addon::get_addons(Some(&self.manifest), &self.addon_dir, self.ignore_case)
+            .map_err(|e| e.to_string())?;
+        let data = Persistence::from(&*self);
+        self.rebuild(list.addons, data);
+        self.list = list.settings;
+        list.warnings.into_iter().for_each(|warning| self.show_message(warning));
+        if let Some(message) = incomplete_addons_message(&list.incomplete) {
+            self.show_message(message);
         }
+        Ok(())
     }
-    fn iwad(&self) -> &str {
-        match &self.selected_iwad {
-            GZDoomBuildSelection::Single => self
-                .iwads
-                .get(0)
-                .map(String::as_str)
-                .expect("How did this happen?!"),
-            GZDoomBuildSelection::ListIndex(index) => {
-                self.iwads.get(*index).map(String::as_str).unwrap_or("")
+    /// Reload the addons, and say whether it worked
+    fn reload_and_report(&mut self) {
+        match self.reload_addons() {
+            Ok(()) => self.show_message(String::from("Reloaded addons")),
+            Err(e) => self.show_message(e),
+        }
+    }
+    /// Start over with the given addons and settings, keeping what isn't part
+    /// of the settings
+    fn rebuild(&mut self, addons: AddonMap, data: Persistence) {
+        let mut rebuilt = AddonManager::new(addons, self.builds.clone(), Some(data));
+        rebuilt.list = mem::take(&mut self.list);
+        rebuilt.allow_network = self.allow_network;
+        rebuilt.ignore_case = self.ignore_case;
+        rebuilt.manifest = mem::take(&mut self.manifest);
+        rebuilt.addon_dir = mem::take(&mut self.addon_dir);
+        rebuilt.arg_order = mem::take(&mut self.arg_order);
+        rebuilt.secondary_filter = mem::take(&mut self.secondary_filter);
+        rebuilt.search_descriptions = self.search_descriptions;
+        rebuilt.load_game = mem::take(&mut self.load_game);
+        rebuilt.import_text = mem::take(&mut self.import_text);
+        rebuilt.loadout_text = mem::take(&mut self.loadout_text);
+        rebuilt.last_command = self.last_command.take();
+        rebuilt.recent_builds = mem::take(&mut self.recent_builds);
+        rebuilt.saved_settings = self.saved_settings.take();
+        rebuilt.failed_launches = mem::take(&mut self.failed_launches);
+        rebuilt.update_check = self.update_check.take();
+        rebuilt.preflighting = self.preflighting.take();
+        rebuilt.saves = self.saves.take();
+        let iwad = String::from(self.iwad());
+        rebuilt.iwads = mem::take(&mut self.iwads);
+        rebuilt.iwad_games = mem::take(&mut self.iwad_games);
+        rebuilt.selected_iwad = iwad_selection(&rebuilt.iwads, &iwad);
+        rebuilt.discovery = self.discovery.take();
+        rebuilt.running = self.running.take();
+        rebuilt.watching = self.watching.take();
+        rebuilt.profiles = mem::take(&mut self.profiles);
+        rebuilt.current_profile = self.current_profile;
+        rebuilt.profile_name = mem::take(&mut self.profile_name);
+        rebuilt.game_log = Arc::clone(&self.game_log);
+        rebuilt.log_open = self.log_open;
+        // Messages which haven't been seen yet go before the new ones
+        let message = rebuilt.popup.take();
+        rebuilt.popup = self.popup.take();
+        if let Some(message) = message {
+            rebuilt.show_message(message);
+        }
+        *self = rebuilt;
+    }
+    /// The selected addons, in a form which can be shared
+    fn loadout(&self) -> Loadout {
+        let names = self.selected_addon_names();
+        let primary = self
+            .primary_addons
+            .get(self.selected_primary_addon)
+            .filter(|name| self.addons.contains_key(*name));
+        Loadout {
+            primary: primary.cloned(),
+            secondary: names
+                .iter()
+                .filter(|name| Some(**name) != primary)
+                .map(|name| (*name).clone())
+                .collect(),
+            variants: names
+                .iter()
+                .filter_map(|&name| Some((name.clone(), self.selected_variants.get(name)?.clone())))
+                .collect(),
+            map: self.chosen_map().map(String::from),
+        }
+    }
+    /// Select the addons of a shared loadout. Returns warnings about what
+    /// couldn't be used.
+    fn apply_loadout(&mut self, loadout: &Loadout) -> Vec<String> {
+        let (selection, mut warnings) = loadout.resolve(&self.addons);
+        let mut data = Persistence::from(&*self);
+        data.primary_addon = selection.primary;
+        data.secondary_addons = Some(selection.secondary);
+        loadout.variants.iter().for_each(|(name, variant)| {
+            let known = self
+                .addons
+                .get(name)
+                .map_or(false, |addon| addon.variant_names().contains(&variant));
+            match known {
+                true => {
+                    data.selected_variants.insert(name.clone(), variant.clone());
+                }
+                false => warnings.push(format!("Unknown variant of {}: {}", name, variant)),
+            }
+        });
+        if let (Some(primary), Some(map)) = (&data.primary_addon, &loadout.map) {
+            let known = self
+                .addons
+                .get(primary)
+                .and_then(|addon| addon.maps.as_ref())
+                .map_or(false, |maps| maps.contains(map));
+            match known {
+                true => {
+                    data.selected_maps.insert(primary.clone(), map.clone());
+                }
+                false => warnings.push(format!("Unknown map of {}: {}", primary, map)),
             }
-            GZDoomBuildSelection::FullPath(path) => path.as_str(),
         }
+        self.rebuild(self.addons.clone(), data);
+        warnings
     }
-    fn files_for_addon<'a>(&'a self, addon: Option<&'a AddonSpecification>) -> Vec<&'a String> {
-        match addon {
-            Some(addon) => {
-                let mut files = vec![];
-                for file in &addon.required {
-                    files.push(file);
+    fn loadout_sharing(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Share loadout")
+                .on_hover_text(
+                    "Copy the selected addons, to share with someone who has the same addon list",
+                )
+                .clicked()
+            {
+                let encoded = self.loadout().encode();
+                ui.output_mut(|output| output.copied_text = encoded);
+                self.show_message(String::from("The loadout was copied to the clipboard."));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Paste a shared loadout:");
+            ui.text_edit_singleline(&mut self.loadout_text);
+            let pasted = !self.loadout_text.trim().is_empty();
+            if ui.add_enabled(pasted, egui::Button::new("Import loadout")).clicked() {
+                match Loadout::decode(&self.loadout_text) {
+                    Ok(loadout) => {
+                        let warnings = self.apply_loadout(&loadout);
+                        self.loadout_text.clear();
+                        self.show_message(match warnings.is_empty() {
+                            true => String::from("The loadout was imported."),
+                            false => format!(
+                                "The loadout was imported, except for:\n{}",
+                                warnings.join("\n")
+                            ),
+                        });
+                    }
+                    Err(e) => self.show_message(e),
                 }
-                if let Some(optional) = &addon.optional {
-                    for file in optional {
-                        if File::open(file).is_ok() {
-                            files.push(file);
-                        }
+            }
+        });
+    }
+    /// Look for GZDoom builds, IWADs and saved games without holding up the
+    /// window. IWADs are looked for in the usual folders, unless there is a
+    /// pattern for them.
+    fn start_discovery(
+        &mut self,
+        builds_file: Option<PathBuf>,
+        gzdoom_glob: Option<String>,
+        iwad_glob: Option<String>,
+    ) {
+        let result = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&result);
+        thread::spawn(move || {
+            let (mut builds, mut warnings) = match builds_file {
+                Some(fname) => builds::load_builds(&fname),
+                None => (vec![], vec![]),
+            };
+            // Builds from the file come first, since they may have labels
+            if let Some(pattern) = gzdoom_glob {
+                let (found, glob_warnings) = builds::glob_builds(&pattern);
+                warnings.extend(glob_warnings);
+                found.into_iter().for_each(|build| {
+                    if !builds.iter().any(|listed| listed.path == build.path) {
+                        builds.push(build);
                     }
+                });
+            }
+            let iwads = match iwad_glob {
+                Some(pattern) => {
+                    let (found, glob_warnings) = iwads::glob_iwads(&pattern);
+                    warnings.extend(glob_warnings);
+                    found
                 }
-                files
+                None => iwads::find_iwads(&iwads::iwad_dirs()),
+            };
+            let saves = savegame::find_saves();
+            if let Ok(mut slot) = slot.lock() {
+                *slot = Some(Discovery { builds, iwads, warnings, saves });
             }
-            None => vec![],
+        });
+        self.discovery = Some(PendingDiscovery {
+            result,
+            build_path: String::from(self.gzdoom_build()),
+        });
+    }
+    /// Use what was found in the background, if it's done. Returns whether
+    /// it is still being looked for.
+    fn poll_discovery(&mut self) -> bool {
+        let pending = match &self.discovery {
+            Some(pending) => pending,
+            None => return false,
+        };
+        let found = pending.result.lock().ok().and_then(|mut result| result.take());
+        let found = match found {
+            Some(found) => found,
+            // The thread is gone without leaving anything
+            None if Arc::strong_count(&pending.result) == 1 => {
+                self.discovery = None;
+                return false;
+            }
+            None => return true,
+        };
+        let build_path = pending.build_path.clone();
+        self.discovery = None;
+        self.use_discovery(found, &build_path);
+        false
+    }
+    /// Check whether GZDoom has exited. Returns whether it is still running.
+    fn poll_running(&mut self) -> bool {
+        let running = match &self.running {
+            Some(running) => running,
+            None => return false,
+        };
+        let exited = running.result.lock().ok().and_then(|mut result| result.take());
+        match exited {
+            Some(Ok((status, output))) if !status.success() => {
+                self.log_open = true;
+                let error = LaunchError::ExitedWithError(failure_report(status, &output));
+                self.show_message(error.to_string());
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => self.show_message(LaunchError::FailedWait(Box::from(e)).to_string()),
+            None if Arc::strong_count(&running.result) > 1 => return true,
+            // The thread is gone without leaving anything
+            None => {}
         }
+        self.running = None;
+        false
     }
-    fn primary_addon<'a>(&'a self) -> Vec<&'a String> {
-        let name = self
-            .primary_addons
-            .get(self.selected_primary_addon)
-            .map(String::as_str)
-            .unwrap_or("");
-        let addon = self.addons.get(name);
-        self.files_for_addon(addon)
+    /// Check whether GZDoom, launched with "quit on launch", has started
+    /// properly. Returns whether it is still being watched.
+    fn poll_watching(&mut self) -> bool {
+        let watching = match self.watching.take() {
+            Some(watching) => watching,
+            None => return false,
+        };
+        let startup = watching.result.lock().ok().and_then(|mut result| result.take());
+        let error = match startup {
+            Some(Ok(Startup::Running | Startup::Exited)) => {
+                self.remember_build(&watching.build);
+                self.last_command = Some(watching.command);
+                self.quit_requested = true;
+                return false;
+            }
+            Some(Ok(Startup::Crashed(output))) => LaunchError::CrashedOnStartup(output),
+            Some(Err(e)) => LaunchError::LaunchFailed(watching.command.program.clone(), e),
+            None if Arc::strong_count(&watching.result) > 1 => {
+                self.watching = Some(watching);
+                return true;
+            }
+            // The thread is gone without leaving anything
+            None => return false,
+        };
+        self.launch_failed(error, watching.settings);
+        false
     }
-    fn secondary_addons<'a>(&'a self) -> Vec<&'a String> {
-        let addons: Vec<String> = self
-            .secondary_addons
-            .iter()
-            .zip(self.selected_secondary_addons.iter())
-            .filter_map(|(addon, &selected)| if selected { Some(addon) } else { None })
-            .cloned()
+    fn use_discovery(&mut self, found: Discovery, build_path: &str) {
+        let path = String::from(self.gzdoom_build());
+        self.builds = found.builds.into_boxed_slice();
+        // Keep a path which was typed in while the builds were being loaded
+        let typed = path != build_path && !self.builds.iter().any(|build| build.path == path);
+        if !typed {
+            self.selected_gzdoom_build = build_selection(&self.builds, &path);
+        }
+        self.use_iwads(found.iwads);
+        self.saves = found.saves;
+        found
+            .warnings
+            .into_iter()
+            .for_each(|warning| self.show_message(warning));
+    }
+    /// List the found IWADs to choose from. The IWAD which is chosen already
+    /// stays chosen, and is added to the list if it wasn't found.
+    fn use_iwads(&mut self, found: Vec<FoundIwad>) {
+        if found.is_empty() {
+            return;
+        }
+        let path = String::from(self.iwad());
+        let kept = (!path.is_empty() && !found.iter().any(|iwad| iwad.path == path))
+            .then(|| FoundIwad::new(Path::new(&path)));
+        let found: Vec<FoundIwad> = kept.into_iter().chain(found).collect();
+        self.iwad_games = found.iter()
+            .filter_map(|iwad| Some((iwad.path.clone(), iwad.game?)))
             .collect();
-        let mut addon_files = vec![];
-        addons.iter().for_each(|addon| {
-            let addon = self.addons.get(addon);
-            addon_files.extend(self.files_for_addon(addon).into_iter());
+        self.iwads = found.into_iter().map(|iwad| iwad.path).collect();
+        self.selected_iwad = iwad_selection(&self.iwads, &path);
+    }
+    /// Download the addon list in the background, to compare it to the local
+    /// one
+    fn check_for_updates(&mut self) {
+        let url = match &self.list.manifest_url {
+            Some(url) => url.clone(),
+            None => return,
+        };
+        let local = fs::read_to_string(&self.manifest).unwrap_or_default();
+        let manifest = self.manifest.clone();
+        let result = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&result);
+        thread::spawn(move || {
+            let checked = update::check(&url, &manifest, &local);
+            if let Ok(mut slot) = slot.lock() {
+                *slot = Some(checked);
+            }
         });
-        addon_files
+        self.update_check = Some(PendingUpdateCheck { result });
     }
-    fn try_launch<'a>(&'a self) -> Result<(), LaunchError> {
-        let gzdoom = self.gzdoom_build();
-        let iwad = self.iwad();
-        if File::open(&gzdoom).is_err() {
-            return Err(LaunchError::GZDoomBuildNotOpenable);
+    /// Offer to replace the local addon list if the downloaded one is
+    /// different. Returns whether it is still being downloaded.
+    fn poll_update_check(&mut self) -> bool {
+        let pending = match &self.update_check {
+            Some(pending) => pending,
+            None => return false,
+        };
+        let checked = pending.result.lock().ok().and_then(|mut result| result.take());
+        match checked {
+            Some(Ok(Some(update))) => self.manifest_update = Some(update),
+            Some(Ok(None)) => self.show_message(String::from("The addon list is up to date.")),
+            Some(Err(e)) => self.show_message(e),
+            None if Arc::strong_count(&pending.result) > 1 => return true,
+            // The thread is gone without leaving anything
+            None => {}
         }
-        if !is_executable(&gzdoom) {
-            return Err(LaunchError::GZDoomBuildNotExecutable);
+        self.update_check = None;
+        false
+    }
+    /// Replace the local addon list with the downloaded one, keeping a backup
+    fn apply_update(&mut self, update: ManifestUpdate) {
+        let backup = format!("{}.bak", self.manifest);
+        if let Err(e) = fs::copy(&self.manifest, &backup) {
+            self.show_message(format!("Could not back up {} to {}:\n{}", self.manifest, backup, e));
+            return;
         }
-        if File::open(&iwad).is_err() {
+        if let Err(e) = fs::write(&self.manifest, update.contents) {
+            self.show_message(format!("Could not write {}:\n{}", self.manifest, e));
+            return;
+        }
+        match self.reload_addons() {
+            Ok(()) => self.show_message(format!(
+                "The addon list was updated. The old one is in {}.",
+                backup
+            )),
+            Err(e) => self.show_message(e),
+        }
+    }
+    /// Launch, and keep a record of the launch if it fails
+    fn launch(&mut self) {
+        if let Err(e) = self.try_launch() {
+            let settings = self.snapshot();
+            self.launch_failed(e, settings);
+        }
+    }
+    /// Keep the settings a launch failed with, and say why it failed
+    fn launch_failed(&mut self, e: LaunchError, settings: Persistence) {
+        let error = e.to_string();
+        self.failed_launches.insert(0, FailedLaunch { error: error.clone(), settings });
+        self.failed_launches.truncate(MAX_FAILED_LAUNCHES);
+        match e {
+            LaunchError::IWADNotFound => self.iwad_help_open = true,
+            _ => self.show_message(error),
+        }
+    }
+    /// Guidance on getting the IWAD, from the addon list if it has some
+    fn iwad_help(&self) -> IwadHelp {
+        let mut help = self.list.iwad_help.clone().unwrap_or_default();
+        if help.hint.is_none() {
+            help.hint = Some(String::from(IWAD_HINT));
+        }
+        if help.links.is_empty() {
+            help.links = IWAD_LINKS
+                .iter()
+                .map(|(label, url)| IwadLink {
+                    label: String::from(*label),
+                    url: String::from(*url),
+                })
+                .collect();
+        }
+        help
+    }
+    /// Ask for the IWAD, and use it if it is one
+    fn locate_iwad(&mut self) -> bool {
+        match browse(&mut self.browse_dirs, Picker::Iwads, None) {
+            Ok(Some(choice)) if is_iwad(&choice) => {
+                self.selected_iwad =
+                    GZDoomBuildSelection::FullPath(String::from(choice.to_str().unwrap_or("")));
+                true
+            }
+            Ok(Some(choice)) => {
+                self.show_message(not_iwad_message(&choice));
+                false
+            }
+            Ok(None) => false,
+            Err(_) => {
+                self.show_message(String::from("File browser unavailable"));
+                false
+            }
+        }
+    }
+    /// The settings for launching the game as it is set up now, without
+    /// history or the pinned settings
+    fn snapshot(&self) -> Persistence {
+        let mut settings = Persistence::from(self);
+        settings.load_game = Some(self.load_game.clone()).filter(|game| !game.is_empty());
+        settings.last_command = None;
+        settings.recent_builds = vec![];
+        settings.failed_launches = vec![];
+        settings.pinned = None;
+        settings
+    }
+    fn theme(&self) -> Theme {
+        if self.light_theme {
+            Theme::Light
+        } else {
+            Theme::Dark
+        }
+    }
+    fn profiles_path(&self) -> PathBuf {
+        Path::new(&self.manifest).with_file_name(PROFILES)
+    }
+    /// The settings as they are now, with the settings for launching the
+    /// game taken from `settings`. The history, the pinned settings and how
+    /// the window looks are kept.
+    fn with_launch_settings(&self, settings: Persistence) -> Persistence {
+        let current = Persistence::from(self);
+        Persistence {
+            group_primary_by_category: current.group_primary_by_category,
+            last_command: current.last_command,
+            secondary_height: current.secondary_height,
+            recent_builds: current.recent_builds,
+            failed_launches: current.failed_launches,
+            browse_dirs: current.browse_dirs,
+            pinned: current.pinned,
+            pinned_hotkey: current.pinned_hotkey,
+            light_theme: current.light_theme,
+            ..settings
+        }
+    }
+    /// Select everything as it was saved in the profile, and switch to its
+    /// theme if it has one. Addons in the profile which are no longer
+    /// available are reported by `rebuild`.
+    fn apply_profile(&mut self, index: usize) {
+        let (settings, light_theme) = match self.profiles.get(index) {
+            Some(profile) => (self.with_launch_settings(profile.settings.clone()), profile.light_theme),
+            None => return,
+        };
+        let load_game = settings.load_game.clone().unwrap_or_default();
+        let addons = self.addons.clone();
+        self.rebuild(addons, settings);
+        self.load_game = load_game;
+        self.current_profile = Some(index);
+        if let Some(light_theme) = light_theme {
+            self.light_theme = light_theme;
+        }
+    }
+    /// Set the theme the current profile switches to, or use the global
+    /// theme for it if `light_theme` is `None`
+    fn set_profile_theme(&mut self, light_theme: Option<bool>) {
+        if let Some(profile) = self.current_profile.and_then(|index| self.profiles.get_mut(index)) {
+            profile.light_theme = light_theme;
+            if let Some(light_theme) = light_theme {
+                self.light_theme = light_theme;
+            }
+            self.write_profiles();
+        }
+    }
+    /// Save the current selection to the current profile
+    fn save_profile(&mut self) {
+        let settings = self.snapshot();
+        if let Some(profile) = self.current_profile.and_then(|index| self.profiles.get_mut(index)) {
+            profile.settings = settings;
+            self.write_profiles();
+        }
+    }
+    /// Save the current selection as a new profile
+    fn new_profile(&mut self) {
+        let name = self.profile_name.trim().to_string();
+        if name.is_empty() {
+            self.show_message(String::from("The new profile needs a name."));
+            return;
+        }
+        if self.profiles.iter().any(|profile| profile.name == name) {
+            self.show_message(format!("There is already a profile called {}.", name));
+            return;
+        }
+        self.profiles.push(Profile {
+            name,
+            settings: self.snapshot(),
+            light_theme: None,
+        });
+        self.current_profile = Some(self.profiles.len() - 1);
+        self.profile_name.clear();
+        self.write_profiles();
+    }
+    fn delete_profile(&mut self) {
+        if let Some(index) = self.current_profile.take() {
+            if index < self.profiles.len() {
+                self.profiles.remove(index);
+                self.write_profiles();
+            }
+        }
+    }
+    fn write_profiles(&mut self) {
+        if let Err(e) = save_profiles(&self.profiles_path(), &self.profiles) {
+            self.show_message(e);
+        }
+    }
+    /// Launch the pinned settings, without changing what is selected. If
+    /// addons from them are gone, launch the current selection instead.
+    fn launch_pinned(&mut self) {
+        let pinned = match &self.pinned {
+            Some(pinned) => (**pinned).clone(),
+            None => {
+                self.show_message(String::from("Nothing is pinned."));
+                return;
+            }
+        };
+        if let Some(missing) = missing_addons_message(&pinned, &self.addons) {
+            self.show_message(format!(
+                "The pinned settings can't be launched. {}\n\nLaunching the current selection instead.",
+                missing
+            ));
+            self.launch();
+            return;
+        }
+        let mut launcher = self.clone();
+        launcher.popup = None;
+        launcher.rebuild(self.addons.clone(), pinned);
+        launcher.launch();
+        self.last_command = launcher.last_command;
+        self.failed_launches = launcher.failed_launches;
+        self.iwad_help_open = launcher.iwad_help_open;
+        self.quit_requested = launcher.quit_requested;
+        if launcher.running.is_some() {
+            self.running = launcher.running;
+        }
+        if launcher.preflighting.is_some() {
+            self.preflighting = launcher.preflighting;
+        }
+        if launcher.watching.is_some() {
+            self.watching = launcher.watching;
+        }
+        if let Some(message) = launcher.popup {
+            self.show_message(message);
+        }
+    }
+    /// Go back to the settings a failed launch was tried with
+    fn reproduce(&mut self, index: usize) {
+        let settings = match self.failed_launches.get(index) {
+            Some(failed) => self.with_launch_settings(failed.settings.clone()),
+            None => return,
+        };
+        let load_game = settings.load_game.clone().unwrap_or_default();
+        self.rebuild(self.addons.clone(), settings);
+        self.load_game = load_game;
+    }
+    /// Select the secondary addons which the addon list's rules select for
+    /// the selected primary addon. The user can still deselect them.
+    fn apply_rules(&mut self) {
+        let primary = match self.primary_addons.get(self.selected_primary_addon) {
+            Some(primary) => primary,
+            None => return,
+        };
+        let selections = addon::rule_selections(&self.list.rules, &self.addons, primary);
+        self.secondary_addons
+            .iter()
+            .zip(self.selected_secondary_addons.iter_mut())
+            .filter(|(name, _)| selections.contains(name))
+            .for_each(|(_, selected)| *selected = true);
+    }
+    fn regroup_primary_addons(&mut self) {
+        let selected = self.primary_addons.get(self.selected_primary_addon).cloned();
+        sort_primary_addons(
+            &mut self.primary_addons,
+            &self.addons,
+            self.group_primary_by_category,
+        );
+        // Keep the same addon selected after re-sorting
+        self.selected_primary_addon = selected
+            .and_then(|selected| self.primary_addons.iter().position(|pa| pa == &selected))
+            .unwrap_or(0);
+    }
+    /// Add a message to the popup, after any message already in it
+    fn show_message(&mut self, message: String) {
+        self.popup = Some(match self.popup.take() {
+            Some(popup) => format!("{}\n\n{}", popup, message),
+            None => message,
+        });
+    }
+    fn gzdoom_build(&self) -> &str {
+        match &self.selected_gzdoom_build {
+            GZDoomBuildSelection::Single => self
+                .builds
+                .get(0)
+                .map(|build| build.path.as_str())
+                .expect("How did this happen?!"),
+            GZDoomBuildSelection::ListIndex(index) => {
+                self.builds.get(*index).map(|build| build.path.as_str()).unwrap_or("")
+            }
+            GZDoomBuildSelection::FullPath(path) => path.as_str(),
+        }
+    }
+    fn iwad(&self) -> &str {
+        match &self.selected_iwad {
+            GZDoomBuildSelection::Single => self
+                .iwads
+                .get(0)
+                .map(String::as_str)
+                .expect("How did this happen?!"),
+            GZDoomBuildSelection::ListIndex(index) => {
+                self.iwads.get(*index).map(String::as_str).unwrap_or("")
+            }
+            GZDoomBuildSelection::FullPath(path) => path.as_str(),
+        }
+    }
+    fn files_for_addon(&self, name: &str) -> Vec<&String> {
+        match self.loadable_addon(name) {
+            Some(addon) => {
+                let mut files = vec![];
+                for file in &addon.required {
+                    files.push(file);
+                }
+                let variant = self.selected_variants.get(name).map(String::as_str);
+                files.extend(addon.variant_files(variant));
+                if let Some(required_any) = &addon.required_any {
+                    for file in required_any {
+                        if File::open(file).is_ok() {
+                            files.push(file);
+                        }
+                    }
+                }
+                if let Some(optional) = &addon.optional {
+                    for file in optional {
+                        if File::open(file).is_ok() && self.optional_selected(name, addon, file) {
+                            files.push(file);
+                        }
+                    }
+                }
+                files
+            }
+            None => vec![],
+        }
+    }
+    /// The addon, or the always loaded addon, with the name, if it can be
+    /// loaded here
+    fn loadable_addon(&self, name: &str) -> Option<&AddonSpecification> {
+        self.addons
+            .get(name)
+            .or_else(|| self.list.always_load.get(name))
+            .filter(|addon| addon.supported_here() && addon.missing.is_empty())
+    }
+    /// The loaded addons, and then the always loaded addons, in the same
+    /// order as their files
+    fn loadable_addons(&self) -> Vec<&AddonSpecification> {
+        let mut always_loaded: Vec<&String> = self.list.always_load.keys().collect();
+        always_loaded.sort();
+        self.loaded_addon_names()
+            .into_iter()
+            .chain(always_loaded)
+            .filter_map(|name| self.loadable_addon(name))
+            .collect()
+    }
+    /// DeHackEd patches of the loaded addons, in the same order as their
+    /// files
+    fn deh_patches(&self) -> Vec<&String> {
+        self.loadable_addons()
+            .into_iter()
+            .flat_map(|addon| addon.deh.iter().flatten())
+            .collect()
+    }
+    /// Environment variables the loaded addons set. Later addons override
+    /// earlier ones.
+    fn addon_environment(&self) -> Vec<(String, String)> {
+        self.addon_environment_with(&env_var)
+    }
+    /// Like `addon_environment`, but variables in the values are looked up
+    /// with `lookup` instead of in the environment
+    fn addon_environment_with(&self, lookup: &dyn Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+        let mut environment = vec![];
+        self.loadable_addons().into_iter().for_each(|addon| {
+            let mut variables: Vec<(&String, &String)> = addon.env.iter().flatten().collect();
+            variables.sort();
+            variables.into_iter().for_each(|(key, value)| {
+                let value = expand_vars_with(value.as_str().into(), lookup).into_owned();
+                set_variable(&mut environment, key, value);
+            });
+        });
+        environment
+    }
+    /// Arguments the loaded addons add to the command
+    fn addon_arguments(&self) -> Vec<&String> {
+        self.loadable_addons()
+            .into_iter()
+            .flat_map(|addon| addon.args.iter().flatten())
+            .collect()
+    }
+    /// Files of the addons which the addon list says to always load, after
+    /// the selected addons
+    fn always_loaded_files(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.list.always_load.keys().collect();
+        names.sort();
+        names.into_iter().flat_map(|name| self.files_for_addon(name)).collect()
+    }
+    /// Whether optional files are only loaded if they are picked. The addon
+    /// list can override the user's setting.
+    fn optional_opt_in(&self) -> bool {
+        match self.list.optional_files {
+            Some(mode) => mode == OptionalFiles::OptIn,
+            None => self.opt_in_optional,
+        }
+    }
+    /// Whether the user picks which of an addon's optional files to load
+    fn picks_optional(&self, addon: &AddonSpecification) -> bool {
+        self.optional_opt_in() || addon.prompts_for_optional()
+    }
+    /// Whether an optional file which exists will be loaded
+    fn optional_selected(&self, name: &str, addon: &AddonSpecification, file: &str) -> bool {
+        !self.picks_optional(addon) || self.optional_picked(name, file)
+    }
+    /// Whether the user picked an addon's optional file
+    fn optional_picked(&self, name: &str, file: &str) -> bool {
+        self.optional_selections
+            .get(name)
+            .and_then(|files| files.get(file))
+            .copied()
+            .unwrap_or(false)
+    }
+    fn pick_optional(&mut self, name: &str, file: &str, selected: bool) {
+        self.optional_selections
+            .entry(name.to_string())
+            .or_default()
+            .insert(file.to_string(), selected);
+    }
+    /// An addon's optional files which exist
+    fn present_optional_files(&self, name: &str) -> Vec<String> {
+        self.addons
+            .get(name)
+            .and_then(|addon| addon.optional.as_ref())
+            .into_iter()
+            .flatten()
+            .filter(|file| File::open(file).is_ok())
+            .cloned()
+            .collect()
+    }
+    /// Optional files of the selected addons which exist and which the user
+    /// picks, and the addons they are from
+    fn optional_files(&self) -> Vec<(String, String)> {
+        self.selected_addon_names()
+            .into_iter()
+            .filter(|name| self.addons.get(*name).map_or(false, |addon| self.picks_optional(addon)))
+            .flat_map(|name| {
+                self.present_optional_files(name)
+                    .into_iter()
+                    .map(move |file| (name.clone(), file))
+            })
+            .collect()
+    }
+    fn selected_primary_spec(&self) -> Option<&AddonSpecification> {
+        let name = self
+            .primary_addons
+            .get(self.selected_primary_addon)
+            .map(String::as_str)
+            .unwrap_or("");
+        self.addons.get(name)
+    }
+    fn primary_is_standalone(&self) -> bool {
+        self.selected_primary_spec()
+            .and_then(|addon| addon.standalone)
+            .unwrap_or(false)
+    }
+    /// Names of the selected addons, primary addon first
+    fn selected_addon_names(&self) -> Vec<&String> {
+        let primary = self
+            .primary_addons
+            .get(self.selected_primary_addon)
+            .filter(|name| self.addons.contains_key(*name));
+        let secondary = self
+            .secondary_addons
+            .iter()
+            .zip(self.selected_secondary_addons.iter())
+            .filter(|_| !self.primary_is_standalone())
+            .filter_map(|(addon, &selected)| selected.then_some(addon));
+        primary.into_iter().chain(secondary).collect()
+    }
+    /// The working directory required by the selected addons, if any
+    fn working_dir(&self) -> Result<Option<&str>, LaunchError> {
+        if !self.launch_dir.is_empty() {
+            return Ok(Some(self.launch_dir.as_str()));
+        }
+        let dirs: Vec<(&String, &String)> = self
+            .selected_addon_names()
+            .into_iter()
+            .filter_map(|name| Some((name, self.addons.get(name)?.cwd.as_ref()?)))
+            .collect();
+        match dirs.first() {
+            None => Ok(None),
+            Some((_, first)) if dirs.iter().all(|(_, dir)| dir == first) => {
+                Ok(Some(first.as_str()))
+            }
+            Some(_) => Err(LaunchError::ConflictingWorkingDirs(
+                dirs.iter()
+                    .map(|(name, dir)| format!("{} ({})", name, dir))
+                    .collect(),
+            )),
+        }
+    }
+    /// Make sure GZDoom can be started in the working directory
+    fn check_working_dir(&self) -> Result<(), LaunchError> {
+        match self.working_dir()? {
+            Some(dir) if !Path::new(dir).is_dir() => {
+                Err(LaunchError::BadWorkingDir(String::from(dir)))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// The selected primary addon, and the secondary addons, in load order
+    fn loaded_addon_names(&self) -> Vec<&String> {
+        let primary = self
+            .primary_addons
+            .get(self.selected_primary_addon)
+            .filter(|name| self.addons.contains_key(*name));
+        primary.into_iter().chain(self.secondary_addon_names()).collect()
+    }
+    /// The files which will be loaded, in order, and the addons they are from
+    fn load_order(&self) -> Vec<(&String, &String)> {
+        self.loaded_addon_names()
+            .into_iter()
+            .flat_map(|name| {
+                self.files_for_addon(name)
+                    .into_iter()
+                    .map(move |file| (name, file))
+            })
+            .collect()
+    }
+    /// Move the secondary addon at `from` in the load order so that it is
+    /// loaded before the one at `to`, or last if `to` is past the end
+    fn move_secondary(&mut self, from: usize, to: usize) {
+        let mut names: Vec<String> = self.secondary_addon_names().into_iter().cloned().collect();
+        if from >= names.len() {
+            return;
+        }
+        let name = names.remove(from);
+        let to = if to > from { to - 1 } else { to };
+        names.insert(to.min(names.len()), name);
+        // Unselected addons keep their place after the selected ones
+        let unselected: Vec<String> = self
+            .secondary_order
+            .iter()
+            .filter(|name| !names.contains(name))
+            .cloned()
+            .collect();
+        names.extend(unselected);
+        self.secondary_order = names;
+    }
+    /// Show the selected secondary addons in load order, and let the user
+    /// drag them into a different order
+    fn secondary_order_list(&mut self, ui: &mut egui::Ui) {
+        let names: Vec<String> = self.secondary_addon_names().into_iter().cloned().collect();
+        if names.len() < 2 {
+            return;
+        }
+        ui.label("Drag secondary addons to change the order they are loaded in:");
+        let mut rects = vec![];
+        let mut dragging = None;
+        let mut dropped = None;
+        names.iter().enumerate().for_each(|(index, name)| {
+            let response = ui
+                .add(egui::Label::new(format!("☰ {}", name)).sense(egui::Sense::drag()))
+                .on_hover_cursor(egui::CursorIcon::Grab);
+            if response.dragged() {
+                dragging = Some(index);
+            } else if response.drag_released() {
+                dropped = Some(index);
+            }
+            rects.push(response.rect);
+        });
+        let pointer = match ui.ctx().pointer_interact_pos() {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        // Where the addon would go if it was dropped now
+        let target = rects
+            .iter()
+            .position(|rect| pointer.y < rect.center().y)
+            .unwrap_or(rects.len());
+        if dragging.is_some() {
+            let y = match rects.get(target) {
+                Some(rect) => rect.top(),
+                None => rects.last().map_or(pointer.y, |rect| rect.bottom()),
+            };
+            let stroke = ui.visuals().selection.stroke;
+            ui.painter().hline(ui.min_rect().x_range(), y, stroke);
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+        }
+        if let Some(from) = dropped {
+            self.move_secondary(from, target);
+        }
+    }
+    fn primary_addon<'a>(&'a self) -> Vec<&'a String> {
+        self.primary_addons
+            .get(self.selected_primary_addon)
+            .map(|name| self.files_for_addon(name))
+            .unwrap_or_default()
+    }
+    /// The selected secondary addons, and the secondary addons they require,
+    /// in load order
+    fn secondary_addon_names(&self) -> Vec<&String> {
+        if self.primary_is_standalone() {
+            return vec![];
+        }
+        let mut selected: Vec<&String> = self
+            .secondary_addons
+            .iter()
+            .zip(self.selected_secondary_addons.iter())
+            .filter_map(|(addon, &selected)| if selected { Some(addon) } else { None })
+            .collect();
+        // Addons the user hasn't put in order stay alphabetical, after the
+        // ones they have. Requirements still come before what requires them.
+        selected.sort_by_key(|name| {
+            self.secondary_order
+                .iter()
+                .position(|ordered| ordered == *name)
+                .unwrap_or(usize::MAX)
+        });
+        let (ordered, _) = addon::with_requirements(&self.addons, &selected);
+        ordered
+            .into_iter()
+            .filter(|name| self.addons.get(*name).map_or(false, |spec| spec.secondary.is_some()))
+            .collect()
+    }
+    fn secondary_addons<'a>(&'a self) -> Vec<&'a String> {
+        self.secondary_addon_names()
+            .into_iter()
+            .flat_map(|addon| self.files_for_addon(addon))
+            .collect()
+    }
+    /// Indices of the secondary addons by category, with the categories in
+    /// alphabetical order, and addons without a category last
+    fn secondary_groups(&self) -> Vec<(Option<String>, Vec<usize>)> {
+        let mut groups: Vec<(Option<String>, Vec<usize>)> = vec![];
+        self.secondary_addons.iter().enumerate().for_each(|(index, name)| {
+            let category = self.addons.get(name).and_then(|addon| addon.category.clone());
+            match groups.iter_mut().find(|(group, _)| *group == category) {
+                Some((_, indices)) => indices.push(index),
+                None => groups.push((category, vec![index])),
+            }
+        });
+        groups.sort_by_key(|(category, _)| (category.is_none(), category.clone()));
+        groups
+    }
+    /// How the secondary addon at `index` matches the search, if it does.
+    /// `query` must already be lowercase.
+    fn secondary_matches(&self, index: usize, query: &str) -> Option<SearchMatch> {
+        let name = self.secondary_addons.get(index)?;
+        self.addons
+            .get(name)
+            .and_then(|addon| addon.search(name, query, self.search_descriptions))
+    }
+    /// Show the checkbox for a secondary addon, if it matches the search.
+    /// Returns whether it was checked or unchecked.
+    fn secondary_addon_row(
+        &mut self,
+        ui: &mut egui::Ui,
+        index: usize,
+        query: &str,
+        required: &[String],
+    ) -> bool {
+        let name = self.secondary_addons[index].clone();
+        let found = self.secondary_matches(index, query);
+        if found.is_none() {
+            return false;
+        }
+        let response = ui
+            .horizontal(|ui| {
+                let spec = self.addons.get(&name);
+                let supported = spec.map_or(true, AddonSpecification::supported_here);
+                let available = spec.map_or(true, |addon| addon.missing.is_empty());
+                let is_required = required.contains(&name);
+                file_status(ui, spec);
+                let selected = &mut self.selected_secondary_addons[index];
+                let mut response = ui.add_enabled(
+                    supported && available && !is_required,
+                    egui::Checkbox::new(selected, name.as_str()),
+                );
+                if let Some(note) = spec.and_then(AddonSpecification::platform_note) {
+                    response = response.on_disabled_hover_text(note);
+                } else if let Some(note) = spec.and_then(AddonSpecification::missing_note) {
+                    response = response.on_disabled_hover_text(note);
+                } else if is_required {
+                    response = response.on_disabled_hover_text("Required by another selected addon");
+                }
+                let selected = *selected;
+                let prompts = spec.map_or(false, AddonSpecification::prompts_for_optional);
+                if response.changed() && selected && prompts {
+                    self.optional_prompt = Some(name.clone());
+                }
+                if let Some(addon) = spec.filter(|_| selected) {
+                    variant_combo(ui, &name, addon, &mut self.selected_variants);
+                }
+                response
+            })
+            .inner;
+        let changed = response.changed();
+        if let Some(tooltip) = addon_tooltip(self.addons.get(&name), found.as_ref()) {
+            response.on_hover_text(tooltip);
+        }
+        changed
+    }
+    /// Two of the addons to be loaded which can't be loaded together, if any
+    fn conflicting_addons(&self) -> Option<(&String, &String)> {
+        addon::find_conflict(&self.addons, &self.loaded_addon_names())
+    }
+    /// Addons which other selected addons require, so they can't be deselected
+    fn required_addons(&self) -> Vec<&String> {
+        let selected = self.selected_addon_names();
+        let mut required = vec![];
+        selected.iter().for_each(|name| {
+            let (order, _) = addon::with_requirements(&self.addons, &[*name]);
+            required.extend(order.into_iter().filter(|required| required != name));
+        });
+        required.sort();
+        required.dedup();
+        required
+    }
+    /// Select the secondary addons which the selected addons require, and say
+    /// which required addons aren't available
+    fn apply_requirements(&mut self) {
+        let required: Vec<String> = self.required_addons().into_iter().cloned().collect();
+        let (_, missing) = addon::with_requirements(&self.addons, &self.selected_addon_names());
+        self.secondary_addons
+            .iter()
+            .zip(self.selected_secondary_addons.iter_mut())
+            .filter(|(name, _)| required.contains(name))
+            .for_each(|(_, selected)| *selected = true);
+        if !missing.is_empty() {
+            self.show_message(missing.join("\n"));
+        }
+    }
+    fn build_command(&self) -> LaunchCommand {
+        let gzdoom = self.gzdoom_build();
+        let iwad = self.iwad();
+        let run_info = get_run_info(&self.exargs, gzdoom);
+        let addon_files: Vec<String> = self
+            .primary_addon()
+            .into_iter()
+            .chain(self.secondary_addons())
+            .chain(self.always_loaded_files())
+            .cloned()
+            .collect();
+        let patches: Vec<String> = self.deh_patches().into_iter().cloned().collect();
+        // When the extra arguments wrap GZDoom in another program, the
+        // wrapper's arguments and GZDoom itself have to stay at the front.
+        let wrapped = run_info
+            .new_executable()
+            .and_then(|_| run_info.arguments().iter().position(|arg| arg == gzdoom))
+            .map_or(0, |index| index + 1);
+        let (wrapper_args, engine_args) = run_info.arguments().split_at(wrapped);
+        let mut arguments: Vec<String> = wrapper_args.iter().map(|arg| arg.to_string()).collect();
+        // Everything but the extra arguments is compacted, so that empty
+        // settings don't show up in the command line.
+        arguments.extend(self.arg_order.arrange(|segment| match segment {
+            Segment::Engine => engine_args
+                .iter()
+                .map(|arg| arg.to_string())
+                .chain(self.addon_arguments().into_iter().cloned())
+                .collect(),
+            Segment::Iwad => compact(["-iwad", iwad].map(String::from).to_vec()),
+            Segment::Config => {
+                compact(["-config", self.config.as_str()].map(String::from).to_vec())
+            }
+            Segment::LoadGame => {
+                compact(["-loadgame", self.load_game.as_str()].map(String::from).to_vec())
+            }
+            Segment::Files => compact(iter::once(String::from("-file"))
+                .chain(addon_files.iter().cloned())
+                .chain(iter::once(String::from("-deh")))
+                .chain(patches.iter().cloned())
+                .collect()),
+            // The map is started after the scripts have run. Loading a saved
+            // game takes precedence over it.
+            Segment::Exec => compact(self
+                .exec_scripts
+                .iter()
+                .flat_map(|script| ["+exec", script.as_str()].map(String::from))
+                .chain(
+                    self.chosen_map()
+                        .filter(|_| self.load_game.is_empty())
+                        .into_iter()
+                        .flat_map(|map| ["+map", map].map(String::from)),
+                )
+                .collect()),
+        }));
+        let mut files = vec![String::from(gzdoom), String::from(iwad)];
+        if !self.load_game.is_empty() {
+            files.push(self.load_game.clone());
+        }
+        files.extend(addon_files);
+        files.extend(patches);
+        files.extend(self.exec_scripts.iter().cloned());
+        let working_dir = self.working_dir().ok().flatten().map(String::from);
+        files.extend(working_dir.iter().cloned());
+        // The user can override what addons set in the extra arguments
+        let mut environment = self.addon_environment();
+        run_info.environment().iter().for_each(|(key, value)| {
+            set_variable(&mut environment, key, value.to_string());
+        });
+        let mut command = LaunchCommand {
+            program: String::from(run_info.program(gzdoom)),
+            arguments,
+            environment,
+            files,
+            working_dir,
+        };
+        if self.uses_wine() {
+            command.wrap_engine(gzdoom, &self.wine);
+            if !self.wine_prefix.is_empty() {
+                command
+                    .environment
+                    .push((String::from("WINEPREFIX"), self.wine_prefix.clone()));
+            }
+        }
+        command
+    }
+    /// The map to start on, if the primary addon has a list of maps and one
+    /// of them is chosen
+    fn chosen_map(&self) -> Option<&str> {
+        let name = self.primary_addons.get(self.selected_primary_addon)?;
+        let maps = self.addons.get(name)?.maps.as_ref()?;
+        self.selected_maps
+            .get(name)
+            .filter(|map| maps.contains(map))
+            .map(String::as_str)
+    }
+    /// The source port the user chose, or the one of the selected build
+    fn source_port(&self) -> SourcePort {
+        self.source_port.unwrap_or_else(|| self.detected_source_port())
+    }
+    /// The source port of the selected build, from the list of builds or its
+    /// name. GZDoom is assumed otherwise.
+    fn detected_source_port(&self) -> SourcePort {
+        let build = match self.selected_gzdoom_build {
+            GZDoomBuildSelection::Single => self.builds.first(),
+            GZDoomBuildSelection::ListIndex(index) => self.builds.get(index),
+            GZDoomBuildSelection::FullPath(_) => None,
+        };
+        build
+            .and_then(|build| build.port)
+            .or_else(|| SourcePort::detect(self.gzdoom_build()))
+            .unwrap_or_default()
+    }
+    /// Whether the selected GZDoom build is a Windows program, which can be
+    /// run with Wine on this system
+    fn can_use_wine(&self) -> bool {
+        !cfg!(target_family = "windows") && is_windows_exe(&self.gzdoom_build())
+    }
+    fn uses_wine(&self) -> bool {
+        self.use_wine && self.can_use_wine()
+    }
+    fn source_port_row(&mut self, ui: &mut egui::Ui) {
+        ui.label("Source port:");
+        let automatic = format!("Automatic ({})", self.detected_source_port().name());
+        egui::ComboBox::from_id_source("source_port")
+            .selected_text(self.source_port.map_or(automatic.clone(), |port| String::from(port.name())))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.source_port, None, automatic);
+                SourcePort::ALL.iter().for_each(|port| {
+                    ui.selectable_value(&mut self.source_port, Some(*port), port.name());
+                });
+            })
+            .response
+            .on_hover_text("The launch can only be checked first with GZDoom and LZDoom");
+    }
+    fn environment_preview(&self, ui: &mut egui::Ui) {
+        let command = self.build_command();
+        if command.environment.is_empty() {
+            ui.label("No environment variables are set for GZDoom.");
+        } else {
+            ui.label("Set for GZDoom:");
+            egui::Grid::new("set_environment").show(ui, |ui| {
+                command.environment.iter().for_each(|(key, value)| {
+                    ui.label(egui::RichText::new(key).strong());
+                    ui.code(value);
+                    if env::var_os(key).is_some() {
+                        ui.label(egui::RichText::new("(overrides inherited value)").weak());
+                    }
+                    ui.end_row();
+                });
+            });
+        }
+        egui::CollapsingHeader::new("Inherited from this program")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut inherited: Vec<(String, String)> = env::vars_os()
+                    .map(|(key, value)| {
+                        (key.to_string_lossy().into_owned(), value.to_string_lossy().into_owned())
+                    })
+                    .filter(|(key, _)| command.environment.iter().all(|(set, _)| set != key))
+                    .collect();
+                inherited.sort();
+                egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    egui::Grid::new("inherited_environment").show(ui, |ui| {
+                        inherited.iter().for_each(|(key, value)| {
+                            ui.label(key);
+                            ui.code(value);
+                            ui.end_row();
+                        });
+                    });
+                });
+            });
+    }
+    fn profile_row(&mut self, ui: &mut egui::Ui) {
+        let current = self.current_profile.and_then(|index| self.profiles.get(index));
+        let mut chosen = None;
+        egui::ComboBox::from_label("Profile")
+            .selected_text(current.map(|profile| profile.name.as_str()).unwrap_or("None"))
+            .width(200.)
+            .show_ui(ui, |ui| {
+                self.profiles.iter().enumerate().for_each(|(index, profile)| {
+                    let selected = self.current_profile == Some(index);
+                    if ui.selectable_label(selected, &profile.name).clicked() {
+                        chosen = Some(index);
+                    }
+                });
+            });
+        if let Some(index) = chosen {
+            self.apply_profile(index);
+        }
+        let has_profile = self.current_profile.is_some();
+        if ui
+            .add_enabled(has_profile, egui::Button::new("Save profile"))
+            .on_hover_text("Save the current selection to this profile")
+            .clicked()
+        {
+            self.save_profile();
+        }
+        if ui.add_enabled(has_profile, egui::Button::new("Delete profile")).clicked() {
+            self.delete_profile();
+        }
+        let theme = self
+            .current_profile
+            .and_then(|index| self.profiles.get(index))
+            .and_then(|profile| profile.light_theme);
+        let theme_name = |theme: Option<bool>| match theme {
+            None => "Use global UI settings",
+            Some(false) => "Dark",
+            Some(true) => "Light",
+        };
+        let mut chosen_theme = theme;
+        ui.add_enabled_ui(has_profile, |ui| {
+            egui::ComboBox::from_id_source("profile_theme")
+                .selected_text(theme_name(theme))
+                .show_ui(ui, |ui| {
+                    [None, Some(false), Some(true)].into_iter().for_each(|option| {
+                        ui.selectable_value(&mut chosen_theme, option, theme_name(option));
+                    });
+                })
+                .response
+                .on_hover_text("The theme to switch to when this profile is loaded");
+        });
+        if chosen_theme != theme {
+            self.set_profile_theme(chosen_theme);
+        }
+        ui.separator();
+        ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("Name").desired_width(120.));
+        if ui
+            .button("New profile")
+            .on_hover_text("Save the current selection as a new profile")
+            .clicked()
+        {
+            self.new_profile();
+        }
+    }
+    fn launch_options_import(&mut self, ui: &mut egui::Ui) {
+        ui.label("Paste launch options from Steam to see how they will be used.");
+        ui.text_edit_singleline(&mut self.import_text);
+        if self.import_text.trim().is_empty() {
+            return;
+        }
+        let run_info = get_run_info(&self.import_text, "%command%");
+        egui::Grid::new("imported_launch_options").show(ui, |ui| {
+            ui.label("Environment:");
+            ui.vertical(|ui| {
+                if run_info.environment().is_empty() {
+                    ui.label("(unchanged)");
+                }
+                run_info.environment().iter().for_each(|(key, value)| {
+                    ui.code(format!("{}={}", key, value));
+                });
+            });
+            ui.end_row();
+            ui.label("Run through:");
+            match run_info.new_executable() {
+                Some(exe) => ui.code(exe),
+                None => ui.label("(GZDoom is run directly)"),
+            };
+            ui.end_row();
+            ui.label("Arguments:");
+            ui.code(run_info.arguments().join(" "));
+            ui.end_row();
+        });
+        if ui.button("Use as extra arguments").clicked() {
+            self.exargs = String::from(self.import_text.trim());
+            self.import_text.clear();
+        }
+    }
+    fn try_launch(&mut self) -> Result<(), LaunchError> {
+        let command = self.prepare_launch()?;
+        let build = self.selected_gzdoom_build.clone();
+        let settings = self.snapshot();
+        if self.needs_preflight() {
+            let engine = String::from(self.gzdoom_build());
+            self.preflighting = Some(preflight_command(command, &engine, build, settings));
+            return Ok(());
+        }
+        self.start_launch(command, build, settings)
+    }
+    /// Run a launch command which is ready, or start watching it with "quit
+    /// on launch"
+    fn start_launch(
+        &mut self,
+        command: LaunchCommand,
+        build: GZDoomBuildSelection,
+        settings: Persistence,
+    ) -> Result<(), LaunchError> {
+        if self.quit_on_launch {
+            let grace = Duration::from_secs(self.startup_grace);
+            self.watching = Some(watch_command(command, build, grace, settings));
+            return Ok(());
+        }
+        self.running = Some(run_command(&command, &self.game_log)?);
+        self.remember_build(&build);
+        self.last_command = Some(command);
+        Ok(())
+    }
+    /// Whether the settings have changed since they were last saved
+    fn has_unsaved_changes(&self) -> bool {
+        self.saved_settings
+            .as_ref()
+            .map_or(false, |saved| saved.user_settings() != Persistence::from(self).user_settings())
+    }
+    /// Whether the launcher can exit now. If there are unsaved changes, the
+    /// user is asked what to do with them first.
+    fn request_exit(&mut self) -> bool {
+        if self.exit_confirmed || self.quit_requested || !self.has_unsaved_changes() {
+            return true;
+        }
+        self.exit_prompt_open = true;
+        false
+    }
+    /// Exit once the user has chosen what to do with the unsaved changes
+    fn confirm_exit(&mut self, save: bool) {
+        self.discard_changes = !save;
+        self.exit_confirmed = true;
+        self.exit_prompt_open = false;
+    }
+    /// Put the GZDoom build first in the recent builds, if it was typed in
+    fn remember_build(&mut self, build: &GZDoomBuildSelection) {
+        if let GZDoomBuildSelection::FullPath(path) = build {
+            let path = path.clone();
+            self.recent_builds.retain(|recent| *recent != path);
+            self.recent_builds.insert(0, path);
+            self.recent_builds.truncate(MAX_RECENT_BUILDS);
+        }
+    }
+    /// Check everything which is needed for launching, and build the command
+    fn prepare_launch(&mut self) -> Result<LaunchCommand, LaunchError> {
+        if let Some((addon, other)) = self.conflicting_addons() {
+            return Err(LaunchError::ConflictingAddons(addon.clone(), other.clone()));
+        }
+        // Addons may have been selected before their files went missing
+        let selected = self.selected_addon_names();
+        let missing = selected.iter().find_map(|name| {
+            let addon = self.addons.get(*name).filter(|addon| !addon.missing.is_empty())?;
+            Some(LaunchError::MissingAddonFiles(String::clone(name), addon.missing.clone()))
+        });
+        if let Some(e) = missing {
+            return Err(e);
+        }
+        let (_, unavailable) = addon::with_requirements(&self.addons, &selected);
+        if !unavailable.is_empty() {
+            return Err(LaunchError::UnavailableRequirements(unavailable));
+        }
+        if let Some(quote) = cmdlineparse::unclosed_quote(&self.exargs) {
+            return Err(LaunchError::UnclosedQuote(quote));
+        }
+        let gzdoom = self.gzdoom_build();
+        let iwad = self.iwad();
+        if let Some(target) = dangling_symlink(&gzdoom) {
+            return Err(LaunchError::GZDoomBuildBrokenLink(target));
+        }
+        if File::open(&gzdoom).is_err() {
+            return Err(LaunchError::GZDoomBuildNotOpenable);
+        }
+        if !is_executable(&gzdoom) && !self.uses_wine() {
+            return Err(LaunchError::GZDoomBuildNotExecutable);
+        }
+        if File::open(&iwad).is_err() {
             return Err(LaunchError::IWADNotFound);
         }
-        if !is_iwad(&iwad) {
-            return Err(LaunchError::IWADNotIWAD);
+        if is_pwad(&iwad) {
+            return Err(LaunchError::IWADIsPWAD);
+        }
+        if has_zip_extension(&iwad) && !is_zip(&iwad) {
+            return Err(LaunchError::IWADNotZip);
+        }
+        if !is_iwad(&iwad) {
+            return Err(LaunchError::IWADNotIWAD);
+        }
+        if !self.load_game.is_empty() && File::open(&self.load_game).is_err() {
+            return Err(LaunchError::SaveGameNotFound);
+        }
+        if let Some(script) = self.exec_scripts.iter().find(|script| File::open(script).is_err()) {
+            return Err(LaunchError::ExecScriptNotFound(script.clone()));
+        }
+        self.check_working_dir()?;
+        if self.backup_config && !self.config.is_empty() && Path::new(&self.config).is_file() {
+            let backup = format!("{}.bak", self.config);
+            if let Err(e) = fs::copy(&self.config, &backup) {
+                self.show_message(format!("Could not back up {} to {}:\n{}", self.config, backup, e));
+            }
+        }
+        Ok(self.build_command())
+    }
+    /// Whether the launch command should be tried with `-norun` first
+    fn needs_preflight(&self) -> bool {
+        self.preflight && self.source_port().may_have_norun()
+    }
+    /// Check whether the launch command has been tried with `-norun`, and
+    /// launch it if that worked. Returns whether it is still being tried.
+    fn poll_preflight(&mut self) -> bool {
+        let checking = match self.preflighting.take() {
+            Some(checking) => checking,
+            None => return false,
+        };
+        let checked = checking.result.lock().ok().and_then(|mut result| result.take());
+        let PendingPreflight { result, command, build, settings } = checking;
+        let launched = match checked {
+            Some(checked) => check_preflight(&command, checked)
+                .and_then(|_| self.start_launch(command, build, settings.clone())),
+            None if Arc::strong_count(&result) > 1 => {
+                self.preflighting = Some(PendingPreflight { result, command, build, settings });
+                return true;
+            }
+            // The thread is gone without leaving anything
+            None => return false,
+        };
+        if let Err(e) = launched {
+            self.launch_failed(e, settings);
+        }
+        false
+    }
+    fn relaunch(&mut self) -> Result<(), LaunchError> {
+        if let Some(command) = &self.last_command {
+            self.running = Some(run_command(command, &self.game_log)?);
+        }
+        Ok(())
+    }
+}
+
+/// Try the command with `-norun` in the background
+fn preflight_command(
+    command: LaunchCommand,
+    engine: &str,
+    build: GZDoomBuildSelection,
+    settings: Persistence,
+) -> PendingPreflight {
+    let result = Arc::new(Mutex::new(None));
+    let slot = Arc::clone(&result);
+    let checked = command.clone();
+    let engine = String::from(engine);
+    thread::spawn(move || {
+        let preflight = checked.preflight(&engine);
+        if let Ok(mut slot) = slot.lock() {
+            *slot = Some(preflight);
+        }
+    });
+    PendingPreflight { result, command, build, settings }
+}
+
+/// Whether trying the command with `-norun` found a problem
+fn check_preflight(command: &LaunchCommand, checked: io::Result<Preflight>) -> Result<(), LaunchError> {
+    match checked {
+        Ok(Preflight::Failed(output)) => Err(LaunchError::PreflightFailed(output)),
+        Ok(Preflight::Passed | Preflight::Unsupported) => Ok(()),
+        Err(e) => Err(LaunchError::LaunchFailed(command.program.clone(), e)),
+    }
+}
+
+/// Select the given GZDoom build from the list of builds, or the first one
+/// if it isn't in the list. Without a list, the path is used as it is.
+fn build_selection(builds: &[Build], path: &str) -> GZDoomBuildSelection {
+    match builds.len() {
+        0 => GZDoomBuildSelection::FullPath(String::from(path)),
+        1 => GZDoomBuildSelection::Single,
+        _ => GZDoomBuildSelection::ListIndex(
+            builds
+                .iter()
+                .position(|build| build.path == path)
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+/// Whether the file can be run as a GZDoom build, either directly or with Wine
+fn usable_build(path: &impl AsRef<Path>) -> bool {
+    is_executable(path) || (!cfg!(target_family = "windows") && is_windows_exe(path))
+}
+
+/// Select the given IWAD from the found IWADs, or the first one if it wasn't
+/// found. Without any, the path is used as it is.
+fn iwad_selection(iwads: &[String], path: &str) -> GZDoomBuildSelection {
+    match iwads.len() {
+        0 => GZDoomBuildSelection::FullPath(String::from(path)),
+        _ => GZDoomBuildSelection::ListIndex(
+            iwads.iter().position(|iwad| iwad == path).unwrap_or_default(),
+        ),
+    }
+}
+
+/// Parse a hotkey like "Ctrl+Enter" or "Shift+F9". Ctrl and Cmd are the
+/// same, so that a hotkey works the same way on every system.
+fn parse_hotkey(hotkey: &str) -> Option<(egui::Modifiers, egui::Key)> {
+    let mut parts: Vec<&str> = hotkey.split('+').map(str::trim).collect();
+    let key = egui::Key::from_name(parts.pop()?)?;
+    let mut modifiers = egui::Modifiers::NONE;
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "cmd" => modifiers.command = true,
+            "shift" => modifiers.shift = true,
+            "alt" => modifiers.alt = true,
+            _ => return None,
+        }
+    }
+    Some((modifiers, key))
+}
+
+/// The file name at the end of a path
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Dropdown for choosing which variant of an addon to load, if it has any
+fn variant_combo(
+    ui: &mut egui::Ui,
+    name: &str,
+    addon: &AddonSpecification,
+    selected_variants: &mut HashMap<String, String>,
+) {
+    let variants = addon.variant_names();
+    let chosen = match addon.variant(selected_variants.get(name).map(String::as_str)) {
+        Some(chosen) => chosen,
+        None => return,
+    };
+    egui::ComboBox::from_id_source(("variant", name))
+        .selected_text(chosen)
+        .show_ui(ui, |ui| {
+            variants.iter().for_each(|&variant| {
+                if ui.selectable_label(variant == chosen, variant).clicked() {
+                    selected_variants.insert(String::from(name), variant.clone());
+                }
+            });
+        });
+}
+
+/// Leave out arguments which do nothing, like `-file` without any files, or
+/// `-config` with an empty path. Some forks are confused by them. The
+/// arguments are flags, each followed by its values.
+fn compact(arguments: Vec<String>) -> Vec<String> {
+    let is_flag = |arg: &String| arg.starts_with('-') || arg.starts_with('+');
+    let mut compacted = Vec::with_capacity(arguments.len());
+    let mut arguments = arguments.into_iter().peekable();
+    while let Some(flag) = arguments.next() {
+        let mut values = vec![];
+        while let Some(value) = arguments.next_if(|arg| !is_flag(arg)) {
+            if !value.is_empty() {
+                values.push(value);
+            }
+        }
+        if !values.is_empty() {
+            compacted.push(flag);
+            compacted.extend(values);
+        }
+    }
+    compacted
+}
+
+/// Show a file browser, starting in the folder last used for this kind of
+/// file, and remember the folder of the chosen file. A folder which no longer
+/// exists is ignored.
+fn browse(
+    dirs: &mut HashMap<Picker, String>,
+    picker: Picker,
+    filter: Option<(&str, &[&str])>,
+) -> Result<Option<PathBuf>, native_dialog::Error> {
+    let start = dirs.get(&picker).filter(|dir| Path::new(dir).is_dir()).cloned();
+    let mut dialog = native_dialog::FileDialog::new();
+    if let Some(dir) = &start {
+        dialog = dialog.set_location(dir);
+    }
+    if let Some((description, extensions)) = filter {
+        dialog = dialog.add_filter(description, extensions);
+    }
+    let choice = dialog.show_open_single_file()?;
+    if let Some(dir) = choice.as_deref().and_then(Path::parent).and_then(Path::to_str) {
+        dirs.insert(picker, String::from(dir));
+    }
+    Ok(choice)
+}
+
+/// Hover text for an addon in the lists: its description, and why it matched
+/// the search, if it has either
+fn addon_tooltip(addon: Option<&AddonSpecification>, found: Option<&SearchMatch>) -> Option<String> {
+    let description = addon
+        .and_then(|addon| addon.description.as_deref())
+        .map(str::trim)
+        .filter(|description| !description.is_empty());
+    let matched = match found {
+        Some(SearchMatch::Description) => Some(String::from("Matched in description")),
+        Some(SearchMatch::Tag(tag)) => Some(format!("Matched tag: {}", tag)),
+        _ => None,
+    };
+    match (description, matched) {
+        (Some(description), Some(matched)) => Some(format!("{}\n\n{}", description, matched)),
+        (Some(description), None) => Some(String::from(description)),
+        (None, matched) => matched,
+    }
+}
+
+/// Pick which of the addon's maps to start on, if it has any
+fn map_combo(
+    ui: &mut egui::Ui,
+    name: &str,
+    addon: &AddonSpecification,
+    selected_maps: &mut HashMap<String, String>,
+) {
+    let maps = match &addon.maps {
+        Some(maps) if !maps.is_empty() => maps,
+        _ => return,
+    };
+    let chosen = selected_maps.get(name).filter(|map| maps.contains(map)).cloned();
+    egui::ComboBox::from_id_source(("map", name))
+        .selected_text(chosen.as_deref().unwrap_or("Start normally"))
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(chosen.is_none(), "Start normally").clicked() {
+                selected_maps.remove(name);
+            }
+            maps.iter().for_each(|map| {
+                if ui.selectable_label(chosen.as_ref() == Some(map), map).clicked() {
+                    selected_maps.insert(String::from(name), map.clone());
+                }
+            });
+        })
+        .response
+        .on_hover_text("Map to start on");
+}
+
+/// Set an environment variable for a command, replacing any earlier value
+fn set_variable(environment: &mut Vec<(String, String)>, key: &str, value: String) {
+    environment.retain(|(existing, _)| existing != key);
+    environment.push((String::from(key), value));
+}
+
+/// Show a folder browser, starting in `start` if it is a folder
+fn browse_folder(start: &str) -> Result<Option<PathBuf>, native_dialog::Error> {
+    let mut dialog = native_dialog::FileDialog::new();
+    if Path::new(start).is_dir() {
+        dialog = dialog.set_location(start);
+    }
+    dialog.show_open_single_dir()
+}
+
+/// Why the file can't be used as the IWAD
+fn not_iwad_message(path: &Path) -> String {
+    if is_pwad(&path) {
+        format!(
+            "{:?} looks like a PWAD, not an IWAD. Add it as an addon instead.",
+            path
+        )
+    } else if has_zip_extension(&path) && !is_zip(&path) {
+        format!("{:?} is not a zip file, so it can't be a zipped IWAD!", path)
+    } else {
+        format!("{:?} is not an IWAD!", path)
+    }
+}
+
+/// Which game the IWAD is for, if it is an IWAD
+fn iwad_kind_label(ui: &mut egui::Ui, kind: Option<IwadKind>) {
+    if let Some(kind) = kind {
+        ui.label(egui::RichText::new(kind.name()).weak())
+            .on_hover_text("The game this IWAD is for");
+    }
+}
+
+/// A green dot for an addon whose files were all found, or a red one which
+/// lists the missing files when it is hovered over
+fn file_status(ui: &mut egui::Ui, addon: Option<&AddonSpecification>) {
+    let addon = match addon {
+        Some(addon) => addon,
+        None => return,
+    };
+    match addon.missing_note() {
+        Some(note) => {
+            let color = ui.visuals().error_fg_color;
+            ui.label(egui::RichText::new("●").color(color)).on_hover_text(note);
+        }
+        None => {
+            let color = egui::Color32::from_rgb(0x40, 0xc0, 0x40);
+            ui.label(egui::RichText::new("●").color(color)).on_hover_text("All files found");
         }
-        let run_info = get_run_info(&self.exargs, &gzdoom);
-        let primary_addon = self.primary_addon();
-        let secondary_addons = self.secondary_addons();
-        match Command::new(run_info.new_executable.unwrap_or(&gzdoom))
-            .envs(env::vars())
-            .envs(run_info.environment.iter().map(|(a, b)| (a, b.as_ref())))
-            .args(run_info.arguments)
-            .args(["-iwad", &iwad])
-            .args(
-                (if self.config.len() > 0 {
-                    Some(["-config", &self.config])
-                } else {
-                    None
-                })
-                .iter()
-                .flatten(),
-            )
-            .arg("-file")
-            .args(primary_addon)
-            .args(secondary_addons)
-            .spawn()
-        {
-            Ok(mut child) => {
-                if let Err(e) = child.wait() {
-                    return Err(LaunchError::FailedWait(Box::from(e)));
-                }
-            }
-            Err(e) => {
-                return Err(LaunchError::LaunchFailed(Box::from(e)));
-            }
+    }
+}
+
+/// Start the command, and wait for it to exit in the background. Its output
+/// goes to the log.
+fn run_command(command: &LaunchCommand, log: &Arc<Mutex<String>>) -> Result<RunningGame, LaunchError> {
+    if let Ok(mut log) = log.lock() {
+        log.push_str(&format!("> {}\n", command.command_line()));
+    }
+    let child = command
+        .spawn_logged(log)
+        .map_err(|e| LaunchError::LaunchFailed(command.program.clone(), e))?;
+    let result = Arc::new(Mutex::new(None));
+    let slot = Arc::clone(&result);
+    // The result is only filled in once all of the output has been read
+    thread::spawn(move || {
+        let exited = child.wait();
+        if let Ok(mut slot) = slot.lock() {
+            *slot = Some(exited);
         }
-        Ok(())
+    });
+    Ok(RunningGame { result })
+}
+
+/// The settings for a launch from the command line, or the problems with the
+/// addon names given
+fn headless_config(args: &LauncherArgs, addons: &AddonMap) -> Result<Persistence, Vec<String>> {
+    let names: Vec<String> = args.primary.iter().chain(&args.secondary).cloned().collect();
+    let (selection, errors) = selection::resolve_selection(&names, addons);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Persistence {
+        gzdoom_build: args.gzdoom.clone(),
+        iwad: args.iwad.clone(),
+        primary_addon: selection.primary,
+        secondary_addons: Some(selection.secondary),
+        ..Default::default()
+    })
+}
+
+/// Launch GZDoom as the command line says, without a window, and wait for it
+/// to exit. The checks are the same as when launching from the window.
+/// Returns the exit code for the launcher.
+fn launch_headless(
+    list: AddonList,
+    args: &LauncherArgs,
+    manifest: &Path,
+    addon_dir: PathBuf,
+    arg_order: ArgOrder,
+) -> i32 {
+    let config = match headless_config(args, &list.addons) {
+        Ok(config) => config,
+        Err(errors) => {
+            errors.iter().for_each(|error| eprintln!("{}", error));
+            return 1;
+        }
+    };
+    let mut manager = AddonManager::new(list.addons, Box::from([]), Some(config));
+    manager.list = list.settings;
+    manager.manifest = manifest.to_string_lossy().into_owned();
+    manager.addon_dir = addon_dir;
+    manager.arg_order = arg_order;
+    if let Some(wine) = &args.wine {
+        manager.wine = wine.clone();
+    }
+    manager.ignore_case = args.ignore_case;
+    let command = manager.prepare_launch();
+    if let Some(message) = manager.popup.take() {
+        eprintln!("{}", message);
+    }
+    let command = match command {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    if manager.needs_preflight() {
+        if let Err(e) = check_preflight(&command, command.preflight(manager.gzdoom_build())) {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+    if args.verbose {
+        eprintln!("> {}", command.command_line());
     }
+    match command.spawn().and_then(|mut child| child.wait()) {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            eprintln!("{}", LaunchError::LaunchFailed(command.program.clone(), e));
+            1
+        }
+    }
+}
+
+/// Start the command, and watch it in the background to see whether it crashes
+/// on startup
+fn watch_command(
+    command: LaunchCommand,
+    build: GZDoomBuildSelection,
+    grace: Duration,
+    settings: Persistence,
+) -> WatchedGame {
+    let result = Arc::new(Mutex::new(None));
+    let slot = Arc::clone(&result);
+    let watched = command.clone();
+    thread::spawn(move || {
+        let startup = watched.spawn_watched(grace);
+        if let Ok(mut slot) = slot.lock() {
+            *slot = Some(startup);
+        }
+    });
+    WatchedGame { result, command, build, settings }
 }
 
 #[derive(Debug)]
 enum LaunchError {
     GZDoomBuildNotOpenable,
     GZDoomBuildNotExecutable,
+    GZDoomBuildBrokenLink(PathBuf),
     IWADNotFound,
     IWADNotIWAD,
-    LaunchFailed(Box<dyn Error>),
+    IWADIsPWAD,
+    IWADNotZip,
+    SaveGameNotFound,
+    ExecScriptNotFound(String),
+    UnclosedQuote(char),
+    ConflictingWorkingDirs(Vec<String>),
+    BadWorkingDir(String),
+    ConflictingAddons(String, String),
+    MissingAddonFiles(String, Vec<String>),
+    UnavailableRequirements(Vec<String>),
+    PreflightFailed(String),
+    CrashedOnStartup(String),
+    ExitedWithError(String),
+    LaunchFailed(String, io::Error),
     FailedWait(Box<dyn Error>),
 }
 
+/// OS error code for an executable which cannot be run on this system
+#[cfg(not(target_family = "windows"))]
+const BAD_EXECUTABLE_FORMAT: i32 = 8; // ENOEXEC
+#[cfg(target_family = "windows")]
+const BAD_EXECUTABLE_FORMAT: i32 = 193; // ERROR_BAD_EXE_FORMAT
+
 impl std::fmt::Display for LaunchError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let thing_to_print = match self {
@@ -465,9 +3146,70 @@ impl std::fmt::Display for LaunchError {
             LaunchError::GZDoomBuildNotExecutable => {
                 String::from("Selected GZDoom build is not an executable!")
             }
+            LaunchError::GZDoomBuildBrokenLink(target) => format!(
+                "Selected GZDoom build is a link to {:?}, which doesn't exist. \
+                Fix the link, or select another build.",
+                target
+            ),
             LaunchError::IWADNotFound => String::from("Cannot open IWAD"),
             LaunchError::IWADNotIWAD => String::from("Selected IWAD is not an IWAD!"),
-            LaunchError::LaunchFailed(e) => format!("Could not launch GZDoom:\n{:?}", e),
+            LaunchError::IWADIsPWAD => String::from(
+                "The selected IWAD looks like a PWAD, not an IWAD. Add it as an addon instead.",
+            ),
+            LaunchError::IWADNotZip => String::from(
+                "The selected IWAD is not a zip file, so it can't be a zipped IWAD!",
+            ),
+            LaunchError::SaveGameNotFound => String::from("Cannot open saved game"),
+            LaunchError::ExecScriptNotFound(script) => {
+                format!("Cannot open exec script {}", script)
+            }
+            LaunchError::UnclosedQuote(quote) => format!(
+                "The extra arguments have a {} which is never closed",
+                quote
+            ),
+            LaunchError::BadWorkingDir(dir) => {
+                format!("Cannot start GZDoom in {}, because it isn't a folder", dir)
+            }
+            LaunchError::ConflictingWorkingDirs(addons) => format!(
+                "The selected addons need different working directories:\n{}",
+                addons.join("\n")
+            ),
+            LaunchError::ConflictingAddons(addon, other) => {
+                format!("{} and {} can't be loaded together. Deselect one of them.", addon, other)
+            }
+            LaunchError::MissingAddonFiles(addon, files) => format!(
+                "{} can't be loaded, because these files are missing:\n{}",
+                addon,
+                files.join("\n")
+            ),
+            LaunchError::UnavailableRequirements(messages) => messages.join("\n"),
+            LaunchError::CrashedOnStartup(output) => {
+                format!("GZDoom quit right after it was started:\n{}", output)
+            }
+            LaunchError::ExitedWithError(output) => {
+                format!("GZDoom exited with an error:\n{}", output)
+            }
+            LaunchError::PreflightFailed(output) => format!(
+                "GZDoom could not load the selected IWAD and addons:\n{}",
+                output
+            ),
+            LaunchError::LaunchFailed(executable, e) => {
+                let hint = match e.kind() {
+                    io::ErrorKind::NotFound => {
+                        format!("Executable not found at {}", executable)
+                    }
+                    io::ErrorKind::PermissionDenied => format!(
+                        "Permission denied when running {}. Is the file executable?",
+                        executable
+                    ),
+                    _ if e.raw_os_error() == Some(BAD_EXECUTABLE_FORMAT) => format!(
+                        "{} is not a valid executable for this system.",
+                        executable
+                    ),
+                    _ => String::from("Could not launch GZDoom"),
+                };
+                format!("{}\n\nDetails: {}", hint, e)
+            }
             LaunchError::FailedWait(e) => format!("Failed to wait on child process:\n{:?}", e),
         };
         write!(f, "{}", thing_to_print)?;
@@ -479,16 +3221,55 @@ impl Error for LaunchError {}
 
 impl App for AddonManager {
     fn update(&mut self, ctx: &egui::Context, _eframe: &mut Frame) {
+        if self.poll_discovery() | self.poll_update_check() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        let iwad = String::from(self.iwad());
+        if self.iwad_kind.as_ref().map_or(true, |(path, _)| *path != iwad) {
+            let kind = iwad_game(&iwad);
+            self.iwad_kind = Some((iwad, kind));
+        }
+        let iwad_kind = self.iwad_kind.as_ref().and_then(|(_, kind)| *kind);
+        // The theme can also be changed by loading a profile
+        if ctx.style().visuals.dark_mode == self.light_theme {
+            ctx.set_visuals(self.theme().egui_visuals());
+        }
+        // The launch command is being tried with -norun, or GZDoom is being
+        // watched on startup with "quit on launch"
+        let starting = self.poll_preflight() || self.poll_watching();
+        let running = self.poll_running() || starting;
+        if running {
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+        if let Some((modifiers, key)) = parse_hotkey(&self.pinned_hotkey) {
+            if self.pinned.is_some() && !running && ctx.input_mut(|i| i.consume_key(modifiers, key)) {
+                self.launch_pinned();
+            }
+        }
+        // Don't steal F5 from a focused text field
+        if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::F5)) {
+            self.reload_and_report();
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.discovery.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Looking for GZDoom builds, IWADs and saved games...");
+                });
+            }
+            ui.horizontal(|ui| self.profile_row(ui));
+            ui.separator();
+
             match &mut self.selected_gzdoom_build {
                 GZDoomBuildSelection::Single => {}
                 GZDoomBuildSelection::ListIndex(bindex) => {
                     egui::ComboBox::from_label("GZDoom build")
-                        .selected_text(self.builds.get(*bindex).unwrap_or(&String::from("None")))
+                        .selected_text(self.builds.get(*bindex).map(Build::label).unwrap_or("None"))
                         .width(400.)
                         .show_ui(ui, |ui| {
                             self.builds.iter().enumerate().for_each(|(index, build)| {
-                                ui.selectable_value(bindex, index, build);
+                                ui.selectable_value(bindex, index, build.label())
+                                    .on_hover_text(&build.path);
                             });
                         });
                     ui.separator();
@@ -497,12 +3278,23 @@ impl App for AddonManager {
                     ui.horizontal(|ui| {
                         ui.label("GZDoom build:");
                         ui.add(egui::TextEdit::singleline(path));
+                        if !self.recent_builds.is_empty() {
+                            egui::ComboBox::from_id_source("recent_builds")
+                                .selected_text("Recent")
+                                .show_ui(ui, |ui| {
+                                    self.recent_builds.iter().for_each(|recent| {
+                                        if ui.selectable_label(*recent == *path, recent.as_str()).clicked() {
+                                            *path = recent.clone();
+                                        }
+                                    });
+                                })
+                                .response
+                                .on_hover_text("GZDoom builds launched before");
+                        }
                         if ui.button("Browse").clicked() {
-                            if let Ok(choice) =
-                                native_dialog::FileDialog::new().show_open_single_file()
-                            {
+                            if let Ok(choice) = browse(&mut self.browse_dirs, Picker::Builds, None) {
                                 if let Some(choice) = choice {
-                                    if is_executable(&choice) {
+                                    if usable_build(&choice) {
                                         *path = String::from(choice.to_str().unwrap_or(""));
                                     } else {
                                         self.popup =
@@ -518,17 +3310,39 @@ impl App for AddonManager {
                 }
             }
 
+            ui.horizontal(|ui| self.source_port_row(ui));
+
+            if self.can_use_wine() {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.use_wine, "Run with Wine")
+                        .on_hover_text("The selected GZDoom build is a Windows program");
+                    ui.add_enabled_ui(self.use_wine, |ui| {
+                        ui.label("Wine:");
+                        ui.text_edit_singleline(&mut self.wine)
+                            .on_hover_text("wine, or the path to Wine or Proton's wine");
+                        ui.label("Prefix:");
+                        ui.text_edit_singleline(&mut self.wine_prefix)
+                            .on_hover_text("WINEPREFIX to use. Leave empty for the default.");
+                    });
+                });
+                ui.separator();
+            }
+
             match &mut self.selected_iwad {
                 GZDoomBuildSelection::Single => {}
                 GZDoomBuildSelection::ListIndex(bindex) => {
-                    egui::ComboBox::from_label("IWAD")
-                        .selected_text(self.builds.get(*bindex).unwrap_or(&String::from("None")))
-                        .width(400.)
-                        .show_ui(ui, |ui| {
-                            self.builds.iter().enumerate().for_each(|(index, build)| {
-                                ui.selectable_value(bindex, index, build);
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("IWAD")
+                            .selected_text(self.iwads.get(*bindex).map(String::as_str).unwrap_or("None"))
+                            .width(400.)
+                            .show_ui(ui, |ui| {
+                                self.iwads.iter().enumerate().for_each(|(index, iwad)| {
+                                    let game = self.iwad_games.get(iwad).copied();
+                                    ui.selectable_value(bindex, index, iwads::iwad_label(iwad, game));
+                                });
                             });
-                        });
+                        iwad_kind_label(ui, iwad_kind);
+                    });
                     ui.separator();
                 }
                 GZDoomBuildSelection::FullPath(path) => {
@@ -536,53 +3350,239 @@ impl App for AddonManager {
                         ui.label("IWAD:");
                         ui.add(egui::TextEdit::singleline(path));
                         if ui.button("Browse").clicked() {
-                            if let Ok(choice) =
-                                native_dialog::FileDialog::new().show_open_single_file()
-                            {
+                            if let Ok(choice) = browse(&mut self.browse_dirs, Picker::Iwads, None) {
                                 if let Some(choice) = choice {
                                     if is_iwad(&choice) {
                                         *path = String::from(choice.to_str().unwrap_or(""));
                                     } else {
-                                        self.popup = Some(format!("{:?} is not an IWAD!", choice));
+                                        self.popup = Some(not_iwad_message(&choice));
                                     }
                                 }
                             } else {
                                 self.popup = Some(format!("File browser unavailable"));
                             }
                         }
+                        iwad_kind_label(ui, iwad_kind);
                     });
                     ui.separator();
                 }
             }
 
-            egui::ComboBox::from_label("Primary addon")
-                .selected_text(
-                    self.primary_addons
-                        .get(self.selected_primary_addon)
-                        .unwrap_or(&String::from("None")),
-                )
-                .width(400.)
-                .show_ui(ui, |ui| {
-                    self.primary_addons
-                        .iter()
-                        .enumerate()
-                        .for_each(|(index, addon)| {
-                            ui.selectable_value(&mut self.selected_primary_addon, index, addon);
-                        });
-                });
+            let previous_primary = self.selected_primary_addon;
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Primary addon")
+                    .selected_text(
+                        self.primary_addons
+                            .get(self.selected_primary_addon)
+                            .unwrap_or(&String::from("None")),
+                    )
+                    .width(400.)
+                    .show_ui(ui, |ui| {
+                        let mut last_category = None;
+                        for (index, addon) in self.primary_addons.iter().enumerate() {
+                            if self.group_primary_by_category && index > 0 {
+                                let category = self
+                                    .addons
+                                    .get(addon)
+                                    .and_then(|addon| addon.category.as_deref());
+                                if index == 1 || category != last_category {
+                                    ui.separator();
+                                    ui.label(
+                                        egui::RichText::new(category.unwrap_or("Uncategorized"))
+                                            .weak(),
+                                    );
+                                }
+                                last_category = category;
+                            }
+                            let spec = self.addons.get(addon);
+                            let supported = spec.map_or(true, AddonSpecification::supported_here);
+                            let available = spec.map_or(true, |addon| addon.missing.is_empty());
+                            let label =
+                                egui::SelectableLabel::new(self.selected_primary_addon == index, addon);
+                            let response = ui
+                                .horizontal(|ui| {
+                                    file_status(ui, spec);
+                                    ui.add_enabled(supported && available, label)
+                                })
+                                .inner;
+                            if response.clicked() {
+                                self.selected_primary_addon = index;
+                            }
+                            let note = spec.and_then(|addon| {
+                                addon.platform_note().or_else(|| addon.missing_note())
+                            });
+                            let response = match note {
+                                Some(note) => response.on_disabled_hover_text(note),
+                                None => response,
+                            };
+                            if let Some(tooltip) = addon_tooltip(spec, None) {
+                                response.on_hover_text(tooltip);
+                            }
+                        }
+                    });
+                if let Some(name) = self.primary_addons.get(self.selected_primary_addon) {
+                    if let Some(addon) = self.addons.get(name) {
+                        variant_combo(ui, name, addon, &mut self.selected_variants);
+                        map_combo(ui, name, addon, &mut self.selected_maps);
+                    }
+                }
+                if ui
+                    .checkbox(&mut self.group_primary_by_category, "Group by category")
+                    .changed()
+                {
+                    self.regroup_primary_addons();
+                }
+            });
+            if self.selected_primary_addon != previous_primary {
+                self.apply_rules();
+                self.apply_requirements();
+                let primary = &self.primary_addons[self.selected_primary_addon];
+                if self.addons.get(primary).map_or(false, AddonSpecification::prompts_for_optional) {
+                    self.optional_prompt = Some(primary.clone());
+                }
+            }
 
             ui.separator();
 
+            let standalone = self.primary_is_standalone();
             egui::CollapsingHeader::new("Secondary addons")
                 .default_open(self.secondary_addons.len() <= 4)
+                .show(ui, |ui| ui.add_enabled_ui(!standalone, |ui| {
+                    if standalone {
+                        ui.label("The selected primary addon can't be combined with secondary addons.");
+                    }
+                    // Filtering only affects what is shown. Hidden addons
+                    // keep their selection state, and are still launched.
+                    let query = self.secondary_filter.to_lowercase();
+                    ui.horizontal(|ui| {
+                        ui.label("Search:");
+                        ui.text_edit_singleline(&mut self.secondary_filter);
+                        if ui
+                            .add_enabled(!query.is_empty(), egui::Button::new("Clear"))
+                            .clicked()
+                        {
+                            self.secondary_filter.clear();
+                        }
+                        ui.checkbox(&mut self.search_descriptions, "Search in descriptions");
+                        if !query.is_empty() {
+                            let shown = (0..self.secondary_addons.len())
+                                .filter(|&index| self.secondary_matches(index, &query).is_some())
+                                .count();
+                            ui.label(format!("{} of {} shown", shown, self.secondary_addons.len()));
+                        }
+                    });
+                    let required: Vec<String> = self.required_addons().into_iter().cloned().collect();
+                    let mut changed = false;
+                    let groups = self.secondary_groups();
+                    egui::ScrollArea::vertical()
+                        .max_height(self.secondary_height)
+                        .min_scrolled_height(self.secondary_height)
+                        .show(ui, |ui| {
+                            if groups.len() == 1 && groups[0].0.is_none() {
+                                groups[0].1.iter().for_each(|&index| {
+                                    changed |= self.secondary_addon_row(ui, index, &query, &required);
+                                });
+                                return;
+                            }
+                            groups.iter().for_each(|(category, indices)| {
+                                // Leave out categories without any matches
+                                let matches = indices
+                                    .iter()
+                                    .any(|&index| self.secondary_matches(index, &query).is_some());
+                                if !matches {
+                                    return;
+                                }
+                                let category = category.as_deref().unwrap_or("Uncategorized");
+                                egui::CollapsingHeader::new(category)
+                                    .id_source(("secondary_category", category))
+                                    .default_open(true)
+                                    .show(ui, |ui| {
+                                        indices.iter().for_each(|&index| {
+                                            changed |=
+                                                self.secondary_addon_row(ui, index, &query, &required);
+                                        });
+                                    });
+                            });
+                        });
+                    if changed {
+                        self.apply_requirements();
+                    }
+                    // Drag handle for resizing the list
+                    let (rect, handle) = ui.allocate_exact_size(
+                        egui::vec2(ui.available_width(), 8.0),
+                        egui::Sense::drag(),
+                    );
+                    let stroke = ui.style().interact(&handle).fg_stroke;
+                    ui.painter().hline(rect.x_range(), rect.center().y, stroke);
+                    if handle.dragged() {
+                        self.secondary_height = (self.secondary_height + handle.drag_delta().y)
+                            .clamp(MIN_SECONDARY_HEIGHT, MAX_SECONDARY_HEIGHT);
+                    }
+                    handle.on_hover_cursor(egui::CursorIcon::ResizeVertical);
+                }));
+
+            let credits: Vec<(&String, String)> = self
+                .selected_addon_names()
+                .into_iter()
+                .filter_map(|name| Some((name, self.addons.get(name)?.credits()?)))
+                .collect();
+            if !credits.is_empty() {
+                egui::CollapsingHeader::new("Addon details").show(ui, |ui| {
+                    egui::Grid::new("addon_details").num_columns(2).show(ui, |ui| {
+                        credits.iter().for_each(|(name, credits)| {
+                            ui.label(name.as_str());
+                            ui.label(egui::RichText::new(credits).weak());
+                            ui.end_row();
+                        });
+                    });
+                });
+            }
+
+            egui::CollapsingHeader::new("Optional files")
+                .default_open(false)
                 .show(ui, |ui| {
-                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
-                    self.selected_secondary_addons
-                        .iter_mut()
-                        .zip(self.secondary_addons.iter())
-                        .for_each(|(selected, name)| {
-                            ui.checkbox(selected, name);
+                    ui.add_enabled(
+                        self.list.optional_files.is_none(),
+                        egui::Checkbox::new(
+                            &mut self.opt_in_optional,
+                            "Only load the optional files I pick",
+                        ),
+                    )
+                    .on_disabled_hover_text("The addon list sets how optional files are loaded");
+                    let files = self.optional_files();
+                    if files.is_empty() {
+                        ui.label(match self.optional_opt_in() {
+                            true => "The selected addons have no optional files.",
+                            false => "Optional files are loaded when they exist.",
                         });
+                    }
+                    files.into_iter().for_each(|(addon, file)| {
+                        let mut selected = self.optional_picked(&addon, &file);
+                        if ui
+                            .checkbox(&mut selected, format!("{}: {}", addon, file_name(&file)))
+                            .on_hover_text(&file)
+                            .changed()
+                        {
+                            self.pick_optional(&addon, &file, selected);
+                        }
+                    });
+                });
+
+            egui::CollapsingHeader::new("Load order")
+                .default_open(false)
+                .show(ui, |ui| {
+                    self.secondary_order_list(ui);
+                    ui.label("Files are loaded from top to bottom. Later files override earlier ones.");
+                    egui::Grid::new("load_order").striped(true).show(ui, |ui| {
+                        self.load_order().into_iter().enumerate().for_each(
+                            |(index, (addon, file))| {
+                                ui.label(format!("{}.", index + 1));
+                                ui.label(if index == 0 { "" } else { "↓" });
+                                ui.code(file);
+                                ui.label(egui::RichText::new(addon).weak());
+                                ui.end_row();
+                            },
+                        );
                     });
                 });
 
@@ -595,28 +3595,448 @@ impl App for AddonManager {
                     ui.label("and/or run GZDoom under another executable, just like");
                     ui.label("the Steam launch options. See this for more information:");
                     ui.hyperlink("https://superuser.com/q/954041");
+                    ui.label("$VAR and ${VAR} are replaced with environment variables.");
                 });
             });
 
+            egui::CollapsingHeader::new("Environment")
+                .default_open(false)
+                .show(ui, |ui| self.environment_preview(ui));
+
+            egui::CollapsingHeader::new("Import Steam launch options")
+                .default_open(false)
+                .show(ui, |ui| self.launch_options_import(ui));
+
+            egui::CollapsingHeader::new("Share loadout")
+                .default_open(false)
+                .show(ui, |ui| self.loadout_sharing(ui));
+
             ui.horizontal(|ui| {
                 ui.label("Configuration file name:");
                 ui.text_edit_singleline(&mut self.config);
+                ui.checkbox(&mut self.backup_config, "Back up before launching")
+                    .on_hover_text("Copy the configuration file to a .bak file before GZDoom starts");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Working directory:");
+                ui.text_edit_singleline(&mut self.launch_dir).on_hover_text(
+                    "Folder to start GZDoom in. Leave empty to use the one addons ask for, \
+                    or the launcher's.",
+                );
+                if ui.button("Browse").clicked() {
+                    match browse_folder(&self.launch_dir) {
+                        Ok(Some(dir)) => self.launch_dir = String::from(dir.to_str().unwrap_or("")),
+                        Ok(None) => {}
+                        Err(_) => self.show_message(String::from("File browser unavailable")),
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Load saved game:");
+                match &self.saves {
+                    Some(saves) => {
+                        let selected_text = saves
+                            .iter()
+                            .find(|save| save.path.as_os_str() == self.load_game.as_str())
+                            .map(SaveGame::label)
+                            .unwrap_or_else(|| match self.load_game.len() {
+                                0 => String::from("None"),
+                                _ => self.load_game.clone(),
+                            });
+                        egui::ComboBox::from_id_source("load_game")
+                            .selected_text(selected_text)
+                            .width(400.)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.load_game, String::new(), "None");
+                                saves.iter().for_each(|save| {
+                                    let path = save.path.to_string_lossy().into_owned();
+                                    ui.selectable_value(&mut self.load_game, path, save.label());
+                                });
+                            });
+                    }
+                    None => {
+                        ui.text_edit_singleline(&mut self.load_game);
+                        if ui.button("Browse").clicked() {
+                            let filter = ("GZDoom saved game", &["zds"][..]);
+                            if let Ok(choice) =
+                                browse(&mut self.browse_dirs, Picker::Files, Some(filter))
+                            {
+                                if let Some(choice) = choice {
+                                    self.load_game = String::from(choice.to_str().unwrap_or(""));
+                                }
+                            } else {
+                                self.show_message(String::from("File browser unavailable"));
+                            }
+                        }
+                    }
+                }
+                ui.checkbox(&mut self.remember_load_game, "Remember");
+            });
+
+            egui::CollapsingHeader::new("Exec scripts")
+                .default_open(!self.exec_scripts.is_empty())
+                .show(ui, |ui| {
+                    ui.label("Console scripts run with +exec when GZDoom starts, in this order.");
+                    let mut removed = None;
+                    let mut unavailable = false;
+                    self.exec_scripts.iter_mut().enumerate().for_each(|(index, script)| {
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(script);
+                            if ui.button("Browse").clicked() {
+                                match browse(&mut self.browse_dirs, Picker::Files, None) {
+                                    Ok(Some(choice)) => {
+                                        *script = String::from(choice.to_str().unwrap_or(""));
+                                    }
+                                    Ok(None) => {}
+                                    Err(_) => unavailable = true,
+                                }
+                            }
+                            if ui.button("Remove").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    });
+                    if let Some(index) = removed {
+                        self.exec_scripts.remove(index);
+                    }
+                    if unavailable {
+                        self.show_message(String::from("File browser unavailable"));
+                    }
+                    if ui.button("Add script").clicked() {
+                        self.exec_scripts.push(String::new());
+                    }
+                });
+
+            egui::CollapsingHeader::new("File browser folders").show(ui, |ui| {
+                ui.label("Each Browse button starts in the last folder used for that kind of file.");
+                egui::Grid::new("browse_dirs").num_columns(2).show(ui, |ui| {
+                    Picker::ALL.into_iter().for_each(|picker| {
+                        ui.label(picker.label());
+                        let mut dir = self.browse_dirs.get(&picker).cloned().unwrap_or_default();
+                        let edit = ui
+                            .text_edit_singleline(&mut dir)
+                            .on_hover_text("Leave empty to start in the default folder");
+                        if edit.changed() {
+                            match dir.is_empty() {
+                                true => self.browse_dirs.remove(&picker),
+                                false => self.browse_dirs.insert(picker, dir),
+                            };
+                        }
+                        ui.end_row();
+                    });
+                });
             });
 
+            if !self.failed_launches.is_empty() {
+                egui::CollapsingHeader::new("Recent failed launches")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let mut reproduce = None;
+                        egui::Grid::new("failed_launches").show(ui, |ui| {
+                            self.failed_launches.iter().enumerate().for_each(|(index, failed)| {
+                                ui.label(failed.summary());
+                                ui.label(
+                                    egui::RichText::new(failed.error.lines().next().unwrap_or_default())
+                                        .weak(),
+                                )
+                                .on_hover_text(&failed.error);
+                                if ui
+                                    .button("Reproduce")
+                                    .on_hover_text("Go back to the settings this launch was tried with")
+                                    .clicked()
+                                {
+                                    reproduce = Some(index);
+                                }
+                                ui.end_row();
+                            });
+                        });
+                        if let Some(index) = reproduce {
+                            self.reproduce(index);
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.failed_launches.clear();
+                        }
+                    });
+            }
+
             ui.separator();
 
             ui.horizontal(|ui| {
-                if ui.button("Launch").clicked() {
-                    if let Err(e) = self.try_launch() {
+                let launch_text = match (starting, running) {
+                    (true, _) => "Starting...",
+                    (false, true) => "Running...",
+                    (false, false) => "Launch",
+                };
+                let launch = ui
+                    .add_enabled(!running, egui::Button::new(launch_text))
+                    .on_disabled_hover_text("GZDoom is still running");
+                if launch.clicked() {
+                    self.launch();
+                }
+                if ui.button("Preview command").clicked() {
+                    self.command_preview_open = true;
+                }
+                if ui.button("GZDoom output").clicked() {
+                    self.log_open = true;
+                }
+                if ui
+                    .button("Copy command")
+                    .on_hover_text("Copy the command which would be launched, to paste into a bug report")
+                    .clicked()
+                {
+                    let command_line = self.build_command().command_line();
+                    ui.output_mut(|output| output.copied_text = command_line);
+                }
+                ui.checkbox(&mut self.preflight, "Check first").on_hover_text(
+                    "Run GZDoom with -norun before launching, to catch a bad IWAD or addon. \
+                    Skipped if the GZDoom build doesn't support -norun.",
+                );
+                ui.checkbox(&mut self.quit_on_launch, "Quit after launching").on_hover_text(
+                    "Quit once GZDoom has been running for a few seconds. \
+                    If it crashes before then, the launcher stays open.",
+                );
+                ui.add_enabled(
+                    self.quit_on_launch,
+                    egui::DragValue::new(&mut self.startup_grace)
+                        .clamp_range(0..=MAX_STARTUP_GRACE)
+                        .suffix(" s"),
+                )
+                .on_hover_text("How long GZDoom has to keep running before the launcher quits");
+
+                let relaunchable = self
+                    .last_command
+                    .as_ref()
+                    .map(LaunchCommand::files_exist)
+                    .unwrap_or(false);
+                let relaunch = ui
+                    .add_enabled(relaunchable && !running, egui::Button::new("Relaunch last"))
+                    .on_hover_text("Run the last successful launch command again, exactly as it was")
+                    .on_disabled_hover_text(match self.last_command {
+                        _ if running => "GZDoom is still running",
+                        Some(_) => "Some files used by the last launch no longer exist",
+                        None => "Nothing has been launched yet",
+                    });
+                if relaunch.clicked() {
+                    if let Err(e) = self.relaunch() {
                         self.popup = Some(e.to_string());
                     }
                 }
-
-                if ui.button("Exit").clicked() {
-                    ctx.send_viewport_cmd(ViewportCommand::Close);
-                }
-            });
-        });
+
+                ui.separator();
+                ui.label("Theme:");
+                ui.selectable_value(&mut self.light_theme, false, "Dark");
+                ui.selectable_value(&mut self.light_theme, true, "Light");
+                ui.separator();
+
+                if ui.button("Exit").clicked() && self.request_exit() {
+                    ctx.send_viewport_cmd(ViewportCommand::Close);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Reload addons")
+                    .on_hover_text(format!("Read {} again, keeping what is selected (F5)", self.manifest))
+                    .clicked()
+                {
+                    self.reload_and_report();
+                }
+                if self.list.manifest_url.is_some() {
+                    let checking = self.update_check.is_some();
+                    let check_text = if checking { "Checking for updates..." } else { "Check for addon updates" };
+                    let check = ui
+                        .add_enabled(self.allow_network && !checking, egui::Button::new(check_text))
+                        .on_hover_text("Download the latest addon list, and see what changed")
+                        .on_disabled_hover_text(if checking {
+                            "The addon list is being downloaded"
+                        } else {
+                            "Start this program with --allow-network to check for updates"
+                        });
+                    if check.clicked() {
+                        self.check_for_updates();
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Pin current settings")
+                    .on_hover_text("Remember what is selected now, to launch it with the hotkey")
+                    .clicked()
+                {
+                    self.pinned = Some(Box::new(self.snapshot()));
+                }
+                if let Some(pinned) = &self.pinned {
+                    let label = pinned.primary_addon.as_deref().unwrap_or("No primary addon");
+                    ui.label(format!("Pinned: {}", label));
+                    if ui.add_enabled(!running, egui::Button::new("Launch pinned")).clicked() {
+                        self.launch_pinned();
+                    }
+                    if ui.button("Unpin").clicked() {
+                        self.pinned = None;
+                    }
+                }
+                ui.label("Hotkey:");
+                let valid = parse_hotkey(&self.pinned_hotkey).is_some();
+                let hotkey = egui::TextEdit::singleline(&mut self.pinned_hotkey).desired_width(100.);
+                let hotkey = ui.add(hotkey).on_hover_text("Like Ctrl+Enter, Shift+F9 or Alt+L");
+                if !valid && !hotkey.has_focus() {
+                    ui.label(egui::RichText::new("Not a valid hotkey").color(ui.visuals().error_fg_color));
+                }
+            });
+        });
+        if let Some(name) = self.optional_prompt.clone() {
+            let files = self.present_optional_files(&name);
+            let mut open = true;
+            let mut done = false;
+            egui::Window::new(format!("Optional files for {}", name))
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label("Pick which optional files to load:");
+                    files.iter().for_each(|file| {
+                        let mut selected = self.optional_picked(&name, file);
+                        if ui
+                            .checkbox(&mut selected, file_name(file))
+                            .on_hover_text(file)
+                            .changed()
+                        {
+                            self.pick_optional(&name, file, selected);
+                        }
+                    });
+                    if ui.button("Done").clicked() {
+                        done = true;
+                    }
+                });
+            if !open || done || files.is_empty() {
+                self.optional_prompt = None;
+            }
+        }
+        if self.log_open {
+            let mut open = true;
+            egui::Window::new("GZDoom output")
+                .open(&mut open)
+                .default_width(600.)
+                .show(ctx, |ui| {
+                    if ui.button("Clear log").clicked() {
+                        if let Ok(mut log) = self.game_log.lock() {
+                            log.clear();
+                        }
+                    }
+                    let log = self.game_log.lock().map(|log| log.clone()).unwrap_or_default();
+                    egui::ScrollArea::vertical()
+                        .max_height(400.)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut log.as_str())
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY),
+                            );
+                        });
+                });
+            if !open {
+                self.log_open = false;
+            }
+        }
+        if self.command_preview_open {
+            let command = self.build_command();
+            let command_line = command.command_line();
+            let mut open = true;
+            egui::Window::new("Launch command")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label("This is what will run when you click Launch:");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut command_line.as_str())
+                            .code_editor()
+                            .desired_width(500.),
+                    );
+                    if let Some(dir) = &command.working_dir {
+                        ui.label(format!("Working directory: {}", dir));
+                    }
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|output| output.copied_text = command_line.clone());
+                    }
+                });
+            if !open {
+                self.command_preview_open = false;
+            }
+        }
+        if self.iwad_help_open {
+            let help = self.iwad_help();
+            let mut open = true;
+            let mut close = false;
+            egui::Window::new("IWAD not found")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label(format!("Cannot open the IWAD: {}", self.iwad()));
+                    if let Some(hint) = &help.hint {
+                        ui.label(hint);
+                    }
+                    help.links.iter().for_each(|link| {
+                        ui.hyperlink_to(&link.label, &link.url);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Locate IWAD...").clicked() && self.locate_iwad() {
+                            close = true;
+                        }
+                        if ui.button("Close").clicked() {
+                            close = true;
+                        }
+                    });
+                });
+            if !open || close {
+                self.iwad_help_open = false;
+            }
+        }
+        if let Some(update) = &self.manifest_update {
+            let mut open = true;
+            let mut replace = false;
+            let mut cancel = false;
+            egui::Window::new("Addon list update")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    if let Some(version) = &update.version {
+                        ui.label(format!("Version {} of the addon list is available.", version));
+                    }
+                    ui.label("Changes to the addon list:");
+                    egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                        update.diff.iter().for_each(|line| {
+                            let (prefix, text, color) = match line {
+                                DiffLine::Same(text) => (" ", text, ui.visuals().weak_text_color()),
+                                DiffLine::Removed(text) => ("-", text, ui.visuals().error_fg_color),
+                                DiffLine::Added(text) => ("+", text, egui::Color32::from_rgb(96, 192, 96)),
+                            };
+                            ui.label(
+                                egui::RichText::new(format!("{} {}", prefix, text))
+                                    .monospace()
+                                    .color(color),
+                            );
+                        });
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Replace addons.yml").clicked() {
+                            replace = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if replace {
+                if let Some(update) = self.manifest_update.take() {
+                    self.apply_update(update);
+                }
+            } else if !open || cancel {
+                self.manifest_update = None;
+            }
+        }
         if let Some(msg) = &self.popup {
             // Work around borrow checker. Argh.
             let mut open = true;
@@ -633,12 +4053,1330 @@ impl App for AddonManager {
                 self.popup = None;
             }
         }
+        if self.exit_prompt_open {
+            let mut open = true;
+            let mut choice = None;
+            let mut cancel = false;
+            egui::Window::new("Unsaved changes")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.label("The settings have changed since they were last saved.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Save and exit").clicked() {
+                            choice = Some(true);
+                        }
+                        if ui.button("Exit without saving").clicked() {
+                            choice = Some(false);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+            if let Some(save) = choice {
+                self.confirm_exit(save);
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+            } else if !open || cancel {
+                self.exit_prompt_open = false;
+            }
+        }
+        // The window's close button, or closing it some other way
+        if ctx.input(|i| i.viewport().close_requested()) && !self.request_exit() {
+            ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+        }
+        if self.quit_requested {
+            ctx.send_viewport_cmd(ViewportCommand::Close);
+        }
     }
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if self.discard_changes {
+            return;
+        }
         let data: Persistence = Persistence::from(&*self);
         data.save(storage);
+        self.saved_settings = Some(data);
     }
     fn persist_egui_memory(&self) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testdir::TestDir;
+
+    fn addon(required: &str, secondary: bool, standalone: bool) -> AddonSpecification {
+        AddonSpecification {
+            required: vec![String::from(required)],
+            secondary: secondary.then(|| String::from("1")),
+            standalone: standalone.then_some(true),
+            ..Default::default()
+        }
+    }
+
+    fn test_addons() -> AddonMap {
+        AddonMap::from([
+            (String::from("Campaign"), addon("campaign.pk3", false, false)),
+            (String::from("Conversion"), addon("conversion.pk3", false, true)),
+            (String::from("Filter"), addon("filter.pk3", true, false)),
+        ])
+    }
+
+    fn select_primary(manager: &mut AddonManager, name: &str) {
+        manager.selected_primary_addon = manager
+            .primary_addons
+            .iter()
+            .position(|addon| addon == name)
+            .unwrap();
+    }
+
+    fn launched_files(manager: &AddonManager) -> Vec<String> {
+        let arguments = manager.build_command().arguments;
+        let files = arguments.iter().position(|arg| arg == "-file").unwrap() + 1;
+        arguments[files..].to_vec()
+    }
+
+    #[test]
+    fn deh_patches() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().deh = Some(vec![String::from("campaign.deh")]);
+        addons.get_mut("Filter").unwrap().deh = Some(vec![String::from("filter.bex")]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        let command = manager.build_command();
+        let files = command.arguments.iter().position(|arg| arg == "-file").unwrap();
+        assert_eq!(
+            command.arguments[files..files + 6],
+            ["-file", "campaign.pk3", "filter.pk3", "-deh", "campaign.deh", "filter.bex"]
+        );
+        assert!(command.files.contains(&String::from("filter.bex")));
+
+        // Patches of addons which aren't loaded are left out
+        select_primary(&mut manager, "Conversion");
+        assert!(!manager.build_command().arguments.contains(&String::from("-deh")));
+    }
+
+    #[test]
+    fn addon_arguments() {
+        let mut addons = test_addons();
+        let args = |args: &[&str]| Some(args.iter().map(|arg| String::from(*arg)).collect());
+        addons.get_mut("Campaign").unwrap().args = args(&["+set", "r_fog", "0"]);
+        addons.get_mut("Filter").unwrap().args = args(&["+set", "hud_name", "Double spaced"]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.exargs = String::from("-nomonsters");
+        assert_eq!(
+            manager.build_command().arguments[..7],
+            ["-nomonsters", "+set", "r_fog", "0", "+set", "hud_name", "Double spaced"]
+        );
+
+        select_primary(&mut manager, "Conversion");
+        assert_eq!(manager.build_command().arguments[..1], ["-nomonsters"]);
+        assert!(!manager.build_command().arguments.contains(&String::from("r_fog")));
+    }
+
+    #[test]
+    fn addon_environment() {
+        let mut addons = test_addons();
+        let vars = |vars: &[(&str, &str)]| {
+            Some(vars.iter().map(|(k, v)| (String::from(*k), String::from(*v))).collect())
+        };
+        addons.get_mut("Campaign").unwrap().env =
+            vars(&[("VKBASALT", "1"), ("CAMPAIGN_DIR", "$TALAUNCHER_TEST_HOME/campaign")]);
+        addons.get_mut("Filter").unwrap().env = vars(&[("VKBASALT", "0"), ("FILTER", "crt")]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.exargs = String::from("FILTER=none %command%");
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(k, v)| (String::from(*k), String::from(*v))).collect::<Vec<_>>()
+        };
+        let lookup = |name: &str| (name == "TALAUNCHER_TEST_HOME").then(|| String::from("/home/doomguy"));
+        assert_eq!(
+            manager.addon_environment_with(&lookup),
+            pairs(&[
+                ("CAMPAIGN_DIR", "/home/doomguy/campaign"),
+                ("FILTER", "crt"),
+                ("VKBASALT", "0"),
+            ])
+        );
+        let campaign_dir = manager.addon_environment()[0].1.clone();
+        assert_eq!(
+            manager.build_command().environment,
+            pairs(&[
+                ("CAMPAIGN_DIR", &campaign_dir),
+                ("VKBASALT", "0"),
+                ("FILTER", "none"),
+            ])
+        );
+
+        select_primary(&mut manager, "Conversion");
+        assert_eq!(manager.build_command().environment, pairs(&[("FILTER", "none")]));
+    }
+
+    #[test]
+    fn standalone_primary() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Conversion");
+        assert!(manager.primary_is_standalone());
+        assert!(manager.secondary_addons().is_empty());
+        assert_eq!(launched_files(&manager), vec!["conversion.pk3"]);
+
+        select_primary(&mut manager, "Campaign");
+        assert!(!manager.primary_is_standalone());
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "filter.pk3"]);
+    }
+
+    #[test]
+    fn headless_launch() {
+        let mut args = LauncherArgs {
+            gzdoom: Some(String::from("/nonexistent/gzdoom")),
+            iwad: Some(String::from("DOOM64.WAD")),
+            primary: Some(String::from("Campaign")),
+            secondary: vec![String::from("Unknown")],
+            ..Default::default()
+        };
+        assert_eq!(
+            headless_config(&args, &test_addons()).unwrap_err(),
+            ["Unknown addon: Unknown"]
+        );
+
+        args.secondary = vec![String::from("Filter")];
+        let config = headless_config(&args, &test_addons()).unwrap();
+        assert_eq!(config.primary_addon.as_deref(), Some("Campaign"));
+        assert_eq!(config.secondary_addons, Some(vec![String::from("Filter")]));
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), Some(config));
+        assert_eq!(manager.primary_addon(), ["campaign.pk3"]);
+        assert_eq!(manager.gzdoom_build(), "/nonexistent/gzdoom");
+        assert_eq!(manager.iwad(), "DOOM64.WAD");
+        // The same checks as launching from the window
+        assert!(matches!(
+            manager.prepare_launch(),
+            Err(LaunchError::GZDoomBuildNotOpenable)
+        ));
+    }
+
+    #[test]
+    fn working_dir() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().cwd = Some(String::from("campaign"));
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(manager.working_dir().unwrap(), Some("campaign"));
+        assert_eq!(manager.build_command().working_dir.as_deref(), Some("campaign"));
+
+        // Same directory twice is fine
+        addons.get_mut("Filter").unwrap().cwd = Some(String::from("campaign"));
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(manager.working_dir().unwrap(), Some("campaign"));
+
+        addons.get_mut("Filter").unwrap().cwd = Some(String::from("filter"));
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert!(matches!(
+            manager.working_dir(),
+            Err(LaunchError::ConflictingWorkingDirs(addons)) if addons.len() == 2
+        ));
+        assert_eq!(manager.build_command().working_dir, None);
+
+        // The user's working directory is used instead of the addons'
+        let temp = TestDir::new("launch-dir");
+        let dir = temp.join("launch");
+        manager.launch_dir = dir.to_string_lossy().into_owned();
+        assert_eq!(manager.working_dir().unwrap(), Some(manager.launch_dir.as_str()));
+        assert_eq!(manager.build_command().working_dir.as_ref(), Some(&manager.launch_dir));
+        assert!(matches!(manager.check_working_dir(), Err(LaunchError::BadWorkingDir(_))));
+        fs::create_dir_all(&dir).unwrap();
+        let result = manager.check_working_dir();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn unclosed_quote() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.exargs = String::from("VKBASALT_CONFIG=\"/my path/cfg %command%");
+        assert!(matches!(manager.try_launch(), Err(LaunchError::UnclosedQuote('"'))));
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn broken_gzdoom_link() {
+        let dir = TestDir::new("broken-link");
+        let link = dir.join("gzdoom");
+        std::os::unix::fs::symlink(dir.join("flatpak-gzdoom"), &link).unwrap();
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(link.to_string_lossy().into());
+        let result = manager.try_launch();
+
+        match result {
+            Err(LaunchError::GZDoomBuildBrokenLink(target)) => {
+                assert_eq!(target, dir.join("flatpak-gzdoom"))
+            }
+            other => panic!("Expected a broken link, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hotkeys() {
+        let ctrl = egui::Modifiers { command: true, ..Default::default() };
+        assert_eq!(parse_hotkey("Ctrl+Enter"), Some((ctrl, egui::Key::Enter)));
+        assert_eq!(parse_hotkey("shift + F9").map(|(m, k)| (m.shift, k)), Some((true, egui::Key::F9)));
+        assert_eq!(parse_hotkey("Enter"), Some((egui::Modifiers::NONE, egui::Key::Enter)));
+        assert_eq!(parse_hotkey("Hyper+Enter"), None);
+        assert_eq!(parse_hotkey("Ctrl+"), None);
+    }
+
+    #[test]
+    fn pinned_missing_addons() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        let mut pinned = manager.snapshot();
+        pinned.primary_addon = Some(String::from("Removed"));
+        manager.pinned = Some(Box::new(pinned));
+        manager.launch_pinned();
+        let popup = manager.popup.unwrap();
+        assert!(popup.contains("Removed"));
+        assert!(popup.contains("Launching the current selection instead"));
+        // The current selection was launched, and failed without a GZDoom build
+        assert_eq!(manager.failed_launches[0].settings.primary_addon.as_deref(), Some("Campaign"));
+    }
+
+    #[test]
+    fn optional_files() {
+        let dir = TestDir::new("optional-files");
+        let extra = dir.join("extra.pk3").to_string_lossy().into_owned();
+        File::create(&extra).unwrap();
+        let missing = dir.join("missing.pk3").to_string_lossy().into_owned();
+        let mut addons = test_addons();
+        addons.get_mut("Conversion").unwrap().optional = Some(vec![extra.clone(), missing]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Conversion");
+
+        let auto = launched_files(&manager);
+        manager.opt_in_optional = true;
+        let opted_out = launched_files(&manager);
+        manager.pick_optional("Conversion", &extra, true);
+        let opted_in = launched_files(&manager);
+        manager.list.optional_files = Some(OptionalFiles::Auto);
+        manager.pick_optional("Conversion", &extra, false);
+        let overridden = launched_files(&manager);
+
+        assert_eq!(auto, vec![String::from("conversion.pk3"), extra.clone()]);
+        assert_eq!(opted_out, vec!["conversion.pk3"]);
+        assert_eq!(opted_in, vec![String::from("conversion.pk3"), extra.clone()]);
+        assert_eq!(overridden, vec![String::from("conversion.pk3"), extra]);
+    }
+
+    #[test]
+    fn prompted_optional_files() {
+        let dir = TestDir::new("prompted-optional-files");
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let (hard, music) = (file("hard.pk3"), file("music.pk3"));
+        File::create(&hard).unwrap();
+        File::create(&music).unwrap();
+        let mut addons = test_addons();
+        let campaign = addons.get_mut("Campaign").unwrap();
+        campaign.optional = Some(vec![hard.clone(), music.clone()]);
+        campaign.prompt_optional = Some(true);
+        addons.get_mut("Filter").unwrap().optional = Some(vec![music.clone()]);
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+
+        // Nothing is picked yet, but the filter's optional file isn't prompted for
+        let nothing_picked = launched_files(&manager);
+        manager.pick_optional("Campaign", &music, true);
+        let music_picked = launched_files(&manager);
+        let remembered = AddonManager::new(addons, Box::from([]), Some(Persistence::from(&manager)));
+        let remembered = launched_files(&remembered);
+        // Picking the campaign's music doesn't pick the filter's
+        manager.opt_in_optional = true;
+        let filter_prompted = launched_files(&manager);
+
+        assert_eq!(
+            nothing_picked,
+            vec![String::from("campaign.pk3"), String::from("filter.pk3"), music.clone()]
+        );
+        assert_eq!(
+            music_picked,
+            vec![String::from("campaign.pk3"), music.clone(), String::from("filter.pk3"), music.clone()]
+        );
+        assert_eq!(remembered, music_picked);
+        assert_eq!(filter_prompted, vec![String::from("campaign.pk3"), music, String::from("filter.pk3")]);
+    }
+
+    #[test]
+    fn discovery() {
+        let builds = vec![
+            Build { path: String::from("/games/gzdoom"), ..Default::default() },
+            Build { path: String::from("/games/lzdoom"), ..Default::default() },
+        ];
+        let found = || Discovery { builds: builds.clone(), ..Default::default() };
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("/games/lzdoom"));
+        manager.use_discovery(found(), "/games/lzdoom");
+        assert_eq!(manager.gzdoom_build(), "/games/lzdoom");
+        assert!(matches!(manager.selected_gzdoom_build, GZDoomBuildSelection::ListIndex(1)));
+
+        // A path typed in while looking for builds is kept
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("/opt/gzdoom"));
+        manager.use_discovery(found(), "");
+        assert_eq!(manager.builds.len(), 2);
+        assert_eq!(manager.gzdoom_build(), "/opt/gzdoom");
+    }
+
+    #[test]
+    fn discovered_iwads() {
+        let found = || Discovery {
+            iwads: vec![
+                FoundIwad { path: String::from("/games/DOOM64.WAD"), game: None },
+                FoundIwad { path: String::from("/games/DOOM2.WAD"), game: Some(IwadKind::Doom2) },
+            ],
+            ..Default::default()
+        };
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("/games/DOOM2.WAD"));
+        manager.use_discovery(found(), "");
+        assert!(matches!(manager.selected_iwad, GZDoomBuildSelection::ListIndex(1)));
+        assert_eq!(manager.iwad(), "/games/DOOM2.WAD");
+        assert_eq!(manager.iwad_games.get("/games/DOOM2.WAD"), Some(&IwadKind::Doom2));
+        assert_eq!(manager.iwad_games.get("/games/DOOM64.WAD"), None);
+
+        // An IWAD which wasn't found is kept, and nothing is chosen without one
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("/nonexistent/DOOM64.WAD"));
+        manager.use_discovery(found(), "");
+        assert_eq!(manager.iwads.len(), 3);
+        assert_eq!(manager.iwad(), "/nonexistent/DOOM64.WAD");
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.use_discovery(found(), "");
+        assert_eq!(manager.iwad(), "/games/DOOM64.WAD");
+
+        // Reloading the addons keeps the list
+        let data = Persistence::from(&manager);
+        manager.rebuild(test_addons(), data);
+        assert_eq!(manager.iwads.len(), 2);
+        assert_eq!(manager.iwad(), "/games/DOOM64.WAD");
+    }
+
+    #[test]
+    fn source_ports() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.builds = Box::from([
+            Build { path: String::from("/opt/zan/zandronum"), ..Default::default() },
+            Build {
+                path: String::from("/usr/bin/flatpak-doom"),
+                port: Some(SourcePort::LZDoom),
+                ..Default::default()
+            },
+        ]);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::ListIndex(0);
+        assert_eq!(manager.source_port(), SourcePort::Zandronum);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::ListIndex(1);
+        assert_eq!(manager.source_port(), SourcePort::LZDoom);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("/opt/doom"));
+        assert_eq!(manager.source_port(), SourcePort::GZDoom);
+        manager.source_port = Some(SourcePort::ZDoom);
+        assert_eq!(manager.source_port(), SourcePort::ZDoom);
+        assert_eq!(manager.detected_source_port(), SourcePort::GZDoom);
+    }
+
+    #[test]
+    fn variants() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().variants = Some(HashMap::from([
+            (String::from("High-res"), vec![String::from("high.pk3")]),
+            (String::from("Low-res"), vec![String::from("low.pk3")]),
+        ]));
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "high.pk3", "filter.pk3"]);
+
+        manager
+            .selected_variants
+            .insert(String::from("Campaign"), String::from("Low-res"));
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "low.pk3", "filter.pk3"]);
+
+        // The choice is remembered
+        let data = Persistence::from(&manager);
+        let manager = AddonManager::new(addons, Box::from([]), Some(data));
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "low.pk3", "filter.pk3"]);
+    }
+
+    #[test]
+    fn unsaved_changes() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        // Without saved settings to compare with, nothing is asked
+        assert!(manager.request_exit());
+        manager.saved_settings = Some(Persistence::from(&manager));
+        assert!(!manager.has_unsaved_changes());
+        assert!(manager.request_exit());
+
+        // Launching and resizing the addon list aren't changes to ask about
+        manager.last_command = Some(manager.build_command());
+        manager.recent_builds.push(String::from("/opt/gzdoom/gzdoom"));
+        manager.secondary_height += 50.;
+        manager.browse_dirs.insert(Picker::Iwads, String::from("/games"));
+        assert!(!manager.has_unsaved_changes());
+
+        manager.exargs = String::from("-fast");
+        assert!(manager.has_unsaved_changes());
+        assert!(!manager.request_exit());
+        assert!(manager.exit_prompt_open);
+
+        // Exiting without saving leaves the stored settings alone
+        manager.confirm_exit(false);
+        assert!(!manager.exit_prompt_open);
+        assert!(manager.request_exit());
+        let mut storage = MemoryStorage::default();
+        App::save(&mut manager, &mut storage);
+        assert_eq!(storage.get_string("exargs"), None);
+
+        // Saving counts as the settings being saved
+        manager.discard_changes = false;
+        App::save(&mut manager, &mut storage);
+        assert_eq!(storage.get_string("exargs").as_deref(), Some("-fast"));
+        assert!(!manager.has_unsaved_changes());
+    }
+
+    #[test]
+    fn recent_builds() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        (0..=MAX_RECENT_BUILDS).for_each(|index| {
+            manager.remember_build(&GZDoomBuildSelection::FullPath(format!("/opt/gzdoom-{}", index)));
+        });
+        assert_eq!(manager.recent_builds.len(), MAX_RECENT_BUILDS);
+        assert_eq!(manager.recent_builds[0], format!("/opt/gzdoom-{}", MAX_RECENT_BUILDS));
+        // Launching one again moves it to the top, without listing it twice
+        manager.remember_build(&GZDoomBuildSelection::FullPath(String::from("/opt/gzdoom-5")));
+        assert_eq!(manager.recent_builds.len(), MAX_RECENT_BUILDS);
+        assert_eq!(manager.recent_builds[0], "/opt/gzdoom-5");
+        assert_eq!(manager.recent_builds.iter().filter(|path| *path == "/opt/gzdoom-5").count(), 1);
+        // Builds chosen from the list aren't typed in
+        manager.builds = Box::from([Build { path: String::from("/usr/bin/gzdoom"), ..Default::default() }]);
+        manager.remember_build(&GZDoomBuildSelection::Single);
+        assert_eq!(manager.recent_builds[0], "/opt/gzdoom-5");
+        // Snapshots of settings don't include the history
+        assert!(manager.snapshot().recent_builds.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removed_recent_builds() {
+        let data = Persistence {
+            recent_builds: vec![String::from("/bin/sh"), String::from("/nonexistent/gzdoom")],
+            ..Default::default()
+        };
+        let manager = AddonManager::new(test_addons(), Box::from([]), Some(data));
+        assert_eq!(manager.recent_builds, ["/bin/sh"]);
+    }
+
+    #[test]
+    fn failed_launches() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.selected_gzdoom_build =
+            GZDoomBuildSelection::FullPath(String::from("/nonexistent/gzdoom"));
+        (0..=MAX_FAILED_LAUNCHES).for_each(|_| manager.launch());
+        assert_eq!(manager.failed_launches.len(), MAX_FAILED_LAUNCHES);
+        assert_eq!(manager.failed_launches[0].error, "Cannot open GZDoom build");
+        assert_eq!(manager.failed_launches[0].summary(), "Campaign with gzdoom");
+
+        select_primary(&mut manager, "Conversion");
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::new());
+        manager.reproduce(0);
+        assert_eq!(manager.selected_addon_names(), vec!["Campaign", "Filter"]);
+        assert_eq!(manager.gzdoom_build(), "/nonexistent/gzdoom");
+        assert_eq!(manager.failed_launches.len(), MAX_FAILED_LAUNCHES);
+    }
+
+    #[test]
+    fn secondary_groups() {
+        let mut addons = test_addons();
+        addons.insert(String::from("Arranged BGM"), addon("bgm.pk3", true, false));
+        addons.insert(String::from("Brightmaps"), addon("brightmaps.pk3", true, false));
+        addons.insert(String::from("Widescreen"), addon("widescreen.pk3", true, false));
+        let manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        assert_eq!(manager.secondary_groups(), vec![(None, vec![0, 1, 2, 3])]);
+
+        addons.get_mut("Arranged BGM").unwrap().category = Some(String::from("Music"));
+        addons.get_mut("Widescreen").unwrap().category = Some(String::from("Graphics"));
+        addons.get_mut("Filter").unwrap().category = Some(String::from("Graphics"));
+        let manager = AddonManager::new(addons, Box::from([]), None);
+        let groups = manager.secondary_groups();
+        let names = |indices: &[usize]| -> Vec<&str> {
+            indices.iter().map(|&index| manager.secondary_addons[index].as_str()).collect()
+        };
+        assert_eq!(groups[0].0.as_deref(), Some("Graphics"));
+        assert_eq!(names(&groups[0].1), ["Filter", "Widescreen"]);
+        assert_eq!(groups[1].0.as_deref(), Some("Music"));
+        assert_eq!(names(&groups[1].1), ["Arranged BGM"]);
+        assert_eq!(groups[2].0, None);
+        assert_eq!(names(&groups[2].1), ["Brightmaps"]);
+    }
+
+    #[test]
+    fn conflicting_addons() {
+        let mut addons = test_addons();
+        addons.insert(String::from("Sharpen"), addon("sharpen.pk3", true, false));
+        addons.get_mut("Sharpen").unwrap().conflicts = Some(vec![String::from("Filter")]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        let conflict = manager.conflicting_addons().map(|(a, b)| (a.clone(), b.clone()));
+        assert_eq!(conflict, Some((String::from("Filter"), String::from("Sharpen"))));
+        assert!(matches!(manager.try_launch(), Err(LaunchError::ConflictingAddons(_, _))));
+
+        let sharpen = manager.secondary_addons.iter().position(|name| name == "Sharpen").unwrap();
+        manager.selected_secondary_addons[sharpen] = false;
+        assert!(manager.conflicting_addons().is_none());
+    }
+
+    #[test]
+    fn requirements() {
+        let mut addons = test_addons();
+        addons.insert(String::from("Base"), addon("base.pk3", true, false));
+        addons.get_mut("Filter").unwrap().requires = Some(vec![String::from("Base")]);
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+        let filter = manager.secondary_addons.iter().position(|name| name == "Filter").unwrap();
+        manager.selected_secondary_addons[filter] = true;
+        // The requirement is loaded first, even before it is selected
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "base.pk3", "filter.pk3"]);
+        assert_eq!(manager.required_addons(), vec!["Base"]);
+
+        manager.apply_requirements();
+        assert_eq!(manager.selected_addon_names(), vec!["Campaign", "Base", "Filter"]);
+        assert!(manager.popup.is_none());
+
+        addons.get_mut("Filter").unwrap().requires = Some(vec![String::from("Missing")]);
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.apply_requirements();
+        assert_eq!(manager.popup.as_deref(), Some("Filter requires Missing, which isn't available."));
+    }
+
+    #[test]
+    fn secondary_order() {
+        let mut addons = test_addons();
+        addons.insert(String::from("Music"), addon("music.pk3", true, false));
+        addons.insert(String::from("Sharpen"), addon("sharpen.pk3", true, false));
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(manager.secondary_addon_names(), vec!["Filter", "Music", "Sharpen"]);
+
+        // Sharpen goes first
+        manager.move_secondary(2, 0);
+        assert_eq!(manager.secondary_addon_names(), vec!["Sharpen", "Filter", "Music"]);
+        // Filter goes last
+        manager.move_secondary(1, 3);
+        assert_eq!(manager.secondary_addon_names(), vec!["Sharpen", "Music", "Filter"]);
+        assert_eq!(
+            launched_files(&manager),
+            vec!["campaign.pk3", "sharpen.pk3", "music.pk3", "filter.pk3"]
+        );
+
+        // Unselecting an addon doesn't forget where it goes
+        let music = manager.secondary_addons.iter().position(|name| name == "Music").unwrap();
+        manager.selected_secondary_addons[music] = false;
+        manager.move_secondary(1, 0);
+        assert_eq!(manager.secondary_addon_names(), vec!["Filter", "Sharpen"]);
+        manager.selected_secondary_addons[music] = true;
+        assert_eq!(manager.secondary_addon_names(), vec!["Filter", "Sharpen", "Music"]);
+
+        // Requirements are still loaded first
+        manager.addons.get_mut("Filter").unwrap().requires = Some(vec![String::from("Music")]);
+        assert_eq!(manager.secondary_addon_names(), vec!["Music", "Filter", "Sharpen"]);
+
+        let data = Persistence::from(&manager);
+        assert_eq!(data.secondary_order, ["Filter", "Sharpen", "Music"]);
+    }
+
+    #[test]
+    fn missing_addon_files() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().missing = vec![String::from("campaign.pk3")];
+        addons.get_mut("Filter").unwrap().missing = vec![String::from("filter.pk3")];
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        // Nothing with missing files is loaded
+        assert!(!manager.build_command().arguments.contains(&String::from("-file")));
+        match manager.try_launch() {
+            Err(LaunchError::MissingAddonFiles(name, files)) => {
+                assert_eq!(name, "Campaign");
+                assert_eq!(files, ["campaign.pk3"]);
+            }
+            other => panic!("Expected missing files, got {:?}", other),
+        }
+        // Nor is an addon which requires one with missing files
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+        manager.addons.get_mut("Campaign").unwrap().missing.clear();
+        manager.addons.get_mut("Campaign").unwrap().requires = Some(vec![String::from("Filter")]);
+        match manager.try_launch() {
+            Err(LaunchError::UnavailableRequirements(messages)) => {
+                assert_eq!(messages, ["Campaign requires Filter, which is missing these files: filter.pk3"]);
+            }
+            other => panic!("Expected a missing requirement, got {:?}", other),
+        }
+        let note = manager.addons["Filter"].missing_note().unwrap();
+        assert_eq!(note, "Missing files:\nfilter.pk3");
+        assert_eq!(manager.addons["Conversion"].missing_note(), None);
+    }
+
+    #[test]
+    fn incomplete_addons() {
+        assert_eq!(incomplete_addons_message(&[]), None);
+        let incomplete = [
+            (String::from("Music"), vec![String::from("music-a.pk3"), String::from("music-b.pk3")]),
+            (String::from("Widescreen HUD"), vec![String::from("hud.pk3")]),
+        ];
+        assert_eq!(
+            incomplete_addons_message(&incomplete).unwrap(),
+            "Some addons can't be loaded, because files are missing:\n\
+            Music: music-a.pk3, music-b.pk3\n\
+            Widescreen HUD: hud.pk3"
+        );
+    }
+
+    #[test]
+    fn pwad_as_iwad() {
+        let dir = TestDir::new("pwad-as-iwad");
+        let pwad = dir.join("maps.wad");
+        let other = dir.join("readme.txt");
+        fs::write(&pwad, b"PWAD\0\0\0\0\x0c\0\0\0").unwrap();
+        let ipk3 = dir.join("game.ipk3");
+        fs::write(&other, b"Hello").unwrap();
+        fs::write(&ipk3, b"Hello").unwrap();
+        let messages = [&pwad, &other, &ipk3].map(|path| not_iwad_message(path));
+
+        assert_eq!(
+            messages[0],
+            format!("{:?} looks like a PWAD, not an IWAD. Add it as an addon instead.", pwad)
+        );
+        assert_eq!(messages[1], format!("{:?} is not an IWAD!", other));
+        assert_eq!(
+            messages[2],
+            format!("{:?} is not a zip file, so it can't be a zipped IWAD!", ipk3)
+        );
+    }
+
+    #[test]
+    fn checksum_mismatches() {
+        assert_eq!(checksums_message(&[]), None);
+        let mismatched = [(String::from("Music"), String::from("music.pk3"))];
+        assert_eq!(
+            checksums_message(&mismatched).unwrap(),
+            "Some addon files don't match their checksums, and may be corrupt. \
+            Download them again before playing:\nMusic: music.pk3"
+        );
+    }
+
+    #[test]
+    fn reload_addons() {
+        let dir = TestDir::new("reload-addons");
+        ["campaign.pk3", "music.pk3", "filter.pk3"].iter().for_each(|file| {
+            File::create(dir.join(file)).unwrap();
+        });
+        let manifest = dir.join(MANIFEST);
+        fs::write(&manifest, "addons:\n  \
+            Campaign:\n    required: [campaign.pk3]\n  \
+            Music:\n    required: [music.pk3]\n    secondary: 1\n  \
+            Filter:\n    required: [filter.pk3]\n    secondary: 1\n").unwrap();
+        let list = addon::get_addons(Some(&manifest.to_string_lossy()), &dir, false).unwrap();
+        let mut manager = AddonManager::new(list.addons, Box::from([]), None);
+        manager.manifest = manifest.to_string_lossy().into_owned();
+        manager.addon_dir = dir.to_path_buf();
+        select_primary(&mut manager, "Campaign");
+        manager.exargs = String::from("-fast");
+
+        // Music is gone, and Sharpen is new
+        File::create(dir.join("sharpen.pk3")).unwrap();
+        fs::write(&manifest, "addons:\n  \
+            Campaign:\n    required: [campaign.pk3]\n  \
+            Filter:\n    required: [filter.pk3]\n    secondary: 1\n  \
+            Sharpen:\n    required: [sharpen.pk3]\n    secondary: 1\n").unwrap();
+        manager.reload_and_report();
+
+        assert_eq!(manager.primary_addons[manager.selected_primary_addon], "Campaign");
+        assert_eq!(manager.secondary_addons.to_vec(), ["Filter", "Sharpen"]);
+        assert_eq!(manager.exargs, "-fast");
+        assert!(manager.popup.unwrap().ends_with("Reloaded addons"));
+    }
+
+    #[test]
+    fn tooltips() {
+        let mut addon = addon("campaign.pk3", false, false);
+        assert_eq!(addon_tooltip(Some(&addon), None), None);
+        assert_eq!(addon_tooltip(None, Some(&SearchMatch::Name)), None);
+        assert_eq!(
+            addon_tooltip(Some(&addon), Some(&SearchMatch::Tag(String::from("music")))).as_deref(),
+            Some("Matched tag: music")
+        );
+        addon.description = Some(String::from("  "));
+        assert_eq!(addon_tooltip(Some(&addon), None), None);
+
+        addon.description = Some(String::from("A new campaign"));
+        assert_eq!(addon_tooltip(Some(&addon), None).as_deref(), Some("A new campaign"));
+        assert_eq!(
+            addon_tooltip(Some(&addon), Some(&SearchMatch::Description)).as_deref(),
+            Some("A new campaign\n\nMatched in description")
+        );
+    }
+
+    #[test]
+    fn loadouts() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().maps = Some(vec![String::from("MAP03")]);
+        addons.get_mut("Filter").unwrap().variants = Some(HashMap::from([
+            (String::from("Sharp"), vec![String::from("sharp.pk3")]),
+            (String::from("Smooth"), vec![String::from("smooth.pk3")]),
+        ]));
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        manager.selected_variants.insert(String::from("Filter"), String::from("Smooth"));
+        manager.selected_maps.insert(String::from("Campaign"), String::from("MAP03"));
+        let loadout = manager.loadout();
+        assert_eq!(loadout.primary.as_deref(), Some("Campaign"));
+        assert_eq!(loadout.secondary, vec!["Filter"]);
+        assert!(!loadout.encode().contains("pk3"));
+
+        let mut other = AddonManager::new(addons, Box::from([]), None);
+        select_primary(&mut other, "Conversion");
+        let mut shared = Loadout::decode(&loadout.encode()).unwrap();
+        shared.secondary.push(String::from("Missing"));
+        let warnings = other.apply_loadout(&shared);
+        assert_eq!(warnings, vec!["Unknown addon: Missing"]);
+        assert_eq!(other.selected_addon_names(), vec!["Campaign", "Filter"]);
+        // The map is started after the files are loaded
+        assert_eq!(launched_files(&other), vec!["campaign.pk3", "filter.pk3", "smooth.pk3", "+map", "MAP03"]);
+        assert_eq!(other.chosen_map(), Some("MAP03"));
+    }
+
+    #[test]
+    fn platforms() {
+        let mut addons = test_addons();
+        addons.get_mut("Filter").unwrap().platforms = Some(vec![String::from("no-such-os")]);
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3"]);
+        assert!(manager.secondary_addons.contains(&String::from("Filter")));
+
+        // Addons saved as selected are deselected, and the user is told
+        let mut settings = Persistence::from(&manager);
+        settings.secondary_addons = Some(vec![String::from("Filter")]);
+        addons.get_mut("Campaign").unwrap().platforms = Some(vec![String::from("no-such-os")]);
+        let manager = AddonManager::new(addons, Box::from([]), Some(settings));
+        assert_eq!(manager.selected_addon_names(), Vec::<&String>::new());
+        let popup = manager.popup.unwrap();
+        assert!(popup.starts_with("2 previously selected addons don't work on"));
+        assert!(popup.ends_with("and were deselected:\nCampaign\nFilter"));
+    }
+
+    #[test]
+    fn browse_dirs() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.browse_dirs.insert(Picker::Iwads, String::from("/games/iwads"));
+        manager.browse_dirs.insert(Picker::Files, String::from("/games/saves"));
+        let data = Persistence::from(&manager);
+        let json = serde_json::to_string(&data.browse_dirs).unwrap();
+        assert!(json.contains("\"iwads\":\"/games/iwads\""));
+        let manager = AddonManager::new(test_addons(), Box::from([]), Some(data));
+        assert_eq!(manager.browse_dirs[&Picker::Iwads], "/games/iwads");
+        assert_eq!(manager.browse_dirs[&Picker::Files], "/games/saves");
+        assert!(!manager.browse_dirs.contains_key(&Picker::Builds));
+    }
+
+    #[test]
+    fn always_load() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.list.always_load = AddonMap::from([
+            (String::from("HUD fix"), addon("hudfix.pk3", true, false)),
+            (String::from("Brightmaps"), addon("brightmaps.pk3", true, false)),
+        ]);
+        select_primary(&mut manager, "None");
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+        assert_eq!(launched_files(&manager), vec!["brightmaps.pk3", "hudfix.pk3"]);
+        assert!(!manager.secondary_addons.contains(&String::from("HUD fix")));
+
+        select_primary(&mut manager, "Campaign");
+        assert_eq!(launched_files(&manager), vec!["campaign.pk3", "brightmaps.pk3", "hudfix.pk3"]);
+    }
+
+    #[test]
+    fn compact_arguments() {
+        let strings = |args: &[&str]| args.iter().map(|&arg| String::from(arg)).collect::<Vec<_>>();
+        assert_eq!(compact(strings(&["-file"])), Vec::<String>::new());
+        assert_eq!(compact(strings(&["-config", ""])), Vec::<String>::new());
+        assert_eq!(
+            compact(strings(&["-file", "a.pk3", "", "b.pk3"])),
+            strings(&["-file", "a.pk3", "b.pk3"])
+        );
+        assert_eq!(
+            compact(strings(&["+exec", "", "+exec", "autoexec.cfg", "+map", "MAP01"])),
+            strings(&["+exec", "autoexec.cfg", "+map", "MAP01"])
+        );
+
+        // No addons, and no config
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("DOOM64.WAD"));
+        select_primary(&mut manager, "None");
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+        assert_eq!(manager.build_command().arguments, strings(&["-iwad", "DOOM64.WAD"]));
+    }
+
+    #[test]
+    fn maps() {
+        let mut addons = test_addons();
+        addons.get_mut("Campaign").unwrap().maps =
+            Some(vec![String::from("MAP01"), String::from("MAP02")]);
+        let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+        select_primary(&mut manager, "Campaign");
+        assert!(!manager.build_command().arguments.contains(&String::from("+map")));
+
+        manager
+            .selected_maps
+            .insert(String::from("Campaign"), String::from("MAP02"));
+        let arguments = manager.build_command().arguments;
+        assert!(arguments.windows(2).any(|pair| pair == ["+map", "MAP02"]));
+
+        // The choice is remembered, but only used for the addon it was made for
+        let data = Persistence::from(&manager);
+        let mut manager = AddonManager::new(addons, Box::from([]), Some(data));
+        assert_eq!(manager.chosen_map(), Some("MAP02"));
+        select_primary(&mut manager, "Conversion");
+        assert_eq!(manager.chosen_map(), None);
+
+        // Loading a saved game takes precedence
+        select_primary(&mut manager, "Campaign");
+        manager.load_game = String::from("save01.zds");
+        assert!(!manager.build_command().arguments.contains(&String::from("+map")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watched_game() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        assert!(!manager.poll_watching());
+        let wait = |manager: &mut AddonManager| {
+            let started = std::time::Instant::now();
+            while manager.poll_watching() {
+                assert!(started.elapsed() < Duration::from_secs(10), "the watch didn't finish");
+                thread::sleep(Duration::from_millis(20));
+            }
+        };
+        let build = GZDoomBuildSelection::FullPath(String::from("/opt/gzdoom"));
+        let crashing = LaunchCommand {
+            program: String::from("sh"),
+            arguments: ["-c", "echo Cannot load bad.wad; exit 1"].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        let grace = Duration::from_secs(5);
+        manager.watching = Some(watch_command(crashing, build.clone(), grace, manager.snapshot()));
+        // Watching doesn't hold up the caller
+        assert!(manager.poll_watching());
+        wait(&mut manager);
+        assert!(!manager.quit_requested);
+        assert!(manager.popup.take().unwrap().contains("Cannot load bad.wad"));
+        assert_eq!(manager.failed_launches.len(), 1);
+        assert!(manager.last_command.is_none());
+
+        let sleeping = LaunchCommand {
+            program: String::from("sleep"),
+            arguments: vec![String::from("1")],
+            ..Default::default()
+        };
+        let grace = Duration::from_millis(100);
+        manager.watching = Some(watch_command(sleeping.clone(), build, grace, manager.snapshot()));
+        wait(&mut manager);
+        assert!(manager.quit_requested);
+        assert!(manager.popup.is_none());
+        assert_eq!(manager.last_command, Some(sleeping));
+        assert_eq!(manager.recent_builds, ["/opt/gzdoom"]);
+    }
+
+    /// Storage which only keeps things in memory
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(String::from(key), value);
+        }
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn persistence_round_trip() {
+        let strings = |names: &[&str]| names.iter().map(|name| String::from(*name)).collect::<Vec<_>>();
+        let settings = Persistence {
+            gzdoom_build: Some(String::from("/usr/bin/gzdoom")),
+            primary_addon: Some(String::from("Campaign")),
+            secondary_addons: Some(strings(&["Music", "Widescreen HUD"])),
+            exargs: Some(String::from("-nomonsters")),
+            config: Some(String::from("doom64.ini")),
+            iwad: Some(String::from("DOOM64.WAD")),
+            load_game: Some(String::from("save01.zds")),
+            remember_load_game: true,
+            group_primary_by_category: true,
+            last_command: Some(LaunchCommand {
+                program: String::from("/usr/bin/gzdoom"),
+                arguments: strings(&["-iwad", "DOOM64.WAD"]),
+                ..Default::default()
+            }),
+            secondary_height: Some(120.),
+            exec_scripts: Some(strings(&["autoexec.cfg"])),
+            backup_config: true,
+            preflight: true,
+            quit_on_launch: true,
+            startup_grace: Some(10),
+            use_wine: true,
+            wine: Some(String::from("wine64")),
+            wine_prefix: Some(String::from("/home/doomguy/.wine")),
+            launch_dir: Some(String::from("/home/doomguy/doom64")),
+            recent_builds: vec![String::from("/opt/gzdoom/gzdoom")],
+            failed_launches: vec![FailedLaunch {
+                error: String::from("GZDoom crashed"),
+                settings: Persistence::default(),
+            }],
+            selected_variants: HashMap::from([(String::from("Campaign"), String::from("Hard"))]),
+            selected_maps: HashMap::from([(String::from("Campaign"), String::from("MAP02"))]),
+            browse_dirs: HashMap::from([(Picker::Iwads, String::from("/games"))]),
+            opt_in_optional: true,
+            optional_selections: HashMap::from([(
+                String::from("Campaign"),
+                HashMap::from([(String::from("Campaign/extras.pk3"), true)]),
+            )]),
+            pinned: Some(Box::new(Persistence::default())),
+            pinned_hotkey: Some(String::from("F5")),
+            secondary_order: strings(&["Widescreen HUD", "Music"]),
+            light_theme: true,
+            source_port: Some(SourcePort::Zandronum),
+        };
+        let mut storage = MemoryStorage::default();
+        settings.save(&mut storage);
+        assert_eq!(Persistence::from(&storage as &dyn Storage), settings);
+    }
+
+    #[test]
+    fn saved_secondary_addons() {
+        let settings = Persistence {
+            secondary_addons: Some(vec![String::from("Music"), String::from("Widescreen HUD")]),
+            ..Default::default()
+        };
+        let mut storage = MemoryStorage::default();
+        settings.save(&mut storage);
+        let names = Persistence::from(&storage as &dyn Storage).secondary_addons.unwrap();
+        assert_eq!(names, ["Music", "Widescreen HUD"]);
+
+        // Older versions ended every name with a newline
+        storage.set_string("secondary_addons", String::from("Music\nWidescreen HUD\n"));
+        let names = Persistence::from(&storage as &dyn Storage).secondary_addons.unwrap();
+        assert_eq!(names, ["Music", "Widescreen HUD"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn running_game() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        assert!(!manager.poll_running());
+        let command = LaunchCommand {
+            program: String::from("sleep"),
+            arguments: vec![String::from("0.2")],
+            ..Default::default()
+        };
+        manager.running = Some(run_command(&command, &manager.game_log).unwrap());
+        assert!(manager.poll_running());
+        let started = std::time::Instant::now();
+        while manager.poll_running() {
+            assert!(started.elapsed() < Duration::from_secs(10), "sleep didn't exit");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(manager.running.is_none());
+        assert!(!manager.log_open);
+        assert!(manager.popup.is_none());
+
+        // The output is kept, and shown if GZDoom fails
+        let failing = LaunchCommand {
+            program: String::from("sh"),
+            arguments: ["-c", "echo Cannot load bad.wad; exit 1"].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        manager.running = Some(run_command(&failing, &manager.game_log).unwrap());
+        while manager.poll_running() {
+            assert!(started.elapsed() < Duration::from_secs(10), "sh didn't exit");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(manager.log_open);
+        let popup = manager.popup.take().unwrap();
+        assert_eq!(popup, "GZDoom exited with an error:\nexit status: 1\nCannot load bad.wad");
+        let log = manager.game_log.lock().unwrap().clone();
+        assert!(log.starts_with("> sleep 0.2\n"));
+        assert!(log.contains("Cannot load bad.wad\n"));
+
+        let missing = LaunchCommand {
+            program: String::from("/nonexistent/gzdoom"),
+            ..Default::default()
+        };
+        assert!(matches!(run_command(&missing, &manager.game_log), Err(LaunchError::LaunchFailed(..))));
+    }
+
+    #[test]
+    fn profiles() {
+        let dir = TestDir::new("profiles");
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.manifest = dir.join(MANIFEST).to_string_lossy().into_owned();
+        manager.exargs = String::from("-nomonsters");
+        select_primary(&mut manager, "None");
+        manager.profile_name = String::from("Vanilla");
+        manager.new_profile();
+
+        select_primary(&mut manager, "Campaign");
+        manager.exargs = String::from("-fast");
+        manager.profile_name = String::from("Vanilla");
+        manager.new_profile();
+        assert!(manager.popup.take().unwrap().contains("already a profile called Vanilla"));
+        manager.profile_name = String::from("Mods");
+        manager.new_profile();
+        assert_eq!(manager.current_profile, Some(1));
+
+        manager.apply_profile(0);
+        assert_eq!(manager.selected_primary_addon, 0);
+        assert_eq!(manager.exargs, "-nomonsters");
+        assert_eq!(manager.profiles.len(), 2);
+        manager.apply_profile(1);
+        assert_eq!(manager.primary_addons[manager.selected_primary_addon], "Campaign");
+        assert_eq!(manager.exargs, "-fast");
+
+        // Only the settings for launching come from the profile
+        manager.light_theme = true;
+        manager.pinned = Some(Box::new(manager.snapshot()));
+        manager.profiles[0].settings.secondary_addons = Some(vec![String::from("Removed")]);
+        manager.popup = Some(String::from("Earlier message"));
+        manager.apply_profile(0);
+        assert!(manager.light_theme);
+        assert!(manager.pinned.is_some());
+        assert_eq!(
+            manager.popup.take().unwrap(),
+            "Earlier message\n\n1 previously selected addon is no longer available:\nRemoved"
+        );
+        manager.apply_profile(1);
+
+        manager.exargs = String::from("-fast -respawn");
+        manager.save_profile();
+        let saved = load_profiles(&manager.profiles_path()).unwrap();
+        assert_eq!(saved, manager.profiles);
+        assert_eq!(saved[1].settings.exargs.as_deref(), Some("-fast -respawn"));
+
+        manager.delete_profile();
+        let saved = load_profiles(&manager.profiles_path()).unwrap();
+        assert_eq!(saved.iter().map(|profile| profile.name.as_str()).collect::<Vec<_>>(), ["Vanilla"]);
+        assert_eq!(manager.current_profile, None);
+        // A missing profiles file has no profiles in it
+        assert_eq!(load_profiles(&dir.join("missing").join(PROFILES)), Ok(vec![]));
+    }
+
+    #[test]
+    fn profile_theme() {
+        let dir = TestDir::new("profile_theme");
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.manifest = dir.join(MANIFEST).to_string_lossy().into_owned();
+        manager.profile_name = String::from("Global");
+        manager.new_profile();
+        manager.profile_name = String::from("Bright");
+        manager.new_profile();
+        manager.set_profile_theme(Some(true));
+        assert!(manager.light_theme);
+
+        // Profiles without a theme don't save one, and profiles saved before
+        // themes could be picked use the global theme
+        let contents = fs::read_to_string(manager.profiles_path()).unwrap();
+        let mut saved: Vec<serde_yaml::Mapping> = serde_yaml::from_str(&contents).unwrap();
+        let theme = serde_yaml::Value::from("light_theme");
+        assert_eq!(saved[0].get(&theme), None);
+        assert_eq!(saved[1].get(&theme), Some(&serde_yaml::Value::from(true)));
+        assert_eq!(load_profiles(&manager.profiles_path()), Ok(manager.profiles.clone()));
+        saved[1].remove(&theme);
+        let saved: Vec<Profile> = serde_yaml::from_value(serde_yaml::to_value(saved).unwrap()).unwrap();
+        assert_eq!(saved[1].light_theme, None);
+
+        manager.light_theme = false;
+        manager.apply_profile(0);
+        assert!(!manager.light_theme);
+        manager.apply_profile(1);
+        assert!(manager.light_theme);
+        manager.set_profile_theme(None);
+        manager.light_theme = false;
+        manager.apply_profile(1);
+        assert!(!manager.light_theme);
+    }
+
+    #[test]
+    fn secondary_search() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = true);
+        let before = manager.selected_secondary_addons.clone();
+        let shown = |manager: &AddonManager, query: &str| -> Vec<String> {
+            (0..manager.secondary_addons.len())
+                .filter(|&index| manager.secondary_matches(index, query).is_some())
+                .map(|index| manager.secondary_addons[index].clone())
+                .collect()
+        };
+        assert_eq!(shown(&manager, ""), manager.secondary_addons.to_vec());
+        let name = manager.secondary_addons[0].clone();
+        let query = name.to_lowercase();
+        assert!(shown(&manager, &query).contains(&name));
+        assert!(shown(&manager, "no addon is called this").is_empty());
+        assert_eq!(manager.secondary_matches(manager.secondary_addons.len(), ""), None);
+        // Searching doesn't change what is selected
+        assert_eq!(manager.selected_secondary_addons, before);
+    }
+
+    #[test]
+    fn iwad_list() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.builds = Box::from([Build { path: String::from("/usr/bin/gzdoom"), ..Default::default() }]);
+        manager.iwads = Box::from([String::from("DOOM64.WAD"), String::from("doom64-remaster.wad")]);
+        manager.selected_iwad = GZDoomBuildSelection::ListIndex(1);
+        assert_eq!(manager.iwad(), "doom64-remaster.wad");
+        manager.selected_iwad = GZDoomBuildSelection::ListIndex(2);
+        assert_eq!(manager.iwad(), "");
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn missing_iwad() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("/bin/sh"));
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("/nonexistent/DOOM64.WAD"));
+        manager.launch();
+        assert!(manager.iwad_help_open);
+        assert!(manager.popup.is_none());
+        assert_eq!(manager.failed_launches[0].error, "Cannot open IWAD");
+
+        let help = manager.iwad_help();
+        assert_eq!(help.hint.as_deref(), Some(IWAD_HINT));
+        let labels: Vec<&str> = help.links.iter().map(|link| link.label.as_str()).collect();
+        assert_eq!(labels, ["Doom 64 on Steam", "Doom 64 on GOG", "Freedoom"]);
+        manager.list.iwad_help = Some(IwadHelp {
+            hint: Some(String::from("Ask in the Discord")),
+            links: vec![],
+        });
+        let help = manager.iwad_help();
+        assert_eq!(help.hint.as_deref(), Some("Ask in the Discord"));
+        assert_eq!(help.links.len(), IWAD_LINKS.len());
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn wine() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("gzdoom.exe"));
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("DOOM64.WAD"));
+        assert!(manager.can_use_wine());
+        assert_eq!(manager.build_command().program, "gzdoom.exe");
+
+        manager.use_wine = true;
+        manager.wine_prefix = String::from("/games/prefix");
+        let command = manager.build_command();
+        assert_eq!(command.program, "wine");
+        assert_eq!(command.arguments[..3], ["gzdoom.exe", "-iwad", "DOOM64.WAD"]);
+        assert_eq!(
+            command.environment,
+            [(String::from("WINEPREFIX"), String::from("/games/prefix"))]
+        );
+
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("gzdoom"));
+        assert!(!manager.can_use_wine());
+        assert_eq!(manager.build_command().program, "gzdoom");
+    }
+
+    #[test]
+    fn rules() {
+        let mut addons = test_addons();
+        addons.get_mut("Filter").unwrap().tags = Some(vec![String::from("campaign-compat")]);
+        addons.insert(String::from("Music"), addon("music.pk3", true, false));
+        let mut manager = AddonManager::new(addons, Box::from([]), None);
+        manager.list.rules = addon::SelectionRules::from([(
+            String::from("Campaign"),
+            vec![String::from("campaign-compat")],
+        )]);
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+
+        select_primary(&mut manager, "Campaign");
+        manager.apply_rules();
+        assert_eq!(manager.selected_addon_names(), vec!["Campaign", "Filter"]);
+
+        // Deselecting an addon the rules selected sticks
+        manager.selected_secondary_addons.iter_mut().for_each(|selected| *selected = false);
+        let data = Persistence::from(&manager);
+        assert_eq!(data.secondary_addons, Some(vec![]));
+        let manager = AddonManager::new(manager.addons.clone(), Box::from([]), Some(data));
+        assert_eq!(manager.selected_addon_names(), vec!["Campaign"]);
+    }
+
+    #[test]
+    fn arg_order() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        select_primary(&mut manager, "Conversion");
+        manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from("DOOM64.WAD"));
+        manager.config = String::from("d64.ini");
+        assert_eq!(
+            manager.build_command().arguments,
+            ["-iwad", "DOOM64.WAD", "-config", "d64.ini", "-file", "conversion.pk3"]
+        );
+        manager.arg_order = ArgOrder::parse("file,iwad").unwrap();
+        assert_eq!(
+            manager.build_command().arguments,
+            ["-file", "conversion.pk3", "-iwad", "DOOM64.WAD", "-config", "d64.ini"]
+        );
+        manager.arg_order = ArgOrder::parse("iwad,file,config").unwrap();
+        assert_eq!(
+            manager.build_command().arguments,
+            ["-iwad", "DOOM64.WAD", "-file", "conversion.pk3", "-config", "d64.ini"]
+        );
+        // GZDoom stays right after the program wrapping it
+        manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from("gzdoom"));
+        manager.exargs = String::from("mangohud %command% +vid_fps 1");
+        manager.arg_order = ArgOrder::parse("iwad,engine").unwrap();
+        let command = manager.build_command();
+        assert_eq!(command.program, "mangohud");
+        assert_eq!(
+            command.arguments,
+            [
+                "gzdoom", "-iwad", "DOOM64.WAD", "+vid_fps", "1",
+                "-config", "d64.ini", "-file", "conversion.pk3",
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_preflight() {
+        let mut manager = AddonManager::new(test_addons(), Box::from([]), None);
+        assert!(!manager.poll_preflight());
+        // Without -norun in its help, the command is launched without trying
+        // it first, which fails here
+        let missing = LaunchCommand {
+            program: String::from("/nonexistent/gzdoom"),
+            ..Default::default()
+        };
+        let build = GZDoomBuildSelection::FullPath(missing.program.clone());
+        let settings = manager.snapshot();
+        manager.preflighting = Some(preflight_command(missing.clone(), &missing.program, build, settings));
+        let started = std::time::Instant::now();
+        while manager.poll_preflight() {
+            assert!(started.elapsed() < Duration::from_secs(10), "the preflight didn't finish");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(manager.preflighting.is_none());
+        assert!(manager.popup.take().is_some());
+        assert_eq!(manager.failed_launches.len(), 1);
+        assert!(manager.last_command.is_none());
+    }
+}
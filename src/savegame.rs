@@ -0,0 +1,74 @@
+use crate::builds::SourcePort;
+use std::{cmp::Reverse, env, fs, path::{Path, PathBuf}, time::SystemTime};
+
+/// A GZDoom saved game found on disk
+#[derive(Debug, Clone)]
+pub struct SaveGame {
+	pub path: PathBuf,
+	pub modified: SystemTime,
+}
+
+impl SaveGame {
+	/// The file name, prefixed by the directory it is in, since GZDoom keeps
+	/// saved games for each IWAD in a separate directory.
+	pub fn label(&self) -> String {
+		let name = self.path.file_stem()
+			.map(|n| n.to_string_lossy())
+			.unwrap_or_default();
+		match self.path.parent().and_then(Path::file_name) {
+			Some(dir) => format!("{}/{}", dir.to_string_lossy(), name),
+			None => name.into_owned(),
+		}
+	}
+}
+
+/// Directories which GZDoom, or another source port, may put saved games in
+pub fn save_directories() -> Vec<PathBuf> {
+	let mut candidates = vec![];
+	if let Some(wad_dir) = env::var_os("DOOMWADDIR") {
+		candidates.push(PathBuf::from(wad_dir));
+	}
+	SourcePort::ALL.iter().for_each(|port| {
+		if let Some(config) = dirs::config_dir() {
+			candidates.push(config.join(port.name().to_lowercase()));
+		}
+		if let Some(documents) = dirs::document_dir() {
+			candidates.push(documents.join("My Games").join(port.name()));
+		}
+	});
+	candidates.into_iter().filter(|dir| dir.is_dir()).collect()
+}
+
+/// Find saved games, most recent first. Returns `None` if none of the save
+/// directories exist, or no saved games are in them.
+pub fn find_saves() -> Option<Box<[SaveGame]>> {
+	let mut saves = vec![];
+	save_directories().iter().for_each(|dir| collect_saves(dir, 2, &mut saves));
+	saves.sort_by(|a, b| a.path.cmp(&b.path));
+	saves.dedup_by(|a, b| a.path == b.path);
+	saves.sort_by_key(|save| Reverse(save.modified));
+	match saves.len() {
+		0 => None,
+		_ => Some(saves.into_boxed_slice()),
+	}
+}
+
+fn collect_saves(dir: &Path, depth: u32, saves: &mut Vec<SaveGame>) {
+	let entries = match fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(_) => return,
+	};
+	entries.flatten().for_each(|entry| {
+		let path = entry.path();
+		if path.is_dir() {
+			if depth > 0 {
+				collect_saves(&path, depth - 1, saves);
+			}
+		} else if path.extension().map(|e| e.eq_ignore_ascii_case("zds")).unwrap_or(false) {
+			let modified = entry.metadata()
+				.and_then(|m| m.modified())
+				.unwrap_or(SystemTime::UNIX_EPOCH);
+			saves.push(SaveGame { path, modified });
+		}
+	});
+}
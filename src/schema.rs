@@ -0,0 +1,183 @@
+use serde_json::{json, Value};
+
+/// JSON Schema for the addon list, for editors which can use it to complete
+/// and check addons.yml. This is kept up to date by hand, so add new fields
+/// of `AddonSpecification` and the addon list here too.
+pub fn manifest_schema() -> Value {
+	let files = json!({
+		"type": "array",
+		"items": { "type": "string" }
+	});
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "Talauncher addon list",
+		"type": "object",
+		"required": ["addons"],
+		"properties": {
+			"addons": {
+				"type": "object",
+				"description": "Addons, by name",
+				"additionalProperties": addon_schema(&files)
+			},
+			"auto_addons": {
+				"type": "array",
+				"description": "Each file matching a pattern becomes an addon, named after the file",
+				"items": {
+					"type": "object",
+					"required": ["pattern", "as"],
+					"properties": {
+						"pattern": { "type": "string" },
+						"as": { "enum": ["primary", "secondary"] }
+					}
+				}
+			},
+			"rules": {
+				"type": "object",
+				"description": "Secondary addon names or tags to select when a primary addon with this name or tag is selected",
+				"additionalProperties": {
+					"type": "array",
+					"items": { "type": "string" }
+				}
+			},
+			"manifest_url": {
+				"type": "string",
+				"description": "Where the latest version of this addon list can be downloaded from"
+			},
+			"version": {
+				"type": ["string", "number"],
+				"description": "Version of this addon list, to tell whether a downloaded one is newer"
+			},
+			"optional_files": {
+				"enum": ["auto", "opt-in"],
+				"description": "Whether optional files are always loaded, or only when picked"
+			},
+			"always_load": {
+				"type": "array",
+				"description": "Names of addons which are loaded with every launch, and can't be deselected",
+				"items": { "type": "string" }
+			},
+			"iwad_help": {
+				"type": "object",
+				"description": "Guidance on getting the IWAD, shown when it can't be found",
+				"properties": {
+					"hint": { "type": "string" },
+					"links": {
+						"type": "array",
+						"items": {
+							"type": "object",
+							"required": ["label", "url"],
+							"properties": {
+								"label": { "type": "string" },
+								"url": { "type": "string" }
+							}
+						}
+					}
+				}
+			}
+		}
+	})
+}
+
+fn addon_schema(files: &Value) -> Value {
+	json!({
+		"type": "object",
+		"required": ["required"],
+		"properties": {
+			"required": files,
+			"required_any": {
+				"description": "At least one of these files must exist. All of them which do are loaded.",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"optional": files,
+			"deh": {
+				"description": "DeHackEd patches, which are loaded with -deh instead of -file",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"args": {
+				"description": "GZDoom arguments added when the addon is loaded, used as they are without shell parsing",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"env": {
+				"description": "Environment variables set when the addon is loaded. The extra arguments override them.",
+				"type": "object",
+				"additionalProperties": { "type": "string" }
+			},
+			"checksums": {
+				"description": "Expected SHA-256 hashes of files, by file name. They are checked with --verify-checksums.",
+				"type": "object",
+				"additionalProperties": { "type": "string" }
+			},
+			"secondary": {
+				"description": "Present if this is a secondary addon",
+				"type": ["string", "number", "boolean"]
+			},
+			"description": { "type": "string" },
+			"tags": {
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"category": { "type": "string" },
+			"standalone": {
+				"description": "Standalone primary addons can't be combined with secondary addons",
+				"type": "boolean"
+			},
+			"cwd": {
+				"description": "Working directory for GZDoom, relative to the addon base directory",
+				"type": "string"
+			},
+			"variants": {
+				"description": "Alternative sets of files, by name. Only the chosen variant's files are loaded.",
+				"type": "object",
+				"additionalProperties": files
+			},
+			"prompt_optional": {
+				"description": "Ask which optional files to load when this addon is selected",
+				"type": "boolean"
+			},
+			"maps": {
+				"description": "Maps which can be started directly from the launcher",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"platforms": {
+				"description": "Platforms the addon works on, like windows, linux or macos, in any case. It works everywhere if this is left out.",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"author": { "type": "string" },
+			"conflicts": {
+				"description": "Names of other addons which can't be loaded with this one",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"requires": {
+				"description": "Names of other addons which must be loaded before this one",
+				"type": "array",
+				"items": { "type": "string" }
+			},
+			"version": {
+				"description": "Version of the addon, like 1.2",
+				"type": ["string", "number"]
+			}
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::addon::AddonSpecification;
+
+	#[test]
+	fn covers_every_field() {
+		let schema = manifest_schema();
+		let properties = &schema["properties"]["addons"]["additionalProperties"]["properties"];
+		let addon = serde_json::to_value(AddonSpecification::default()).unwrap();
+		addon.as_object().unwrap().keys().for_each(|field| {
+			assert!(properties.get(field).is_some(), "{} is missing from the schema", field);
+		});
+	}
+}
@@ -0,0 +1,111 @@
+use crate::addon::AddonMap;
+
+/// URL scheme for selecting addons, like `talauncher://Addon%20One,Addon%20Two`
+pub const URL_SCHEME: &str = "talauncher://";
+
+/// Addons selected from the command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+	pub primary: Option<String>,
+	pub secondary: Vec<String>,
+}
+
+/// Split a comma-separated list of addon names, which may be a
+/// `talauncher://` URL.
+pub fn parse_selection(text: &str) -> Vec<String> {
+	let (text, url) = match text.strip_prefix(URL_SCHEME) {
+		Some(rest) => (rest.trim_end_matches('/'), true),
+		None => (text, false),
+	};
+	text.split(',')
+		.map(|name| match url {
+			true => percent_decode(name),
+			false => String::from(name),
+		})
+		.map(|name| String::from(name.trim()))
+		.filter(|name| !name.is_empty())
+		.collect()
+}
+
+fn percent_decode(text: &str) -> String {
+	let bytes = text.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut index = 0;
+	while index < bytes.len() {
+		let escaped = text.get(index + 1..index + 3)
+			.filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		match (bytes[index], escaped) {
+			(b'%', Some(byte)) => {
+				decoded.push(byte);
+				index += 3;
+			},
+			(byte, _) => {
+				decoded.push(byte);
+				index += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Find the given addon names in the addon list. Returns the selection, and
+/// warnings about names which couldn't be used.
+pub fn resolve_selection(names: &[String], addons: &AddonMap) -> (Selection, Vec<String>) {
+	let mut selection = Selection::default();
+	let mut warnings = vec![];
+	names.iter().for_each(|name| match addons.get(name) {
+		Some(addon) if addon.secondary.is_some() => {
+			selection.secondary.push(name.clone());
+		},
+		Some(_) => match selection.primary {
+			Some(ref primary) => warnings.push(format!(
+				"Only one primary addon can be selected. Ignoring {}, since {} is selected.",
+				name, primary)),
+			None => { selection.primary = Some(name.clone()); }
+		},
+		None => warnings.push(format!("Unknown addon: {}", name)),
+	});
+	(selection, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::addon::AddonSpecification;
+
+	#[test]
+	fn plain_list() {
+		let names = parse_selection("Beta64 by Antnee, Arranged BGM,,");
+		assert_eq!(names, vec!["Beta64 by Antnee", "Arranged BGM"]);
+	}
+
+	#[test]
+	fn url() {
+		let names = parse_selection("talauncher://Beta64%20by%20Antnee,Arranged%20BGM/");
+		assert_eq!(names, vec!["Beta64 by Antnee", "Arranged BGM"]);
+		assert_eq!(percent_decode("100%"), "100%");
+		assert_eq!(percent_decode("%zz%41"), "%zzA");
+	}
+
+	#[test]
+	fn resolve() {
+		let primary = AddonSpecification::default();
+		let secondary = AddonSpecification {
+			secondary: Some(String::from("1")),
+			..Default::default()
+		};
+		let addons = AddonMap::from([
+			(String::from("One"), primary.clone()),
+			(String::from("Two"), primary),
+			(String::from("Filter"), secondary),
+		]);
+		let names = ["Filter", "One", "Two", "Missing"].map(String::from);
+		let (selection, warnings) = resolve_selection(&names, &addons);
+		assert_eq!(selection, Selection {
+			primary: Some(String::from("One")),
+			secondary: vec![String::from("Filter")],
+		});
+		assert_eq!(warnings.len(), 2);
+	}
+}
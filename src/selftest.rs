@@ -0,0 +1,165 @@
+use crate::{addon::{self, AddonList, AddonSpecification}, AddonManager, GZDoomBuildSelection};
+use std::path::Path;
+
+const GZDOOM: &str = "gzdoom";
+const IWAD: &str = "DOOM64.WAD";
+
+/// Build the launch command for a fixed selection of addons from the given
+/// addon list, print it, and check that it is what it should be. This does
+/// not need a display, and does not run GZDoom.
+pub fn run(manifest: &Path, addon_dir: &Path, ignore_case: bool) -> Result<(), String> {
+	let manifest = manifest.to_string_lossy();
+	let addons = addon::get_addons(Some(&*manifest), addon_dir, ignore_case)
+		.map_err(|e| e.to_string())?
+		.addons;
+	let mut manager = AddonManager::new(addons.clone(), Box::from([]), None);
+	manager.selected_gzdoom_build = GZDoomBuildSelection::FullPath(String::from(GZDOOM));
+	manager.selected_iwad = GZDoomBuildSelection::FullPath(String::from(IWAD));
+	// The first primary addon after "None" which has all of its files, if
+	// there is one. All secondary addons are selected by default.
+	manager.selected_primary_addon = manager.primary_addons.iter()
+		.position(|name| manager.addons.get(name).map_or(false, |addon| addon.missing.is_empty()))
+		.unwrap_or(0);
+
+	let command = manager.build_command();
+	println!("{}", command.program);
+	command.arguments.iter().for_each(|arg| println!("{}", arg));
+
+	if command.program != GZDOOM {
+		return Err(format!("Expected to run {}, not {}", GZDOOM, command.program));
+	}
+	let arguments: Vec<&str> = command.arguments.iter().map(String::as_str).collect();
+	if !arguments.windows(2).any(|pair| pair == ["-iwad", IWAD]) {
+		return Err(String::from("-iwad is missing"));
+	}
+	let files: Vec<&str> = arguments.iter()
+		.position(|arg| *arg == "-file")
+		.map(|index| &arguments[index + 1..])
+		.ok_or_else(|| String::from("-file is missing"))?
+		.iter()
+		.copied()
+		.take_while(|arg| !arg.starts_with(['-', '+']))
+		.collect();
+
+	// What should be loaded is worked out from the addon list itself, rather
+	// than by the code which builds the command
+	let loadable = |addon: &&AddonSpecification| addon.missing.is_empty() && addon.supported_here();
+	let primary = manager.primary_addons.get(manager.selected_primary_addon)
+		.and_then(|name| addons.get(name))
+		.filter(loadable);
+	let primary_files: Vec<&str> = primary.iter()
+		.flat_map(|addon| &addon.required)
+		.map(String::as_str)
+		.collect();
+	if !files.starts_with(&primary_files) {
+		return Err(format!("Expected the files to start with {:?}, got {:?}", primary_files, files));
+	}
+	let selected = manager.selected_addon_names();
+	let unloaded = selected.iter()
+		.filter_map(|name| addons.get(*name))
+		.filter(loadable)
+		.flat_map(|addon| &addon.required)
+		.find(|file| !files.contains(&file.as_str()));
+	if let Some(file) = unloaded {
+		return Err(format!("{} is missing from the files {:?}", file, files));
+	}
+	let unlisted = files.iter().find(|file| !addons.values().any(|addon| lists_file(addon, file)));
+	if let Some(file) = unlisted {
+		return Err(format!("{} is loaded, but no addon lists it", file));
+	}
+	Ok(())
+}
+
+/// Whether the file is one of the addon's files, in any variant
+fn lists_file(addon: &AddonSpecification, file: &str) -> bool {
+	addon.required.iter()
+		.chain(addon.required_any.iter().flatten())
+		.chain(addon.optional.iter().flatten())
+		.chain(addon.variants.iter().flat_map(|variants| variants.values().flatten()))
+		.any(|listed| listed == file)
+}
+
+/// Load the addon list, and print each addon with whether all of its files
+/// exist. Returns whether they all do.
+pub fn list_addons(manifest: &Path, addon_dir: &Path, ignore_case: bool) -> Result<bool, String> {
+	let manifest = manifest.to_string_lossy();
+	let list = addon::get_addons(Some(&*manifest), addon_dir, ignore_case)
+		.map_err(|e| e.to_string())?;
+	list.warnings.iter().for_each(|warning| eprintln!("{}", warning));
+	let (lines, complete) = addon_status(&list);
+	lines.iter().for_each(|line| println!("{}", line));
+	Ok(complete)
+}
+
+/// A line for each addon, in alphabetical order, saying whether its files
+/// exist, and whether all of them do
+fn addon_status(list: &AddonList) -> (Vec<String>, bool) {
+	let mut lines: Vec<(&str, String)> = list.addons.iter()
+		.map(|(name, addon)| match addon.missing.len() {
+			0 => (name.as_str(), format!("ok       {}", name)),
+			_ => (name.as_str(), format!("missing  {}: {}", name, addon.missing.join(", "))),
+		})
+		.chain(list.settings.always_load.keys()
+			.map(|name| (name.as_str(), format!("ok       {} (always loaded)", name))))
+		.chain(list.settings.missing_always_load.iter()
+			.map(|name| (name.as_str(), format!("missing  {} (always loaded)", name))))
+		.collect();
+	lines.sort();
+	let complete = list.incomplete.is_empty() && list.settings.missing_always_load.is_empty();
+	(lines.into_iter().map(|(_, line)| line).collect(), complete)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testdir::TestDir;
+	use std::fs::{self, File};
+
+	#[test]
+	fn addon_statuses() {
+		let dir = TestDir::new("list-addons");
+		File::create(dir.join("music.pk3")).unwrap();
+		File::create(dir.join("hud.pk3")).unwrap();
+		let manifest = dir.join("addons.yml");
+		fs::write(&manifest, "addons:\n  \
+			Music:\n    required: [music.pk3]\n  \
+			Campaign:\n    required: [campaign.pk3, music.pk3]\n  \
+			HUD:\n    required: [hud.pk3]\n  \
+			Filter:\n    required: [filter.pk3]\n\
+			always_load: [HUD, Filter]\n").unwrap();
+		let list = addon::get_addons(Some(&manifest.to_string_lossy()), &dir, false).unwrap();
+		let complete = list_addons(&manifest, &dir, false).unwrap();
+
+		let (lines, all_found) = addon_status(&list);
+		assert_eq!(lines, [
+			format!("missing  Campaign: {}", dir.join("campaign.pk3").display()),
+			String::from("missing  Filter (always loaded)"),
+			String::from("ok       HUD (always loaded)"),
+			String::from("ok       Music"),
+		]);
+		assert!(!all_found);
+		assert!(!complete);
+	}
+
+	#[test]
+	fn self_test() {
+		let dir = TestDir::new("self-test");
+		["campaign.pk3", "campaign-music.pk3", "hud.pk3", "hud-hires.pk3"].iter()
+			.for_each(|name| drop(File::create(dir.join(name)).unwrap()));
+		let manifest = dir.join("addons.yml");
+		fs::write(&manifest, "addons:\n  \
+			Campaign:\n    required: [campaign.pk3, campaign-music.pk3]\n  \
+			HUD:\n    required: [hud.pk3]\n    secondary: \"yes\"\n    \
+			variants:\n      High: [hud-hires.pk3]\n").unwrap();
+		let result = run(&manifest, &dir, false);
+
+		assert_eq!(result, Ok(()));
+		let hud = AddonSpecification {
+			required: vec![String::from("hud.pk3")],
+			variants: Some([(String::from("High"), vec![String::from("hud-hires.pk3")])].into()),
+			..Default::default()
+		};
+		assert!(lists_file(&hud, "hud-hires.pk3"));
+		assert!(!lists_file(&hud, "campaign.pk3"));
+	}
+}
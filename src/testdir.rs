@@ -0,0 +1,47 @@
+use std::{
+	env,
+	fs,
+	ops::Deref,
+	path::{Path, PathBuf},
+	process,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Counts the folders made in this run, so that tests running at the same
+/// time don't share one
+static MADE: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty temporary folder for a test to put files in. It is removed when
+/// it is dropped, even if the test fails.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+	pub fn new(name: &str) -> TestDir {
+		let count = MADE.fetch_add(1, Ordering::Relaxed);
+		let path = env::temp_dir().join(format!("talauncher-{}-{}-{}", name, process::id(), count));
+		// Left over from an earlier run which was killed
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		TestDir(path)
+	}
+}
+
+impl Deref for TestDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for TestDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TestDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
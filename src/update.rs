@@ -0,0 +1,106 @@
+use crate::addon;
+use std::time::Duration;
+
+/// How long to wait for the addon list to download
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A line of the difference between two versions of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+	Same(String),
+	Removed(String),
+	Added(String),
+}
+
+/// A newer addon list which can replace the local one
+#[derive(Debug, Clone)]
+pub struct ManifestUpdate {
+	pub contents: String,
+	pub version: Option<String>,
+	pub diff: Vec<DiffLine>,
+}
+
+/// Download the addon list from `url`, and compare it to the local one, which
+/// is `local` read from `fname`. Both are read in the format of `fname`, since
+/// the downloaded list replaces that file. Returns `None` if the local one is
+/// up to date.
+pub fn check(url: &str, fname: &str, local: &str) -> Result<Option<ManifestUpdate>, String> {
+	let remote = ureq::get(url)
+		.timeout(FETCH_TIMEOUT)
+		.call()
+		.map_err(|e| format!("Could not download the addon list from {}:\n{}", url, e))?
+		.into_string()
+		.map_err(|e| format!("Could not read the addon list from {}:\n{}", url, e))?;
+	if let Err(e) = addon::check_manifest(fname, &remote) {
+		return Err(format!("The addon list from {} has a problem:\n{}", url, e));
+	}
+	let version = addon::manifest_version(fname, &remote);
+	let up_to_date = match (&version, addon::manifest_version(fname, local)) {
+		(Some(remote_version), Some(local_version)) => *remote_version == local_version,
+		// Without versions, only the contents can be compared
+		_ => remote == local,
+	};
+	if up_to_date {
+		return Ok(None);
+	}
+	Ok(Some(ManifestUpdate {
+		diff: line_diff(local, &remote),
+		contents: remote,
+		version,
+	}))
+}
+
+/// Compare two files line by line, using their longest common subsequence
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+	let old: Vec<&str> = old.lines().collect();
+	let new: Vec<&str> = new.lines().collect();
+	// common[i][j] is the length of the longest common subsequence of
+	// old[i..] and new[j..]
+	let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+	for i in (0..old.len()).rev() {
+		for j in (0..new.len()).rev() {
+			common[i][j] = match old[i] == new[j] {
+				true => common[i + 1][j + 1] + 1,
+				false => common[i + 1][j].max(common[i][j + 1]),
+			};
+		}
+	}
+	let (mut i, mut j) = (0, 0);
+	let mut diff = Vec::with_capacity(old.len().max(new.len()));
+	while i < old.len() || j < new.len() {
+		if i < old.len() && j < new.len() && old[i] == new[j] {
+			diff.push(DiffLine::Same(String::from(old[i])));
+			i += 1;
+			j += 1;
+		} else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+			diff.push(DiffLine::Removed(String::from(old[i])));
+			i += 1;
+		} else {
+			diff.push(DiffLine::Added(String::from(new[j])));
+			j += 1;
+		}
+	}
+	diff
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn diff() {
+		let old = "addons:\n  A:\n    required: [a.pk3]\n";
+		let new = "addons:\n  A:\n    required: [a2.pk3]\n  B:\n    required: []\n";
+		assert_eq!(line_diff(old, new), vec![
+			DiffLine::Same(String::from("addons:")),
+			DiffLine::Same(String::from("  A:")),
+			DiffLine::Removed(String::from("    required: [a.pk3]")),
+			DiffLine::Added(String::from("    required: [a2.pk3]")),
+			DiffLine::Added(String::from("  B:")),
+			DiffLine::Added(String::from("    required: []")),
+		]);
+		assert!(line_diff(old, old).iter().all(|line| matches!(line, DiffLine::Same(_))));
+		assert_eq!(line_diff("", "a"), vec![DiffLine::Added(String::from("a"))]);
+		assert_eq!(line_diff("a", ""), vec![DiffLine::Removed(String::from("a"))]);
+	}
+}